@@ -8,7 +8,7 @@ fn protofish_parse_once_without_parse(data: &[u8], context: &Context, msg: &Mess
 }
 
 fn protofish_parse_once(data: &[u8]) {
-    let context = Context::parse(&[r#"
+    let context = Context::parse([r#"
             syntax = "proto3";
             message Message {}
         "#])
@@ -24,12 +24,21 @@ fn pb2json_parse_once(data: &[u8]) {
     assert_eq!(v.fields.len(), 14);
 }
 
+fn pb2json_parse_once_into<'a>(
+    parser: &protobuf_to_json::Parser,
+    data: &'a [u8],
+    msg: &mut protobuf_to_json::Message<'a>,
+) {
+    parser.parse_once_into(data, msg);
+    assert_eq!(msg.fields.len(), 14);
+}
+
 fn benchmark_parse_once(c: &mut Criterion) {
     let data = hex!(
         "0a0a6173636f6e2d66756c6c120a6173636f6e2d66756c6c1a1b323032352d30392d30325430393a33373a32362e3033393032385a2203302e312a0474657374421b323032352d30392d30325430393a33373a32362e3033393032385a480068007205302e312e308a016e46756c6c204173636f6e20696d706c656d656e746174696f6e202868617368e280913235362c2041454144e280913132382077697468206e6f6e6365206d61736b696e67202620746167207472756e636174696f6e2c20584f46e280913132382c2043584f46e28091313238292e92012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6c9a011a68747470733a2f2f646f63732e72732f6173636f6e2d66756c6ca2012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6caa014612222f6170692f76312f6372617465732f6173636f6e2d66756c6c2f76657273696f6e731a202f6170692f76312f6372617465732f6173636f6e2d66756c6c2f6f776e657273"
     );
 
-    let context = Context::parse(&[r#"
+    let context = Context::parse([r#"
             syntax = "proto3";
             message Message {}
         "#])
@@ -53,6 +62,15 @@ fn benchmark_parse_once(c: &mut Criterion) {
         &data.as_slice(),
         |b, &s| b.iter(|| pb2json_parse_once(s)),
     );
+    group.bench_with_input(
+        BenchmarkId::new("protobuf-to-json-reused-buffer", 4),
+        &data.as_slice(),
+        |b, &s| {
+            let parser = protobuf_to_json::Parser::new();
+            let mut msg = protobuf_to_json::Message::default();
+            b.iter(|| pb2json_parse_once_into(&parser, s, &mut msg))
+        },
+    );
     group.finish();
 }
 
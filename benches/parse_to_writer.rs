@@ -0,0 +1,44 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use hex_literal::hex;
+use protobuf_to_json::Parser;
+
+fn build_then_serialize(parser: &Parser, data: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    let json = parser.parse(data).unwrap();
+    serde_json::to_writer(&mut *out, &json).unwrap();
+}
+
+fn parse_to_writer(parser: &Parser, data: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    assert!(parser.parse_to_writer(data, &mut *out).unwrap());
+}
+
+fn benchmark_parse_to_writer(c: &mut Criterion) {
+    let data = hex!(
+        "0a0a6173636f6e2d66756c6c120a6173636f6e2d66756c6c1a1b323032352d30392d30325430393a33373a32362e3033393032385a2203302e312a0474657374421b323032352d30392d30325430393a33373a32362e3033393032385a480068007205302e312e308a016e46756c6c204173636f6e20696d706c656d656e746174696f6e202868617368e280913235362c2041454144e280913132382077697468206e6f6e6365206d61736b696e67202620746167207472756e636174696f6e2c20584f46e280913132382c2043584f46e28091313238292e92012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6c9a011a68747470733a2f2f646f63732e72732f6173636f6e2d66756c6ca2012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6caa014612222f6170692f76312f6372617465732f6173636f6e2d66756c6c2f76657273696f6e731a202f6170692f76312f6372617465732f6173636f6e2d66756c6c2f6f776e657273"
+    );
+    let parser = Parser::new();
+
+    let mut group = c.benchmark_group("parse_to_writer");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_with_input(
+        BenchmarkId::new("build-then-serialize", 1),
+        &data.as_slice(),
+        |b, &s| {
+            let mut out = Vec::new();
+            b.iter(|| build_then_serialize(&parser, s, &mut out))
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("parse-to-writer", 2),
+        &data.as_slice(),
+        |b, &s| {
+            let mut out = Vec::new();
+            b.iter(|| parse_to_writer(&parser, s, &mut out))
+        },
+    );
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_parse_to_writer);
+criterion_main!(benches);
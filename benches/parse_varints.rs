@@ -0,0 +1,40 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+fn push_varint(data: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        data.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    data.push(v as u8);
+}
+
+/// Telemetry-like message of 64 small counters followed by a fixed64 timestamp.
+fn varint_payload() -> Vec<u8> {
+    let mut data = Vec::new();
+    for number in 1..=64 {
+        push_varint(&mut data, number << 3);
+        push_varint(&mut data, number * 37 % 300);
+    }
+    push_varint(&mut data, 100 << 3 | 1);
+    data.extend_from_slice(&1_757_000_000_000u64.to_le_bytes());
+    data
+}
+
+fn benchmark_parse_varints(c: &mut Criterion) {
+    let data = varint_payload();
+    let parser = protobuf_to_json::Parser::new();
+    let json = parser.parse(&data).unwrap();
+    assert_eq!(json.as_object().unwrap().len(), 65);
+
+    let mut group = c.benchmark_group("parse_varints");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_with_input(
+        BenchmarkId::new("protobuf-to-json", 1),
+        &data.as_slice(),
+        |b, &s| b.iter(|| parser.parse(s).unwrap()),
+    );
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_parse_varints);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+//! Convert a protobuf file to JSON without reading it into memory.
+//!
+//! ```sh
+//! cargo run --example parse_file --features mmap -- message.pb [bytes-encoding]
+//! ```
+
+use std::process::ExitCode;
+
+use protobuf_to_json::{BytesEncoding, Parser};
+
+fn main() -> ExitCode {
+    let mut args = std::env::args_os().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: parse_file <FILE> [BYTES_ENCODING]");
+        return ExitCode::FAILURE;
+    };
+    let encoding = match args.next().map(|s| s.to_string_lossy().parse()) {
+        None => BytesEncoding::default(),
+        Some(Ok(encoding)) => encoding,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // SAFETY: the file is not expected to change while it is converted, as with any
+    // memory-mapped input of a command line tool.
+    match unsafe { Parser::with_bytes_encoding(encoding).parse_mmap(&path) } {
+        Ok(Some(json)) => {
+            println!("{json:#}");
+            ExitCode::SUCCESS
+        }
+        Ok(None) => {
+            eprintln!("{}: not a protobuf message", path.to_string_lossy());
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{}: {e}", path.to_string_lossy());
+            ExitCode::FAILURE
+        }
+    }
+}
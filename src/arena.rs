@@ -0,0 +1,48 @@
+//! Parsing into fields allocated in a [`bumpalo`] arena.
+
+use bumpalo::{Bump, collections::Vec};
+
+use crate::{Field, Parser};
+
+/// Decoded first layer of a message, with the fields allocated in an arena.
+///
+/// Same as [`crate::Message`], returned by [`Parser::parse_once_in`].
+#[derive(Debug)]
+pub struct MessageArena<'a> {
+    /// Decoded fields.
+    pub fields: Vec<'a, Field<'a>>,
+
+    /// Garbage data at the end of the message, see [`crate::Message::garbage`].
+    pub garbage: Option<&'a [u8]>,
+}
+
+impl Parser {
+    /// Parse only the first layer of the message, allocating the fields in `arena`.
+    ///
+    /// Same as [`Parser::parse_once`], but many messages can be parsed without a heap allocation
+    /// each, and freed at once by resetting the arena.
+    pub fn parse_once_in<'a>(&self, data: &'a [u8], arena: &'a Bump) -> MessageArena<'a> {
+        let mut fields = Vec::new_in(arena);
+        let garbage = self.decode_fields(data, |field| fields.push(field));
+        MessageArena { fields, garbage }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_once_in() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200ff");
+        let parser = Parser::new();
+        let arena = Bump::new();
+        let msg = parser.parse_once_in(&data, &arena);
+        let expected = parser.parse_once(&data);
+        assert_eq!(msg.fields.as_slice(), expected.fields.as_slice());
+        assert_eq!(msg.garbage, expected.garbage);
+        assert_eq!(msg.garbage, Some(&[0xff][..]));
+    }
+}
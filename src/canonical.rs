@@ -0,0 +1,222 @@
+//! Canonical re-encoding of messages.
+
+use crate::{
+    ElementType, Field, FieldValue, Message, Parser, WireType, decode_var, encode_var,
+    message::encode_tag,
+};
+
+impl Parser {
+    /// Parse a protobuf message and re-encode it in a canonical form.
+    ///
+    /// Encodings of the same message that only differ in field order or varint sizes give the
+    /// same bytes, so they can be compared or hashed directly. The normalizations are:
+    /// * fields are sorted by field number, occurrences of a repeated field keeping their order,
+    /// * tags, lengths and varints use their shortest encoding,
+    /// * nested messages, guessed the same way as [`Parser::parse`] does, are canonicalized
+    ///   recursively,
+    /// * occurrences of a field hinted with [`Parser::with_packed_fields`] or
+    ///   [`Parser::with_packed_field_spec`] are merged into a single packed field, whether they
+    ///   were packed or not,
+    /// * garbage and the fields from the first invalid or incomplete one on, which are not
+    ///   part of the JSON output, are dropped.
+    ///
+    /// Values the JSON output shows as strings or bytes are kept as is, including those that
+    /// would be guessed as messages but are not because of hints, a descriptor or
+    /// [`Parser::with_ambiguity`]. Returns `None` if the data is not a message or has no valid
+    /// field, so canonicalizing the output again gives the same bytes.
+    pub fn canonicalize(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let msg = self.root_message(data)?;
+        let mut out = Vec::with_capacity(data.len());
+        self.canonical_message(&msg, &mut Vec::new(), &mut out);
+        (!out.is_empty()).then_some(out)
+    }
+
+    /// Append the canonical encoding of the fields of a message.
+    fn canonical_message(&self, msg: &Message, path: &mut Vec<u64>, out: &mut Vec<u8>) {
+        let mut fields: Vec<&Field> = msg
+            .fields
+            .iter()
+            .take_while(|f| {
+                !matches!(
+                    f.value,
+                    FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _)
+                )
+            })
+            .collect();
+        fields.sort_by_key(|f| f.number);
+
+        for group in fields.chunk_by(|a, b| a.number == b.number) {
+            let number = group[0].number;
+            path.push(number);
+            match self
+                .packed_element(path)
+                .and_then(|element| packed_elements(group, element))
+            {
+                Some(packed) => {
+                    encode_tag(number, WireType::LengthDelimited, out);
+                    encode_var(packed.len() as u64, out);
+                    out.extend_from_slice(&packed);
+                }
+                None => {
+                    for field in group {
+                        self.canonical_field(field, path, out);
+                    }
+                }
+            }
+            path.pop();
+        }
+    }
+
+    /// Append the canonical encoding of a field, canonicalizing nested messages.
+    fn canonical_field(&self, field: &Field, path: &mut Vec<u64>, out: &mut Vec<u8>) {
+        let nested = match &field.value {
+            FieldValue::LengthDelimited(bytes)
+                if !bytes.is_empty() && !self.never_nested(bytes, path) =>
+            {
+                self.classify_message(bytes, false).ok()
+            }
+            FieldValue::Message(msg) => Some(msg.clone()),
+            _ => None,
+        };
+        let Some(nested) = nested else {
+            field.encode_into(out);
+            return;
+        };
+        let mut bytes = Vec::new();
+        self.canonical_message(&nested, path, &mut bytes);
+        encode_tag(field.number, WireType::LengthDelimited, out);
+        encode_var(bytes.len() as u64, out);
+        out.extend_from_slice(&bytes);
+    }
+}
+
+/// Concatenate the elements of the occurrences of a packed field, packed or not.
+///
+/// Returns `None` if an occurrence doesn't hold elements of the given type.
+fn packed_elements(group: &[&Field], element: ElementType) -> Option<Vec<u8>> {
+    let wire_type = match element {
+        ElementType::Fixed32 | ElementType::Sfixed32 | ElementType::Float => WireType::Fixed32,
+        ElementType::Fixed64 | ElementType::Sfixed64 | ElementType::Double => WireType::Fixed64,
+        ElementType::Varint | ElementType::Sint | ElementType::Int | ElementType::Bool => {
+            WireType::Varint
+        }
+    };
+    let mut packed = Vec::new();
+    for field in group {
+        match (&field.value, wire_type) {
+            (FieldValue::Varint(v), WireType::Varint) => encode_var(*v as u64, &mut packed),
+            (FieldValue::Fixed32(v), WireType::Fixed32) => {
+                packed.extend_from_slice(&v.to_le_bytes())
+            }
+            (FieldValue::Fixed64(v), WireType::Fixed64) => {
+                packed.extend_from_slice(&v.to_le_bytes())
+            }
+            (FieldValue::LengthDelimited(bytes), WireType::Varint) => {
+                let mut bytes = *bytes;
+                while !bytes.is_empty() {
+                    encode_var(decode_var(&mut bytes).ok()?, &mut packed);
+                }
+            }
+            (FieldValue::LengthDelimited(bytes), WireType::Fixed32)
+                if bytes.len().is_multiple_of(4) =>
+            {
+                packed.extend_from_slice(bytes)
+            }
+            (FieldValue::LengthDelimited(bytes), WireType::Fixed64)
+                if bytes.len().is_multiple_of(8) =>
+            {
+                packed.extend_from_slice(bytes)
+            }
+            _ => return None,
+        }
+    }
+    Some(packed)
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::{Ambiguity, FieldPath};
+
+    #[test]
+    fn test_canonicalize() {
+        // 1: 1, 2: "You", 5: {1: 1, 2: 2}
+        let a = hex!("0801 1203596f75 2a0408011002");
+        // The same fields in reverse order, with an overlong varint for field 1.
+        let b = hex!("2a0410020801 1203596f75 088100");
+        let parser = Parser::new();
+        let canonical = parser.canonicalize(&a).unwrap();
+        assert_eq!(canonical, a);
+        assert_eq!(parser.canonicalize(&b).unwrap(), canonical);
+        assert_eq!(parser.canonicalize(&canonical).unwrap(), canonical);
+
+        // Repeated fields keep their order, garbage is dropped.
+        let data = hex!("1001 0802 1003 ff");
+        assert_eq!(parser.canonicalize(&data).unwrap(), hex!("0802 1001 1003"));
+
+        assert_eq!(parser.canonicalize(&[]), None);
+        // No valid field.
+        assert_eq!(parser.canonicalize(&hex!("2a062a042b")), None);
+    }
+
+    #[test]
+    fn test_canonicalize_kept_values() {
+        // 5: {2: 2, 1: 1}, which is valid UTF-8 as well.
+        let data = hex!("2a0410020801");
+        assert_eq!(
+            Parser::new().canonicalize(&data).unwrap(),
+            hex!("2a0408011002")
+        );
+        let parsers = [
+            Parser::new().with_ambiguity(Ambiguity::PreferString),
+            Parser::new().with_string_fields(&[FieldPath::from(5)]),
+            Parser::new().with_descriptor(r#"{"5": {"type": "bytes"}}"#.parse().unwrap()),
+        ];
+        for parser in parsers {
+            assert_eq!(parser.canonicalize(&data).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_idempotent() {
+        let parser = Parser::new();
+        let mut state = 0x853c_49e6_748f_ea9bu64;
+        for _ in 0..5000 {
+            let mut data = Vec::new();
+            for _ in 0..state % 24 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                // Mostly small field numbers and lengths, so that fields are often valid.
+                data.push(match state % 4 {
+                    0 => (state >> 8) as u8,
+                    _ => (state >> 8) as u8 & 0x2f,
+                });
+            }
+            if let Some(canonical) = parser.canonicalize(&data) {
+                assert_eq!(
+                    parser.canonicalize(&canonical).as_ref(),
+                    Some(&canonical),
+                    "{data:02x?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_packed() {
+        let parser = Parser::new()
+            .with_packed_fields(&[FieldPath::from(4)])
+            .with_packed_field_spec(5, ElementType::Fixed32);
+        let packed = hex!("2203010203 2a080100000002000000");
+        let unpacked = hex!("2001 2d01000000 2202 0203 2d02000000");
+        assert_eq!(parser.canonicalize(&packed).unwrap(), packed);
+        assert_eq!(parser.canonicalize(&unpacked).unwrap(), packed);
+
+        // Without the hint repeated fields are not packed.
+        let data = hex!("2001 2002");
+        assert_eq!(Parser::new().canonicalize(&data).unwrap(), data);
+    }
+}
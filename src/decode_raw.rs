@@ -0,0 +1,230 @@
+//! Text output in the format of `protoc --decode_raw`.
+
+use std::fmt::Write;
+
+use crate::{FieldValue, Parser, WireType};
+
+/// Deepest nesting of groups and messages, protoc's default recursion limit.
+const MAX_DEPTH: usize = 100;
+
+impl Parser {
+    /// Convert a protobuf message to the text printed by `protoc --decode_raw`.
+    ///
+    /// The output matches protoc byte for byte: fixed values in hex, strings C-escaped and
+    /// nested messages and groups in braces, indented by two spaces. Nested messages are detected
+    /// the way protoc does, i.e. any non-empty value that decodes into valid fields entirely,
+    /// instead of the heuristics of [`Parser::parse`]. Returns `None` where protoc fails to parse
+    /// the input, including groups nested deeper than protoc's recursion limit of 100. Values
+    /// below that depth are printed as strings.
+    pub fn parse_to_decode_raw(&self, data: &[u8]) -> Option<String> {
+        let mut out = String::new();
+        self.write_decode_raw(&mut &data[..], None, 0, &mut out)?;
+        Some(out)
+    }
+
+    /// Write the fields of a message, or those of the group with the given field number up to
+    /// its end tag. Returns `None` if protoc would reject the data, leaving `out` partly written.
+    fn write_decode_raw(
+        &self,
+        data: &mut &[u8],
+        group: Option<u64>,
+        depth: usize,
+        out: &mut String,
+    ) -> Option<()> {
+        let indent = "  ".repeat(depth);
+        let decode_var = self.varint_decoder();
+        while !data.is_empty() {
+            let tag = self.decode_tag(data)?;
+            let number = tag >> 3;
+            if number == 0 {
+                return None;
+            }
+            let wire_type = match (tag & 0x07) as u8 {
+                3 if depth < MAX_DEPTH => {
+                    writeln!(out, "{indent}{number} {{").unwrap();
+                    self.write_decode_raw(data, Some(number), depth + 1, out)?;
+                    writeln!(out, "{indent}}}").unwrap();
+                    continue;
+                }
+                4 => return (group == Some(number)).then_some(()),
+                wire_type => WireType::from(wire_type),
+            };
+            match FieldValue::decode_with(data, wire_type, decode_var) {
+                FieldValue::Varint(v) if self.digit_grouping => {
+                    writeln!(out, "{indent}{number}: {}", group_digits(v))
+                }
+                FieldValue::Varint(v) => writeln!(out, "{indent}{number}: {v}"),
+                FieldValue::Fixed64(v) => writeln!(out, "{indent}{number}: 0x{v:016x}"),
+                FieldValue::Fixed32(v) => writeln!(out, "{indent}{number}: 0x{v:08x}"),
+                FieldValue::LengthDelimited(bytes) => {
+                    let mut nested = String::new();
+                    if !bytes.is_empty()
+                        && depth < MAX_DEPTH
+                        && self
+                            .write_decode_raw(&mut &bytes[..], None, depth + 1, &mut nested)
+                            .is_some()
+                    {
+                        writeln!(out, "{indent}{number} {{").unwrap();
+                        out.push_str(&nested);
+                        writeln!(out, "{indent}}}")
+                    } else {
+                        writeln!(out, "{indent}{number}: \"{}\"", c_escape(bytes))
+                    }
+                }
+                // Invalid wire types, including a group too deep, and truncated values.
+                FieldValue::Invalid(_, _)
+                | FieldValue::Incomplete(_, _)
+                | FieldValue::Message(_) => return None,
+            }
+            .unwrap();
+        }
+        // A group without its end tag.
+        group.is_none().then_some(())
+    }
+}
+
+/// Format an integer with a comma between each group of three digits.
+fn group_digits(v: u128) -> String {
+    let digits = v.to_string();
+    let mut s = String::with_capacity(digits.len() * 4 / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            s.push(',');
+        }
+        s.push(digit);
+    }
+    s
+}
+
+/// Escape bytes like protobuf's `CEscape`: common escapes, and octal for anything else that
+/// isn't printable ASCII.
+fn c_escape(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            b'"' => s.push_str("\\\""),
+            b'\'' => s.push_str("\\'"),
+            b'\\' => s.push_str("\\\\"),
+            0x20..0x7f => s.push(b as char),
+            _ => write!(s, "\\{b:03o}").unwrap(),
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_to_decode_raw() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let text = Parser::new().parse_to_decode_raw(&data).unwrap();
+        let expected = "\
+1: 0x0000001c
+2: \"You\"
+3: \"Me\"
+4: 43
+5 {
+  1: \"abc123\"
+  2: \"\"
+}
+";
+        assert_eq!(text, expected);
+
+        let data = hex!("0a050a0322ff0a11000000000000f03f");
+        let text = Parser::new().parse_to_decode_raw(&data).unwrap();
+        let expected = "\
+1 {
+  1: \"\\\"\\377\\n\"
+}
+2: 0x3ff0000000000000
+";
+        assert_eq!(text, expected);
+
+        assert_eq!(Parser::new().parse_to_decode_raw(&hex!("0801ff")), None);
+        assert_eq!(Parser::new().parse_to_decode_raw(&hex!("0001")), None);
+    }
+
+    #[test]
+    fn test_decode_raw_groups() {
+        // 1: group {2: 1, 3: group {}}, 4: "\x0b\x0c", i.e. a group as a length-delimited value
+        let data = hex!("0b 1001 1b1c 0c 2202 0b0c");
+        let text = Parser::new().parse_to_decode_raw(&data).unwrap();
+        let expected = "\
+1 {
+  2: 1
+  3 {
+  }
+}
+4 {
+  1 {
+  }
+}
+";
+        assert_eq!(text, expected);
+
+        // Unterminated group, mismatched and unopened end tags.
+        assert_eq!(Parser::new().parse_to_decode_raw(&hex!("0b1001")), None);
+        assert_eq!(Parser::new().parse_to_decode_raw(&hex!("0b14")), None);
+        assert_eq!(Parser::new().parse_to_decode_raw(&hex!("1001 0c")), None);
+        // Wire types 6 and 7.
+        assert_eq!(Parser::new().parse_to_decode_raw(&hex!("0e")), None);
+        // A broken group in a length-delimited value makes it a string.
+        let text = Parser::new()
+            .parse_to_decode_raw(&hex!("0a020b14"))
+            .unwrap();
+        assert_eq!(text, "1: \"\\013\\024\"\n");
+    }
+
+    #[test]
+    fn test_decode_raw_depth_limit() {
+        let nested = |depth: usize| {
+            let mut data = Vec::new();
+            data.extend(std::iter::repeat_n(0x0b, depth));
+            data.extend(std::iter::repeat_n(0x0c, depth));
+            data
+        };
+        let text = Parser::new()
+            .parse_to_decode_raw(&nested(MAX_DEPTH))
+            .unwrap();
+        assert_eq!(text.lines().count(), 2 * MAX_DEPTH);
+        assert_eq!(
+            Parser::new().parse_to_decode_raw(&nested(MAX_DEPTH + 1)),
+            None
+        );
+
+        // Length-delimited values below the limit are strings.
+        let mut data = vec![0x08, 0x01];
+        for _ in 0..=MAX_DEPTH {
+            let mut outer = vec![0x0a];
+            crate::varint::encode_var(data.len() as u64, &mut outer);
+            outer.extend_from_slice(&data);
+            data = outer;
+        }
+        let text = Parser::new().parse_to_decode_raw(&data).unwrap();
+        assert_eq!(text.lines().filter(|l| l.ends_with('{')).count(), MAX_DEPTH);
+        assert!(!text.contains("1: 1"));
+    }
+
+    #[test]
+    fn test_decode_raw_digit_grouping() {
+        let data = hex!("202b30bae5a4e5965838e807");
+        let text = Parser::new()
+            .with_digit_grouping(true)
+            .parse_to_decode_raw(&data)
+            .unwrap();
+        assert_eq!(text, "4: 43\n6: 3,029,774,971,578\n7: 1,000\n");
+
+        let json = Parser::new().with_digit_grouping(true).parse(&data);
+        assert_eq!(
+            json,
+            Some(serde_json::json!({"4": 43, "6": 3029774971578u64, "7": 1000}))
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! Partial schema of field names and types, easier to write than a `.proto` file.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde_json::{Map, Value};
+
+use crate::{DescriptorError, FieldOutput, FieldValue, ParseError, Parser, varint::zigzag_decode};
+
+/// Names and types of some fields of a message, see [`Parser::with_descriptor`].
+///
+/// A descriptor is written as JSON keyed by field number. Each field may have a `name`, a
+/// protobuf scalar `type` and, for nested messages, a `message` descriptor:
+///
+/// ```json
+/// {"1": {"name": "id", "type": "int64"}, "5": {"name": "meta", "message": {"1": {"name": "tag"}}}}
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Descriptor {
+    fields: BTreeMap<u64, FieldDescriptor>,
+}
+
+/// Description of a single field of a [`Descriptor`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct FieldDescriptor {
+    /// Key of the field in the output.
+    pub name: Option<String>,
+
+    /// Scalar type of the field.
+    pub field_type: Option<FieldType>,
+
+    /// Fields of the nested message held by the field.
+    pub message: Option<Descriptor>,
+}
+
+/// Protobuf scalar types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    /// `double`
+    Double,
+
+    /// `float`
+    Float,
+
+    /// `int32`
+    Int32,
+
+    /// `int64`
+    Int64,
+
+    /// `uint32`
+    Uint32,
+
+    /// `uint64`
+    Uint64,
+
+    /// `sint32`
+    Sint32,
+
+    /// `sint64`
+    Sint64,
+
+    /// `fixed32`
+    Fixed32,
+
+    /// `fixed64`
+    Fixed64,
+
+    /// `sfixed32`
+    Sfixed32,
+
+    /// `sfixed64`
+    Sfixed64,
+
+    /// `bool`
+    Bool,
+
+    /// `string`
+    String,
+
+    /// `bytes`
+    Bytes,
+}
+
+impl Descriptor {
+    /// Load a descriptor from its JSON representation.
+    pub fn from_json(value: &Value) -> Result<Self, DescriptorError> {
+        let Value::Object(map) = value else {
+            return Err(DescriptorError::NotAnObject);
+        };
+        let mut fields = BTreeMap::new();
+        for (key, value) in map {
+            let number = key
+                .parse()
+                .map_err(|_| DescriptorError::InvalidFieldNumber(key.clone()))?;
+            fields.insert(number, FieldDescriptor::from_json(value)?);
+        }
+        Ok(Self { fields })
+    }
+
+    /// Description of the field at the given path.
+    pub fn field(&self, path: &[u64]) -> Option<&FieldDescriptor> {
+        let (last, parents) = path.split_last()?;
+        let mut descriptor = self;
+        for number in parents {
+            descriptor = descriptor.fields.get(number)?.message.as_ref()?;
+        }
+        descriptor.fields.get(last)
+    }
+}
+
+impl FromStr for Descriptor {
+    type Err = DescriptorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: Value =
+            serde_json::from_str(s).map_err(|e| DescriptorError::Json(e.to_string()))?;
+        Self::from_json(&value)
+    }
+}
+
+impl FieldDescriptor {
+    fn from_json(value: &Value) -> Result<Self, DescriptorError> {
+        let Value::Object(map) = value else {
+            return Err(DescriptorError::NotAnObject);
+        };
+        let name = string_entry(map, "name")?.map(str::to_string);
+        let field_type = string_entry(map, "type")?
+            .map(|ty| ty.parse())
+            .transpose()?;
+        let message = map.get("message").map(Descriptor::from_json).transpose()?;
+        Ok(Self {
+            name,
+            field_type,
+            message,
+        })
+    }
+}
+
+fn string_entry<'a>(
+    map: &'a Map<String, Value>,
+    key: &str,
+) -> Result<Option<&'a str>, DescriptorError> {
+    match map.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => Err(DescriptorError::NotAString(key.to_string())),
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = DescriptorError;
+
+    /// Parse a type name as written in `.proto` files.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ty = match s {
+            "double" => FieldType::Double,
+            "float" => FieldType::Float,
+            "int32" => FieldType::Int32,
+            "int64" => FieldType::Int64,
+            "uint32" => FieldType::Uint32,
+            "uint64" => FieldType::Uint64,
+            "sint32" => FieldType::Sint32,
+            "sint64" => FieldType::Sint64,
+            "fixed32" => FieldType::Fixed32,
+            "fixed64" => FieldType::Fixed64,
+            "sfixed32" => FieldType::Sfixed32,
+            "sfixed64" => FieldType::Sfixed64,
+            "bool" => FieldType::Bool,
+            "string" => FieldType::String,
+            "bytes" => FieldType::Bytes,
+            _ => return Err(DescriptorError::UnknownType(s.to_string())),
+        };
+        Ok(ty)
+    }
+}
+
+impl Parser {
+    /// Apply the names and types of a [`Descriptor`].
+    ///
+    /// Named fields use their name as key, typed fields are converted according to their type
+    /// and fields with a `message` are always parsed as nested messages. Fields missing from the
+    /// descriptor, or whose wire type doesn't match the declared type, are guessed as usual.
+    /// Names from [`Parser::with_field_names`] take precedence.
+    pub fn with_descriptor(mut self, descriptor: Descriptor) -> Self {
+        Arc::make_mut(&mut self.hints).descriptor = Some(Arc::new(descriptor));
+        self
+    }
+
+    /// Description of the field at the given path, if any.
+    pub(crate) fn declared_field(&self, path: &[u64]) -> Option<&FieldDescriptor> {
+        self.hints.descriptor.as_ref()?.field(path)
+    }
+
+    /// Convert a value according to its declared type.
+    ///
+    /// Returns `Ok(None)` if the field has no type or the type doesn't match the wire type.
+    pub(crate) fn declared_to_json(
+        &self,
+        value: &FieldValue,
+        path: &[u64],
+    ) -> Result<Option<(Value, FieldOutput)>, ParseError> {
+        let Some(ty) = self.declared_field(path).and_then(|field| field.field_type) else {
+            return Ok(None);
+        };
+        let json = match (ty, value) {
+            (FieldType::Int32, FieldValue::Varint(v)) => self.sint_to_json(*v as i32 as i64),
+            (FieldType::Int64, FieldValue::Varint(v)) => self.sint_to_json(*v as i64),
+            (FieldType::Uint32, FieldValue::Varint(v)) => self.uint_to_json(*v as u32 as u64),
+            (FieldType::Uint64, FieldValue::Varint(v)) => self.uint_to_json(*v as u64),
+            (FieldType::Sint32, FieldValue::Varint(v)) => {
+                self.sint_to_json(zigzag_decode(*v as u32 as u64))
+            }
+            (FieldType::Sint64, FieldValue::Varint(v)) => {
+                self.sint_to_json(zigzag_decode(*v as u64))
+            }
+            (FieldType::Bool, FieldValue::Varint(v)) => Value::Bool(*v != 0),
+            (FieldType::Fixed32, FieldValue::Fixed32(v)) => self.uint_to_json(*v as u64),
+            (FieldType::Sfixed32, FieldValue::Fixed32(v)) => self.sint_to_json(*v as i32 as i64),
+            (FieldType::Float, FieldValue::Fixed32(v)) => {
+                let v = f32::from_bits(*v);
+                self.float_to_json(v, v.into(), path)?
+            }
+            (FieldType::Fixed64, FieldValue::Fixed64(v)) => self.uint_to_json(*v),
+            (FieldType::Sfixed64, FieldValue::Fixed64(v)) => self.sint_to_json(*v as i64),
+            (FieldType::Double, FieldValue::Fixed64(v)) => {
+                let v = f64::from_bits(*v);
+                self.float_to_json(v, v, path)?
+            }
+            (FieldType::String, FieldValue::LengthDelimited(bytes)) => {
+                let Ok(s) = std::str::from_utf8(bytes) else {
+                    return Ok(None);
+                };
+                return Ok(Some((self.string_to_json(s), FieldOutput::String)));
+            }
+            // Declared bytes are never taken for text in auto mode.
+            (FieldType::Bytes, FieldValue::LengthDelimited(bytes)) => {
+                return Ok(Some((self.opaque_to_json(bytes), FieldOutput::Bytes)));
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some((json, FieldOutput::Scalar)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_with_descriptor() {
+        let descriptor: Descriptor = r#"{
+            "1": {"name": "id", "type": "sfixed32"},
+            "2": {"name": "name"},
+            "3": {"type": "bytes"},
+            "4": {"name": "count", "type": "sint64"},
+            "5": {"name": "meta", "message": {"1": {"name": "tag"}, "2": {"type": "string"}}},
+            "6": {"name": "flag", "type": "bool"}
+        }"#
+        .parse()
+        .unwrap();
+        let data = hex!("0dfeffffff1203596f751a024d65202b2a0a0a0661626331323312003003");
+        let json = Parser::new()
+            .with_descriptor(descriptor)
+            .parse(&data)
+            .unwrap();
+        let expected = json!({
+            "id": -2,
+            "name": "You",
+            "3": "TWU=",
+            "count": -22,
+            "meta": {"tag": "abc123", "2": ""},
+            "flag": true
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_declared_string_max_len() {
+        let descriptor: Descriptor =
+            r#"{"2": {"name": "name", "type": "string"}}"#.parse().unwrap();
+        let json = Parser::new()
+            .with_descriptor(descriptor)
+            .with_max_string_len(1)
+            .parse(&hex!("1203596f75"))
+            .unwrap();
+        assert_eq!(json, json!({"name": "Y\u{2026}(+2 bytes)"}));
+    }
+
+    #[test]
+    fn test_descriptor_errors() {
+        assert_eq!(
+            "[]".parse::<Descriptor>(),
+            Err(DescriptorError::NotAnObject)
+        );
+        assert_eq!(
+            r#"{"x": {}}"#.parse::<Descriptor>(),
+            Err(DescriptorError::InvalidFieldNumber("x".to_string()))
+        );
+        assert_eq!(
+            r#"{"1": {"type": "int128"}}"#.parse::<Descriptor>(),
+            Err(DescriptorError::UnknownType("int128".to_string()))
+        );
+        assert_eq!(
+            r#"{"1": {"name": 1}}"#.parse::<Descriptor>(),
+            Err(DescriptorError::NotAString("name".to_string()))
+        );
+    }
+}
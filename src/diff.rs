@@ -0,0 +1,210 @@
+//! Field level comparison of two messages.
+
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+use crate::{Parser, TypedValue};
+
+/// Differences between two messages, as returned by [`Parser::diff`].
+///
+/// Field numbers are listed in ascending order. A field held once by both messages as a nested
+/// message is compared recursively and reported in `nested` instead of `changed`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct MessageDiff {
+    /// Fields only present in the second message.
+    pub added: Vec<u64>,
+
+    /// Fields only present in the first message.
+    pub removed: Vec<u64>,
+
+    /// Fields present in both messages with different values.
+    pub changed: Vec<u64>,
+
+    /// Differences of nested messages, by field number.
+    pub nested: BTreeMap<u64, MessageDiff>,
+}
+
+impl MessageDiff {
+    /// Whether the messages are identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.nested.is_empty()
+    }
+
+    fn between(a: &[(u64, TypedValue)], b: &[(u64, TypedValue)]) -> Self {
+        let a = group(a);
+        let b = group(b);
+        let mut diff = Self::default();
+        for (number, values) in &a {
+            match b.get(number) {
+                None => diff.removed.push(*number),
+                Some(other) if other == values => {}
+                Some(other) => match (values.as_slice(), other.as_slice()) {
+                    ([TypedValue::Message(a)], [TypedValue::Message(b)]) => {
+                        diff.nested.insert(*number, Self::between(a, b));
+                    }
+                    _ => diff.changed.push(*number),
+                },
+            }
+        }
+        diff.added = b.keys().filter(|n| !a.contains_key(n)).copied().collect();
+        diff
+    }
+}
+
+/// Collect the values of each field number in wire order.
+fn group(fields: &[(u64, TypedValue)]) -> BTreeMap<u64, Vec<&TypedValue>> {
+    let mut grouped: BTreeMap<u64, Vec<&TypedValue>> = BTreeMap::new();
+    for (number, value) in fields {
+        grouped.entry(*number).or_default().push(value);
+    }
+    grouped
+}
+
+impl From<&MessageDiff> for Value {
+    /// Convert to JSON with `added`, `removed`, `changed` and `nested` keys, nested diffs being
+    /// keyed by field number.
+    fn from(diff: &MessageDiff) -> Self {
+        let nested = diff
+            .nested
+            .iter()
+            .map(|(number, diff)| (number.to_string(), Value::from(diff)))
+            .collect::<Map<_, _>>();
+        serde_json::json!({
+            "added": diff.added,
+            "removed": diff.removed,
+            "changed": diff.changed,
+            "nested": nested,
+        })
+    }
+}
+
+impl From<MessageDiff> for Value {
+    fn from(diff: MessageDiff) -> Self {
+        Value::from(&diff)
+    }
+}
+
+impl Parser {
+    /// Compare two protobuf messages field by field.
+    ///
+    /// Both messages are parsed with [`Parser::parse_typed`], so nested messages are guessed the
+    /// same way on both sides. Data that doesn't parse as a message is compared as an empty
+    /// message.
+    pub fn diff(&self, a: &[u8], b: &[u8]) -> MessageDiff {
+        let fields = |data| match self.parse_typed(data) {
+            Some(TypedValue::Message(fields)) => fields,
+            _ => Vec::new(),
+        };
+        MessageDiff::between(&fields(a), &fields(b))
+    }
+
+    /// Describe the changes from `old` to `new` as a JSON merge patch (RFC 7386).
+    ///
+    /// Both messages are converted with [`Parser::parse`]. The patch holds the added and changed
+    /// fields with their new values, and removed fields as `null`. Nested messages are patched
+    /// recursively, while other values, including arrays of repeated fields, are replaced
+    /// as a whole. Data that doesn't parse as a message is treated as an empty message.
+    ///
+    /// A merge patch can't hold a `null` value: a field converted to `null`, e.g. a non-finite
+    /// float with [`NonFinite::Null`](crate::NonFinite::Null), is reported as `null` like a
+    /// removed field, and applying the patch removes it.
+    pub fn parse_patch(&self, old: &[u8], new: &[u8]) -> Value {
+        let object = |data| match self.parse(data) {
+            Some(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+        Value::Object(merge_patch(&object(old), object(new)))
+    }
+}
+
+/// Merge patch turning `old` into `new`, empty if they are equal.
+fn merge_patch(old: &Map<String, Value>, new: Map<String, Value>) -> Map<String, Value> {
+    let mut patch: Map<_, _> = old
+        .keys()
+        .filter(|key| !new.contains_key(*key))
+        .map(|key| (key.clone(), Value::Null))
+        .collect();
+    for (key, value) in new {
+        match (old.get(&key), value) {
+            (Some(old), value) if *old == value => {}
+            (Some(Value::Object(old)), Value::Object(value)) => {
+                patch.insert(key, Value::Object(merge_patch(old, value)));
+            }
+            (_, value) => {
+                patch.insert(key, value);
+            }
+        }
+    }
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+    use crate::NonFinite;
+
+    #[test]
+    fn test_diff() {
+        let a = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let b = hex!("0d1c0000001203596f751a024d65202c2a0a0a066162633132331200");
+        let diff = Parser::new().diff(&a, &b);
+        assert_eq!(
+            diff,
+            MessageDiff {
+                changed: vec![4],
+                ..Default::default()
+            }
+        );
+        assert!(Parser::new().diff(&a, &a).is_empty());
+
+        let b = hex!("0d1c0000001203596f75202b2a0a0a0661626331323412003001");
+        let diff = Parser::new().diff(&a, &b);
+        assert_eq!(
+            Value::from(diff),
+            json!({
+                "added": [6],
+                "removed": [3],
+                "changed": [],
+                "nested": {"5": {"added": [], "removed": [], "changed": [1], "nested": {}}}
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let parser = Parser::new();
+        let a = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        assert_eq!(parser.parse_patch(&a, &a), json!({}));
+
+        // 4 changed and 6 added.
+        let b = hex!("0d1c0000001203596f751a024d65202c2a0a0a0661626331323312003001");
+        assert_eq!(parser.parse_patch(&a, &b), json!({"4": 44, "6": 1}));
+
+        // 3 removed, 5.1 changed.
+        let b = hex!("0d1c0000001203596f75202b2a0a0a066162633132341200");
+        assert_eq!(
+            parser.parse_patch(&a, &b),
+            json!({"3": null, "5": {"1": "abc124"}})
+        );
+        assert_eq!(parser.parse_patch(&[], &b), parser.parse(&b).unwrap());
+
+        // 5.2 removed.
+        let b = hex!("0d1c0000001203596f751a024d65202b2a080a06616263313233");
+        assert_eq!(parser.parse_patch(&a, &b), json!({"5": {"2": null}}));
+        assert_eq!(parser.parse_patch(&b, &a), json!({"5": {"2": ""}}));
+
+        // 1: 1.0, then NaN converted to null.
+        let parser = Parser::new()
+            .with_descriptor(r#"{"1": {"type": "float"}}"#.parse().unwrap())
+            .with_nonfinite_floats(NonFinite::Null);
+        let patch = parser.parse_patch(&hex!("0d0000803f"), &hex!("0d0000c07f"));
+        assert_eq!(patch, json!({"1": null}));
+    }
+}
@@ -0,0 +1,495 @@
+//! Error types.
+
+use std::{error, fmt};
+
+use crate::{FieldPath, WireType};
+
+/// Error returned when a protobuf message cannot be converted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParseError {
+    /// The data doesn't contain any protobuf field.
+    NotAMessage,
+
+    /// The operation budget set with [`Parser::with_op_budget`](crate::Parser::with_op_budget)
+    /// was exhausted.
+    BudgetExhausted,
+
+    /// Trailing bytes at `offset` that are not a field, rejected in strict mode.
+    Garbage {
+        /// Offset of the garbage in the data.
+        offset: usize,
+    },
+
+    /// A field has an invalid wire type, rejected in strict mode.
+    InvalidWireType {
+        /// Field number.
+        field: u64,
+        /// Wire type number.
+        wire_type: u8,
+    },
+
+    /// A field is truncated, rejected in strict mode.
+    Incomplete {
+        /// Field number.
+        field: u64,
+        /// Wire type of the truncated value.
+        wire_type: WireType,
+    },
+
+    /// A float interpretation is NaN or infinite, rejected with
+    /// [`NonFinite::Error`](crate::NonFinite::Error).
+    NonFiniteFloat {
+        /// Field number.
+        field: u64,
+    },
+
+    /// A length-delimited field may be a string or a message, rejected with
+    /// [`Ambiguity::Error`](crate::Ambiguity::Error).
+    Ambiguous {
+        /// Path of the field.
+        path: FieldPath,
+    },
+
+    /// The header to skip is longer than the data.
+    HeaderOutOfBounds {
+        /// Number of header bytes to skip.
+        skip: usize,
+        /// Length of the data.
+        len: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NotAMessage => f.write_str("data is not a protobuf message"),
+            ParseError::BudgetExhausted => f.write_str("operation budget exhausted"),
+            ParseError::Garbage { offset } => write!(f, "trailing garbage at offset {offset}"),
+            ParseError::InvalidWireType { field, wire_type } => {
+                write!(f, "field {field} has invalid wire type {wire_type}")
+            }
+            ParseError::Incomplete { field, wire_type } => {
+                write!(f, "field {field} has a truncated {wire_type} value")
+            }
+            ParseError::NonFiniteFloat { field } => {
+                write!(f, "field {field} has a non-finite float value")
+            }
+            ParseError::Ambiguous { path } => {
+                write!(f, "field {path} may be a string or a nested message")
+            }
+            ParseError::HeaderOutOfBounds { skip, len } => {
+                write!(
+                    f,
+                    "header of {skip} bytes is longer than the {len} bytes of data"
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Error returned by [`Parser::parse_expecting`](crate::Parser::parse_expecting) when expected
+/// fields are absent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MissingFields {
+    /// Numbers of the missing fields, in the order they were expected.
+    pub missing: Vec<u64>,
+}
+
+impl fmt::Display for MissingFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("missing fields")?;
+        for (i, number) in self.missing.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{sep}{number}")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for MissingFields {}
+
+/// Error returned by [`Message::from_json`](crate::Message::from_json).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FromJsonError {
+    /// The value is not a JSON object.
+    NotAnObject,
+
+    /// An object key is not a field number.
+    InvalidFieldNumber(String),
+
+    /// The value of the given key has no protobuf representation, e.g. `null` or a nested array.
+    UnsupportedValue(String),
+}
+
+impl fmt::Display for FromJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromJsonError::NotAnObject => f.write_str("JSON value is not an object"),
+            FromJsonError::InvalidFieldNumber(key) => {
+                write!(f, "key {key:?} is not a field number")
+            }
+            FromJsonError::UnsupportedValue(key) => {
+                write!(f, "value of key {key:?} has no protobuf representation")
+            }
+        }
+    }
+}
+
+impl error::Error for FromJsonError {}
+
+/// Error returned when converting a [`FieldValue`](crate::FieldValue) into a primitive type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldValueError {
+    /// The value has a wire type the target type can't be decoded from.
+    UnexpectedWireType(WireType),
+
+    /// The value is truncated.
+    Incomplete,
+
+    /// The value doesn't fit in the target type, e.g. a varint of more than 64 bits, or of more
+    /// than 32 bits for `u32` and `i32`.
+    OutOfRange,
+
+    /// The bytes are not valid UTF-8.
+    InvalidUtf8,
+
+    /// The value is a nested message that has not been serialized, so it has no bytes.
+    Message,
+}
+
+impl fmt::Display for FieldValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValueError::UnexpectedWireType(wire_type) => {
+                write!(f, "unexpected {wire_type} value")
+            }
+            FieldValueError::Incomplete => f.write_str("value is truncated"),
+            FieldValueError::OutOfRange => f.write_str("value doesn't fit in the target type"),
+            FieldValueError::InvalidUtf8 => f.write_str("bytes are not valid UTF-8"),
+            FieldValueError::Message => f.write_str("value is an unserialized message"),
+        }
+    }
+}
+
+impl error::Error for FieldValueError {}
+
+/// Error returned by [`WireType::try_from`] for an unknown wire type number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InvalidWireType(pub u8);
+
+impl fmt::Display for InvalidWireType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid wire type {}", self.0)
+    }
+}
+
+impl error::Error for InvalidWireType {}
+
+/// Error returned by [`decode_var`](crate::decode_var) and the other varint decoding functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarintError {
+    /// The data ends before the last byte of the varint.
+    Incomplete,
+
+    /// The varint is longer than 10 bytes.
+    TooLong,
+}
+
+impl fmt::Display for VarintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VarintError::Incomplete => "varint is truncated",
+            VarintError::TooLong => "varint is longer than 10 bytes",
+        })
+    }
+}
+
+impl error::Error for VarintError {}
+
+/// Error returned when parsing an unknown [`BytesEncoding`](crate::BytesEncoding) name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownBytesEncoding(pub String);
+
+impl fmt::Display for UnknownBytesEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown bytes encoding {:?}", self.0)
+    }
+}
+
+impl error::Error for UnknownBytesEncoding {}
+
+/// Differences of a message from a [`ShapeDescriptor`](crate::ShapeDescriptor), returned by
+/// [`Parser::validate_against_shape`](crate::Parser::validate_against_shape).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShapeMismatch {
+    /// Differences in wire order, fields missing from a message following the fields it has.
+    pub issues: Vec<ShapeIssue>,
+}
+
+/// A single difference of a [`ShapeMismatch`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ShapeIssue {
+    /// The data is not a message.
+    NotAMessage,
+
+    /// A field is not part of the shape.
+    NewField {
+        /// Path of the field.
+        path: FieldPath,
+    },
+
+    /// A field present in every sample the shape was inferred from is missing.
+    MissingField {
+        /// Path of the field.
+        path: FieldPath,
+    },
+
+    /// A field has a wire type it never had in the samples.
+    WireTypeChanged {
+        /// Path of the field.
+        path: FieldPath,
+        /// Wire types of the field in the shape.
+        expected: Vec<WireType>,
+        /// Wire type of the field in the message.
+        found: WireType,
+    },
+}
+
+impl fmt::Display for ShapeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("message doesn't match the shape")?;
+        for (i, issue) in self.issues.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            write!(f, "{sep}{issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ShapeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShapeIssue::NotAMessage => f.write_str("data is not a protobuf message"),
+            ShapeIssue::NewField { path } => write!(f, "new field {path}"),
+            ShapeIssue::MissingField { path } => write!(f, "missing field {path}"),
+            ShapeIssue::WireTypeChanged { path, found, .. } => {
+                write!(f, "field {path} has unexpected wire type {found}")
+            }
+        }
+    }
+}
+
+impl error::Error for ShapeMismatch {}
+
+/// Error returned when converting JSON back to a [`ShapeDescriptor`](crate::ShapeDescriptor).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ShapeError {
+    /// A shape or field shape is not a JSON object.
+    NotAnObject,
+
+    /// An object key is not a field number.
+    InvalidFieldNumber(String),
+
+    /// A field shape lacks the given key.
+    MissingKey(String),
+
+    /// The value of the given key has the wrong JSON type.
+    InvalidValue(String),
+
+    /// The wire type name is not one of a valid wire type.
+    UnknownWireType(String),
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShapeError::NotAnObject => f.write_str("shape is not a JSON object"),
+            ShapeError::InvalidFieldNumber(key) => write!(f, "key {key:?} is not a field number"),
+            ShapeError::MissingKey(key) => write!(f, "field shape lacks key {key:?}"),
+            ShapeError::InvalidValue(key) => write!(f, "value of key {key:?} has the wrong type"),
+            ShapeError::UnknownWireType(name) => write!(f, "unknown wire type {name:?}"),
+        }
+    }
+}
+
+impl error::Error for ShapeError {}
+
+/// Error returned when loading a [`Descriptor`](crate::Descriptor).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DescriptorError {
+    /// The text is not valid JSON.
+    Json(String),
+
+    /// A descriptor or field description is not a JSON object.
+    NotAnObject,
+
+    /// An object key is not a field number.
+    InvalidFieldNumber(String),
+
+    /// The value of the given key is not a string.
+    NotAString(String),
+
+    /// The type name is not a protobuf scalar type.
+    UnknownType(String),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::Json(err) => write!(f, "descriptor is not valid JSON: {err}"),
+            DescriptorError::NotAnObject => f.write_str("descriptor is not a JSON object"),
+            DescriptorError::InvalidFieldNumber(key) => {
+                write!(f, "key {key:?} is not a field number")
+            }
+            DescriptorError::NotAString(key) => write!(f, "value of key {key:?} is not a string"),
+            DescriptorError::UnknownType(name) => write!(f, "unknown field type {name:?}"),
+        }
+    }
+}
+
+impl error::Error for DescriptorError {}
+
+/// Error returned when loading a [`Schema`](crate::Schema).
+#[cfg(feature = "schema")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SchemaError {
+    /// The `.proto` files could not be parsed.
+    Parse(String),
+
+    /// The root message is not defined by the schema.
+    UnknownMessage(String),
+}
+
+#[cfg(feature = "schema")]
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Parse(err) => write!(f, "invalid .proto schema: {err}"),
+            SchemaError::UnknownMessage(name) => write!(f, "unknown message {name:?}"),
+        }
+    }
+}
+
+#[cfg(feature = "schema")]
+impl error::Error for SchemaError {}
+
+/// Error returned by [`Parser::parse_gzip`](crate::Parser::parse_gzip).
+#[cfg(feature = "gzip")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GzipError {
+    /// The data doesn't start with a gzip header of deflate compressed data.
+    InvalidHeader,
+
+    /// The compressed data is corrupt.
+    InvalidDeflate,
+
+    /// The data ends before the end of the gzip member.
+    Truncated,
+
+    /// The CRC-32 or the length of the decompressed data doesn't match the gzip trailer.
+    ChecksumMismatch,
+
+    /// The decompressed data exceeds the limit of
+    /// [`Parser::with_max_decompressed_len`](crate::Parser::with_max_decompressed_len).
+    TooLarge {
+        /// The limit, in bytes.
+        limit: usize,
+    },
+
+    /// The decompressed data is not a message.
+    Parse(ParseError),
+}
+
+#[cfg(feature = "gzip")]
+impl fmt::Display for GzipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GzipError::InvalidHeader => f.write_str("data is not gzip compressed"),
+            GzipError::InvalidDeflate => f.write_str("invalid deflate data"),
+            GzipError::Truncated => f.write_str("gzip data is truncated"),
+            GzipError::ChecksumMismatch => f.write_str("gzip checksum mismatch"),
+            GzipError::TooLarge { limit } => {
+                write!(f, "decompressed data is larger than {limit} bytes")
+            }
+            GzipError::Parse(err) => write!(f, "decompressed data: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl error::Error for GzipError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            GzipError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        let cases: [(Box<dyn error::Error>, &str); 8] = [
+            (
+                Box::new(ParseError::Garbage { offset: 4 }),
+                "trailing garbage at offset 4",
+            ),
+            (
+                Box::new(ParseError::Incomplete {
+                    field: 2,
+                    wire_type: WireType::LengthDelimited,
+                }),
+                "field 2 has a truncated length-delimited value",
+            ),
+            (
+                Box::new(ParseError::Ambiguous {
+                    path: FieldPath::from([1, 2]),
+                }),
+                "field 1.2 may be a string or a nested message",
+            ),
+            (
+                Box::new(MissingFields {
+                    missing: vec![1, 3],
+                }),
+                "missing fields 1, 3",
+            ),
+            (
+                Box::new(FromJsonError::InvalidFieldNumber("a".to_string())),
+                "key \"a\" is not a field number",
+            ),
+            (
+                Box::new(FieldValueError::UnexpectedWireType(WireType::Fixed32)),
+                "unexpected fixed32 value",
+            ),
+            (Box::new(InvalidWireType(7)), "invalid wire type 7"),
+            (Box::new(VarintError::Incomplete), "varint is truncated"),
+        ];
+        for (err, expected) in cases {
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_error_bounds() {
+        fn assert_error<T: error::Error + Send + Sync + 'static>() {}
+        assert_error::<ParseError>();
+        assert_error::<MissingFields>();
+        assert_error::<FromJsonError>();
+        assert_error::<FieldValueError>();
+        assert_error::<InvalidWireType>();
+        assert_error::<VarintError>();
+        assert_error::<UnknownBytesEncoding>();
+        assert_error::<DescriptorError>();
+        assert_error::<ShapeMismatch>();
+        assert_error::<ShapeError>();
+        #[cfg(feature = "schema")]
+        assert_error::<SchemaError>();
+        #[cfg(feature = "gzip")]
+        assert_error::<GzipError>();
+    }
+}
@@ -0,0 +1,251 @@
+//! Report of the decisions made while guessing field types.
+
+use std::fmt;
+
+use serde_json::{Value, json};
+
+use crate::{FieldPath, Parser, WireType, parser::Walk};
+
+/// How a field was converted by [`Parser::parse`], as reported by [`Parser::explain`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldExplanation {
+    /// Path of the field.
+    pub path: FieldPath,
+
+    /// Wire type of the field.
+    pub wire_type: WireType,
+
+    /// Why a length-delimited value emitted as string or bytes was not taken as a nested
+    /// message.
+    pub rejection: Option<NestedRejection>,
+
+    /// What the field was emitted as.
+    pub output: FieldOutput,
+}
+
+/// Reason a length-delimited value was not parsed as a nested message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NestedRejection {
+    /// The value is empty.
+    Empty,
+
+    /// The value is shorter than [`Parser::with_min_nested_len`].
+    TooShort,
+
+    /// The value is text without control characters.
+    Text,
+
+    /// No field could be decoded.
+    NoFields,
+
+    /// The value contains an invalid or incomplete field.
+    InvalidField,
+
+    /// The value ends with garbage.
+    Garbage,
+
+    /// The value uses a reserved field number.
+    ReservedField,
+}
+
+/// What a field was emitted as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldOutput {
+    /// A number or another scalar rendering, such as an enum name.
+    Scalar,
+
+    /// An array of packed elements.
+    Packed,
+
+    /// A nested message.
+    Message,
+
+    /// Embedded JSON text, inlined.
+    Json,
+
+    /// A UTF-8 string.
+    String,
+
+    /// Bytes in the configured [`BytesEncoding`](crate::BytesEncoding).
+    Bytes,
+
+    /// Nothing, the field is invalid or incomplete.
+    Dropped,
+}
+
+/// Number of length-delimited fields per output kind, as returned by
+/// [`Parser::encoding_report`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EncodingReport {
+    /// Fields emitted as nested messages.
+    pub messages: usize,
+
+    /// Fields emitted as UTF-8 strings.
+    pub strings: usize,
+
+    /// Fields emitted as bytes in the configured encoding.
+    pub bytes: usize,
+
+    /// Fields emitted as packed arrays.
+    pub packed: usize,
+
+    /// Fields emitted as embedded JSON.
+    pub json: usize,
+
+    /// Invalid or incomplete fields left out.
+    pub dropped: usize,
+}
+
+impl From<&EncodingReport> for Value {
+    fn from(report: &EncodingReport) -> Self {
+        json!({
+            "messages": report.messages,
+            "strings": report.strings,
+            "bytes": report.bytes,
+            "packed": report.packed,
+            "json": report.json,
+            "dropped": report.dropped,
+        })
+    }
+}
+
+impl From<EncodingReport> for Value {
+    fn from(report: EncodingReport) -> Self {
+        Value::from(&report)
+    }
+}
+
+impl Parser {
+    /// Parse a protobuf message and count how its length-delimited fields were converted, at
+    /// every nesting level.
+    ///
+    /// This summarizes [`Parser::explain`], showing at a glance whether the guesses need to be
+    /// corrected with hints.
+    pub fn encoding_report(&self, data: &[u8]) -> EncodingReport {
+        let mut report = EncodingReport::default();
+        for explanation in self.explain(data) {
+            if explanation.wire_type != WireType::LengthDelimited {
+                continue;
+            }
+            let count = match explanation.output {
+                FieldOutput::Message => &mut report.messages,
+                FieldOutput::String => &mut report.strings,
+                FieldOutput::Bytes => &mut report.bytes,
+                FieldOutput::Packed => &mut report.packed,
+                FieldOutput::Json => &mut report.json,
+                FieldOutput::Dropped => &mut report.dropped,
+                FieldOutput::Scalar => continue,
+            };
+            *count += 1;
+        }
+        report
+    }
+
+    /// Parse a protobuf message and report how each field was converted.
+    ///
+    /// The fields are listed in output order, each nested message right after the field holding
+    /// it. The conversion is the one of [`Parser::parse`], which is not affected by this report.
+    /// Returns an empty list if the data is not a message.
+    pub fn explain(&self, data: &[u8]) -> Vec<FieldExplanation> {
+        let mut walk = Walk::new(self);
+        walk.explanations = Some(Vec::new());
+        // The report covers the fields converted before a failure too.
+        let _ = self.parse_to_json(data, &mut walk);
+        walk.explanations.unwrap_or_default()
+    }
+}
+
+impl fmt::Display for FieldExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "field {}: {}", self.path, self.wire_type)?;
+        if let Some(rejection) = self.rejection {
+            write!(f, ", tried nested (failed: {rejection})")?;
+        }
+        write!(f, ", emitted as {}", self.output)
+    }
+}
+
+impl fmt::Display for NestedRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NestedRejection::Empty => "empty",
+            NestedRejection::TooShort => "too short",
+            NestedRejection::Text => "text",
+            NestedRejection::NoFields => "no fields",
+            NestedRejection::InvalidField => "invalid field",
+            NestedRejection::Garbage => "garbage",
+            NestedRejection::ReservedField => "reserved field number",
+        })
+    }
+}
+
+impl fmt::Display for FieldOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FieldOutput::Scalar => "scalar",
+            FieldOutput::Packed => "packed",
+            FieldOutput::Message => "message",
+            FieldOutput::Json => "JSON",
+            FieldOutput::String => "string",
+            FieldOutput::Bytes => "bytes",
+            FieldOutput::Dropped => "nothing",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new();
+        let report: Vec<_> = parser
+            .explain(&data)
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            report,
+            [
+                "field 1: fixed32, emitted as scalar",
+                "field 2: length-delimited, tried nested (failed: text), emitted as string",
+                "field 3: length-delimited, tried nested (failed: text), emitted as string",
+                "field 4: varint, emitted as scalar",
+                "field 5: length-delimited, emitted as message",
+                "field 5.1: length-delimited, tried nested (failed: text), emitted as string",
+                "field 5.2: length-delimited, tried nested (failed: empty), emitted as string",
+            ]
+        );
+        assert_eq!(parser.parse(&data), Parser::new().parse(&data));
+
+        let report = parser.explain(&hex!("0a040801c3a9120208ff"));
+        assert_eq!(report[0].rejection, Some(NestedRejection::Garbage));
+        assert_eq!(report[0].output, FieldOutput::String);
+        assert_eq!(report[1].rejection, Some(NestedRejection::InvalidField));
+        assert_eq!(report[1].output, FieldOutput::Bytes);
+    }
+
+    #[test]
+    fn test_encoding_report() {
+        let data = hex!(
+            "0a0a6173636f6e2d66756c6c120a6173636f6e2d66756c6c1a1b323032352d30392d30325430393a33373a32362e3033393032385a2203302e312a0474657374421b323032352d30392d30325430393a33373a32362e3033393032385a480068007205302e312e308a016e46756c6c204173636f6e20696d706c656d656e746174696f6e202868617368e280913235362c2041454144e280913132382077697468206e6f6e6365206d61736b696e67202620746167207472756e636174696f6e2c20584f46e280913132382c2043584f46e28091313238292e92012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6c9a011a68747470733a2f2f646f63732e72732f6173636f6e2d66756c6ca2012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6caa014612222f6170692f76312f6372617465732f6173636f6e2d66756c6c2f76657273696f6e731a202f6170692f76312f6372617465732f6173636f6e2d66756c6c2f6f776e657273"
+        );
+        let report = Parser::new().encoding_report(&data);
+        assert_eq!(
+            report,
+            EncodingReport {
+                messages: 1,
+                strings: 13,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Value::from(report),
+            json!({"messages": 1, "strings": 13, "bytes": 0, "packed": 0, "json": 0, "dropped": 0})
+        );
+    }
+}
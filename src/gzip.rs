@@ -0,0 +1,328 @@
+//! Parsing of gzip compressed messages (RFC 1952), inflated with `flate2`.
+
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+use serde_json::Value;
+
+use crate::{GzipError, Parser};
+
+impl Parser {
+    /// Decompress a gzip compressed protobuf message and convert it to JSON.
+    ///
+    /// Data of several concatenated gzip members is decompressed as a whole, as `gzip -d` does.
+    /// The CRC-32 and length of every member are checked. Decompression stops with
+    /// [`GzipError::TooLarge`] beyond the limit of [`Parser::with_max_decompressed_len`].
+    pub fn parse_gzip(&self, data: &[u8]) -> Result<Value, GzipError> {
+        let data = gunzip(data, self.max_decompressed_len)?;
+        self.try_parse(&data).map_err(GzipError::Parse)
+    }
+}
+
+/// Decompress all gzip members of `data`, into at most `max_len` bytes.
+fn gunzip(mut data: &[u8], max_len: usize) -> Result<Vec<u8>, GzipError> {
+    let mut out = Vec::new();
+    loop {
+        let body = skip_header(data)?;
+        let start = out.len();
+        let read = inflate(body, &mut out, max_len, false)?;
+        let (trailer, rest) = body[read..]
+            .split_first_chunk::<8>()
+            .ok_or(GzipError::Truncated)?;
+        let (crc, len) = trailer.split_at(4);
+        let mut sum = Crc::new();
+        sum.update(&out[start..]);
+        if sum.sum().to_le_bytes() != crc || sum.amount().to_le_bytes() != len {
+            return Err(GzipError::ChecksumMismatch);
+        }
+        if rest.is_empty() {
+            return Ok(out);
+        }
+        data = rest;
+    }
+}
+
+/// Decompress a bytes field noted as gzip or zlib compressed, see [`Parser::with_verbose`].
+#[cfg(feature = "decompress")]
+pub(crate) fn decompress(data: &[u8], compression: &str, max_len: usize) -> Option<Vec<u8>> {
+    match compression {
+        "gzip" => gunzip(data, max_len).ok(),
+        "zlib" => unzlib(data, max_len).ok(),
+        _ => None,
+    }
+}
+
+/// Decompress a zlib stream (RFC 1950), into at most `max_len` bytes.
+#[cfg(feature = "decompress")]
+fn unzlib(data: &[u8], max_len: usize) -> Result<Vec<u8>, GzipError> {
+    let mut out = Vec::new();
+    inflate(data, &mut out, max_len, true)?;
+    Ok(out)
+}
+
+/// Skip the header of a gzip member, returning the deflate stream after it.
+fn skip_header(data: &[u8]) -> Result<&[u8], GzipError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let Some((&[0x1f, 0x8b, 8, flags, ..], mut rest)) = data.split_first_chunk::<10>() else {
+        return Err(
+            match data.len() < 10 && data.starts_with(&[0x1f, 0x8b][..data.len().min(2)]) {
+                true => GzipError::Truncated,
+                false => GzipError::InvalidHeader,
+            },
+        );
+    };
+    if flags & 0xe0 != 0 {
+        return Err(GzipError::InvalidHeader);
+    }
+    if flags & FEXTRA != 0 {
+        let (len, extra) = rest.split_first_chunk::<2>().ok_or(GzipError::Truncated)?;
+        rest = extra
+            .get(u16::from_le_bytes(*len) as usize..)
+            .ok_or(GzipError::Truncated)?;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = rest
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or(GzipError::Truncated)?;
+            rest = &rest[end + 1..];
+        }
+    }
+    if flags & FHCRC != 0 {
+        rest = rest.get(2..).ok_or(GzipError::Truncated)?;
+    }
+    Ok(rest)
+}
+
+/// Decompress a deflate stream, or a zlib stream with `zlib`, appending to `out`. Returns the
+/// number of bytes read from `data`. `out` never grows beyond `max_len` bytes.
+fn inflate(data: &[u8], out: &mut Vec<u8>, max_len: usize, zlib: bool) -> Result<usize, GzipError> {
+    let mut inflater = Decompress::new(zlib);
+    let mut buf = [0; 1 << 15];
+    loop {
+        // One byte more than the limit allows tells a stream ending at the limit from a larger one.
+        let room = (max_len - out.len()).saturating_add(1).min(buf.len());
+        let (read, written) = (inflater.total_in(), inflater.total_out());
+        let status = inflater
+            .decompress(
+                &data[read as usize..],
+                &mut buf[..room],
+                FlushDecompress::None,
+            )
+            .map_err(|_| GzipError::InvalidDeflate)?;
+        let len = (inflater.total_out() - written) as usize;
+        out.extend_from_slice(&buf[..len]);
+        if out.len() > max_len {
+            return Err(GzipError::TooLarge { limit: max_len });
+        }
+        match status {
+            Status::StreamEnd => return Ok(inflater.total_in() as usize),
+            _ if len == 0 && inflater.total_in() == read => return Err(GzipError::Truncated),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::ParseError;
+
+    const README: [u8; 28] = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+
+    #[test]
+    fn test_parse_gzip() {
+        let expected = Parser::new().parse(&README).unwrap();
+
+        // Fixed Huffman codes, as written by `gzip`.
+        let fixed = hex!(
+            "1f8b0800000000000203e39561606010628ecc2f9562f24d55d0d6e2e2624b4c4a3634321662000002"
+            "4303f71c000000"
+        );
+        assert_eq!(Parser::new().parse_gzip(&fixed), Ok(expected.clone()));
+
+        // Stored block, as written by `gzip -0`.
+        let stored = hex!(
+            "1f8b0800000000000403011c00e3ff0d1c0000001203596f751a024d65202b2a0a0a0661626331323312"
+            "00024303f71c000000"
+        );
+        assert_eq!(gunzip(&stored, usize::MAX).unwrap(), README);
+
+        // Two members.
+        let both = [&fixed[..], &stored[..]].concat();
+        assert_eq!(
+            gunzip(&both, usize::MAX).unwrap(),
+            [README, README].concat()
+        );
+    }
+
+    #[test]
+    fn test_parse_gzip_dynamic_codes() {
+        let words =
+            b"protobuf json parser field varint message nested length delimited fixed wire type ";
+        let mut data = Vec::new();
+        for i in 0..6 {
+            data.extend([0x0a, words.len() as u8]);
+            data.extend_from_slice(&words[i * 7..]);
+            data.extend_from_slice(&words[..i * 7]);
+        }
+        let compressed = hex!(
+            "1f8b0800000000000203adcecb0d03310884e13b5550131d38f27843e4b52d20afeeb3915c029739cce1"
+            "d747b26cc6bc3d1b3f7c0e5ec51cc64dd12bbf8ae9083ee15e0ef080072a778c23ee5cd1f5d4ffd1f473"
+            "ed5b0d1cdf0526498c6d1e49626cf32e675e6cf34812639b479218dbbc1f791c10c7f8010000"
+        );
+        assert_eq!(gunzip(&compressed, usize::MAX).unwrap(), data);
+    }
+
+    #[test]
+    fn test_parse_gzip_errors() {
+        let parser = Parser::new();
+        assert_eq!(parser.parse_gzip(&README), Err(GzipError::InvalidHeader));
+        assert_eq!(parser.parse_gzip(&[0x1f, 0x8b]), Err(GzipError::Truncated));
+
+        let data = hex!(
+            "1f8b0800000000000203e39561606010628ecc2f9562f24d55d0d6e2e2624b4c4a3634321662000002"
+            "4303f71c000000"
+        );
+        assert_eq!(
+            parser.parse_gzip(&data[..data.len() - 4]),
+            Err(GzipError::Truncated)
+        );
+        let mut corrupt = data;
+        corrupt[data.len() - 8] ^= 1;
+        assert_eq!(
+            parser.parse_gzip(&corrupt),
+            Err(GzipError::ChecksumMismatch)
+        );
+        // Reserved block type 3.
+        let mut invalid = data;
+        invalid[10] = 0x07;
+        assert_eq!(parser.parse_gzip(&invalid), Err(GzipError::InvalidDeflate));
+
+        // Valid gzip of data that is not a message.
+        let empty = hex!("1f8b080000000000000303000000000000000000");
+        assert_eq!(
+            parser.parse_gzip(&empty),
+            Err(GzipError::Parse(ParseError::NotAMessage))
+        );
+    }
+
+    #[test]
+    fn test_parse_gzip_too_large() {
+        // 100000 zero bytes.
+        let zeros = hex!(
+            "1f8b0800000000000203edc13101000000c2a0f54f6d0d0fa00000000000000000000000000000000000"
+            "000000000000000000000000000000000000000000000000000000000000000000000000000000000000"
+            "000000000000000000000000000000000000000000000000000000000000000000000000008057037d95"
+            "11d4a0860100"
+        );
+        assert_eq!(gunzip(&zeros, 100_000).unwrap().len(), 100_000);
+        assert_eq!(
+            gunzip(&zeros, 99_999),
+            Err(GzipError::TooLarge { limit: 99_999 })
+        );
+
+        // Stored block of README.
+        let stored = hex!(
+            "1f8b0800000000000403011c00e3ff0d1c0000001203596f751a024d65202b2a0a0a0661626331323312"
+            "00024303f71c000000"
+        );
+        assert_eq!(
+            Parser::new()
+                .with_max_decompressed_len(27)
+                .parse_gzip(&stored),
+            Err(GzipError::TooLarge { limit: 27 })
+        );
+        // The limit applies to all members together.
+        let both = [&stored[..], &stored[..]].concat();
+        assert_eq!(gunzip(&both, 55), Err(GzipError::TooLarge { limit: 55 }));
+    }
+
+    #[test]
+    fn test_parse_gzip_corrupt() {
+        let fixed = hex!(
+            "1f8b0800000000000203e39561606010628ecc2f9562f24d55d0d6e2e2624b4c4a3634321662000002"
+            "4303f71c000000"
+        );
+        let dynamic = hex!(
+            "1f8b0800000000000203adcecb0d03310884e13b5550131d38f27843e4b52d20afeeb3915c029739cce1"
+            "d747b26cc6bc3d1b3f7c0e5ec51cc64dd12bbf8ae9083ee15e0ef080072a778c23ee5cd1f5d4ffd1f473"
+            "ed5b0d1cdf0526498c6d1e49626cf32e675e6cf34812639b479218dbbc1f791c10c7f8010000"
+        );
+        for data in [&fixed[..], &dynamic[..]] {
+            // Every truncation fails.
+            for len in 0..data.len() {
+                assert!(gunzip(&data[..len], 1 << 16).is_err(), "truncated to {len}");
+            }
+            // Every single bit flip either fails or is caught by the checksum, without
+            // exceeding the limit.
+            for i in 0..data.len() {
+                for bit in 0..8 {
+                    let mut corrupt = data.to_vec();
+                    corrupt[i] ^= 1 << bit;
+                    if let Ok(out) = gunzip(&corrupt, 1 << 16) {
+                        assert!(out.len() <= 1 << 16);
+                    }
+                }
+            }
+        }
+
+        // Random deflate streams never panic nor exceed the limit.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..2000 {
+            let mut data = hex!("1f8b08000000000000ff").to_vec();
+            for _ in 0..64 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                data.push(state as u8);
+            }
+            if let Err(GzipError::TooLarge { limit }) = gunzip(&data, 1024) {
+                assert_eq!(limit, 1024);
+            }
+        }
+    }
+
+    #[cfg(feature = "decompress")]
+    #[test]
+    fn test_parse_verbose_decompress() {
+        use serde_json::json;
+
+        // 1: zlib({1: 1, 2: "You"}), 2: gzip({1: 1, 2: "You"})
+        let data = hex!(
+            "0a0f789ce36014628ecc2f05000309015c"
+            "121b1f8b0800000000000203e36014628ecc2f050054ce2dcc07000000"
+        );
+        let json = Parser::new().with_verbose(true).parse(&data).unwrap();
+        assert_eq!(json["1"]["compressed"], json!("zlib"));
+        assert_eq!(json["1"]["decompressed"], json!({"1": 1, "2": "You"}));
+        assert_eq!(json["2"]["compressed"], json!("gzip"));
+        assert_eq!(json["2"]["decompressed"], json!({"1": 1, "2": "You"}));
+
+        let json = Parser::new()
+            .with_verbose(true)
+            .with_typed_json(true)
+            .parse(&data)
+            .unwrap();
+        assert_eq!(json["1"]["type"], json!("bytes"));
+        assert_eq!(json["1"]["decompressed"]["2"]["value"], json!("You"));
+
+        // Beyond the size limit, or corrupt, the value is only noted as compressed.
+        let parser = Parser::new()
+            .with_verbose(true)
+            .with_max_decompressed_len(6);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["1"]["compressed"], json!("zlib"));
+        assert_eq!(json["1"].get("decompressed"), None);
+        let json = Parser::new()
+            .with_verbose(true)
+            .parse(&hex!("0a0f789ce36014628ecc2f05000309015d"))
+            .unwrap();
+        assert_eq!(json["1"].get("decompressed"), None);
+    }
+}
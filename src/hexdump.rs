@@ -0,0 +1,168 @@
+//! Parsing of hex dumps, as printed by `xxd` or `hexdump -C`.
+
+use serde_json::Value;
+
+use crate::Parser;
+
+impl Parser {
+    /// Parse a protobuf message from a hex dump with offsets, e.g. the output of `xxd`,
+    /// `hexdump -C` or Wireshark's "Copy as Hex + ASCII Dump".
+    ///
+    /// Every line starts with an offset column, followed by the hex bytes and an optional ASCII
+    /// column, which is ignored. Lines of a `*` standing for repeated lines are expanded up to
+    /// the offset of the next line, up to a total of 16 MiB. Returns `None` if the text is not
+    /// a hex dump, expands beyond that size or the bytes are not a message.
+    ///
+    /// ``` rust
+    /// use protobuf_to_json::Parser;
+    /// use serde_json::json;
+    ///
+    /// let dump = "00000000: 0801 1203 596f 75                        ....You";
+    /// let json = Parser::new().parse_hexdump(dump).unwrap();
+    /// assert_eq!(json, json!({"1": 1, "2": "You"}));
+    /// ```
+    pub fn parse_hexdump(&self, text: &str) -> Option<Value> {
+        self.parse(&hexdump_bytes(text)?)
+    }
+}
+
+/// Largest offset a `*` line of a hex dump may expand to, so that a crafted dump can't allocate
+/// gigabytes.
+const MAX_REPEAT_END: usize = 16 << 20;
+
+/// Extract the bytes of a hex dump.
+fn hexdump_bytes(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut start = None;
+    let mut last_line = 0..0;
+    let mut repeat = false;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line == "*" {
+            repeat = true;
+            continue;
+        }
+        let (offset, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let xxd = offset.ends_with(':');
+        let offset = u64::from_str_radix(offset.trim_end_matches(':'), 16).ok()?;
+        let start = *start.get_or_insert(offset);
+        if repeat {
+            let end = usize::try_from(offset.checked_sub(start)?).ok()?;
+            let line = data.get(last_line.clone())?.to_vec();
+            if line.is_empty()
+                || end < data.len()
+                || end > MAX_REPEAT_END
+                || (end - data.len()) % line.len() != 0
+            {
+                return None;
+            }
+            while data.len() < end {
+                data.extend_from_slice(&line);
+            }
+            repeat = false;
+        }
+
+        let rest = rest.trim_start();
+        // The ASCII column is enclosed in `|` by `hexdump -C`, and follows a wider gap
+        // otherwise. `hexdump -C` also splits the hex column in two halves with such a gap.
+        let hex = match rest.split_once('|') {
+            Some((hex, _)) if !xxd && rest.ends_with('|') => hex,
+            _ => rest.split_once("  ").map_or(rest, |(hex, _)| hex),
+        };
+        let line_start = data.len();
+        for group in hex.split_whitespace() {
+            data.extend(decode_hex(group)?);
+        }
+        last_line = line_start..data.len();
+    }
+    if repeat {
+        return None;
+    }
+    Some(data)
+}
+
+/// Decode a group of hex digits.
+fn decode_hex(group: &str) -> Option<Vec<u8>> {
+    let (pairs, []) = group.as_bytes().as_chunks::<2>() else {
+        return None;
+    };
+    pairs
+        .iter()
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_hexdump() {
+        let expected = Parser::new()
+            .parse(&hex!(
+                "0d1c0000001203596f751a024d65202b2a0a0a066162633132331200"
+            ))
+            .unwrap();
+
+        let xxd = "\
+00000000: 0d1c 0000 0012 0359 6f75 1a02 4d65 202b  .......You..Me +
+00000010: 2a0a 0a06 6162 6331 3233 1200            *...abc123..
+";
+        assert_eq!(Parser::new().parse_hexdump(xxd).unwrap(), expected);
+
+        let xxd = "\
+00000000: 0d 1c 00 00 00 12 03 59  .......Y
+00000008: 6f 75 1a 02 4d 65 20 2b  ou..Me +
+00000010: 2a 0a 0a 06 61 62 63 31  *...abc1
+00000018: 32 33 12 00              23..
+";
+        assert_eq!(Parser::new().parse_hexdump(xxd).unwrap(), expected);
+
+        let hexdump = "\
+00000000  0d 1c 00 00 00 12 03 59  6f 75 1a 02 4d 65 20 2b  |.......You..Me +|
+00000010  2a 0a 0a 06 61 62 63 31  32 33 12 00              |*...abc123..|
+0000001c
+";
+        assert_eq!(Parser::new().parse_hexdump(hexdump).unwrap(), expected);
+
+        let wireshark = "\
+0000   0d 1c 00 00 00 12 03 59 6f 75 1a 02 4d 65 20 2b   .......You..Me +
+0010   2a 0a 0a 06 61 62 63 31 32 33 12 00               *...abc123..
+";
+        assert_eq!(Parser::new().parse_hexdump(wireshark).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_hexdump_repeated_lines() {
+        let dump = "\
+00000000  0a 04 00 00 00 00 00 00  |........|
+00000008  00 00 00 00 00 00 00 00  |........|
+*
+00000020  10 01                    |..|
+00000022
+";
+        let data = hexdump_bytes(dump).unwrap();
+        assert_eq!(data.len(), 0x22);
+        assert_eq!(data[0x20..], hex!("1001"));
+        assert!(data[2..0x20].iter().all(|b| *b == 0));
+
+        assert_eq!(hexdump_bytes("00000000: 0801 xyz"), None);
+        assert_eq!(hexdump_bytes("00000000: 080  ..."), None);
+        assert_eq!(hexdump_bytes("00000000  08 01\n*\n"), None);
+        assert_eq!(Parser::new().parse_hexdump("not a dump"), None);
+    }
+
+    #[test]
+    fn test_hexdump_repeat_limit() {
+        let dump = "00000000  00 00 00 00 00 00 00 00\n*\nffffffff  00\n";
+        assert_eq!(hexdump_bytes(dump), None);
+        let dump = "00000000  00 00 00 00 00 00 00 00\n*\n01000000\n";
+        assert_eq!(hexdump_bytes(dump).unwrap().len(), MAX_REPEAT_END);
+        let dump = "00000000  00 00 00 00 00 00 00 00\n*\n01000008\n";
+        assert_eq!(hexdump_bytes(dump), None);
+    }
+}
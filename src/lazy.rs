@@ -0,0 +1,128 @@
+//! Messages decoded on access.
+
+use serde_json::Value;
+
+use crate::{FieldValue, Parser};
+
+/// A message or field value whose nested messages are only decoded when accessed, as returned by
+/// [`Parser::parse_lazy`].
+///
+/// Fields are looked up with [`LazyValue::get`], which decodes the first layer of the value only,
+/// and converted to JSON with [`LazyValue::to_json`].
+#[derive(Debug, Clone)]
+pub struct LazyValue<'a> {
+    parser: &'a Parser,
+    value: FieldValue<'a>,
+
+    /// Field numbers leading to the value, empty for the top-level message.
+    path: Vec<u64>,
+}
+
+impl<'a> LazyValue<'a> {
+    /// Field numbers leading to the value, empty for the top-level message.
+    pub fn path(&self) -> &[u64] {
+        &self.path
+    }
+
+    /// Wire value, the top-level message being a length-delimited value.
+    pub fn value(&self) -> &FieldValue<'a> {
+        &self.value
+    }
+
+    /// Last occurrence of a field of the message, as the last one wins in protobuf.
+    ///
+    /// Only the first layer of the value is decoded. Returns `None` if the field is missing or
+    /// the value is not length-delimited. Fields before garbage are still found.
+    pub fn get(&self, field: u64) -> Option<LazyValue<'a>> {
+        self.get_all(field).pop()
+    }
+
+    /// All occurrences of a field of the message, in wire order.
+    ///
+    /// Same as [`LazyValue::get`], for repeated fields.
+    pub fn get_all(&self, field: u64) -> Vec<LazyValue<'a>> {
+        let FieldValue::LengthDelimited(data) = self.value else {
+            return Vec::new();
+        };
+        let mut values = Vec::new();
+        self.parser.decode_fields(data, |f| {
+            if f.number == field {
+                let mut path = self.path.clone();
+                path.push(field);
+                values.push(LazyValue {
+                    parser: self.parser,
+                    value: f.value,
+                    path,
+                });
+            }
+        });
+        values
+    }
+
+    /// Decode the value and convert it to JSON, the way it is in the output of
+    /// [`Parser::parse`].
+    ///
+    /// Returns `None` if the top-level message or the value is invalid.
+    pub fn to_json(&self) -> Option<Value> {
+        match (&self.value, self.path.is_empty()) {
+            (FieldValue::LengthDelimited(data), true) => self.parser.parse(data),
+            (value, _) => self.parser.field_value_to_json(value, &self.path),
+        }
+    }
+}
+
+impl Parser {
+    /// Parse a protobuf message lazily, decoding nested messages only when accessed.
+    ///
+    /// Nothing is decoded until a field is looked up with [`LazyValue::get`], which avoids parsing
+    /// large nested structures when only part of the tree is needed.
+    pub fn parse_lazy<'a>(&'a self, data: &'a [u8]) -> LazyValue<'a> {
+        LazyValue {
+            parser: self,
+            value: FieldValue::LengthDelimited(data),
+            path: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_lazy() {
+        // 1: 28, 5: {1: "abc123", 2: ""}, 1: 29.
+        let data = hex!("081c 2a0a0a06616263313233120008 1d");
+        let tags = Arc::new(AtomicUsize::new(0));
+        let counter = tags.clone();
+        let parser = Parser::new().with_tag_transform(move |tag| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            tag
+        });
+
+        let lazy = parser.parse_lazy(&data);
+        assert_eq!(tags.load(Ordering::Relaxed), 0);
+        let nested = lazy.get(5).unwrap();
+        assert_eq!(tags.load(Ordering::Relaxed), 3);
+        assert_eq!(lazy.get(1).unwrap().to_json(), Some(json!(29)));
+        assert_eq!(lazy.get_all(1).len(), 2);
+        assert!(lazy.get(2).is_none());
+        // The nested message is only decoded now.
+        let before = tags.load(Ordering::Relaxed);
+        assert_eq!(nested.get(1).unwrap().to_json(), Some(json!("abc123")));
+        assert_eq!(nested.path(), [5]);
+        assert_eq!(tags.load(Ordering::Relaxed), before + 2);
+
+        assert_eq!(nested.to_json(), Some(json!({"1": "abc123", "2": ""})));
+        assert_eq!(lazy.to_json(), parser.parse(&data));
+        assert!(lazy.get(1).unwrap().get(1).is_none());
+    }
+}
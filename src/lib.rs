@@ -7,10 +7,19 @@
 //! * Use field number as json key
 //! * Configurable bytes encoding (base64, hex, byte array, etc.)
 //! * Automatically guesses length-delimited value types (string, nested message, bytes)
+//! * Optional field names and types from a lightweight JSON descriptor
+//! * Canonical proto3 JSON output when a `.proto` schema is available (`schema` feature)
+//! * Parsing of memory-mapped files (`mmap` feature)
+//! * YAML output (`yaml` feature)
+//! * Parsing of gzip compressed messages (`gzip` feature)
+//! * Decompression of gzip and zlib compressed bytes fields in the verbose output
+//!   (`decompress` feature)
+//! * Parsing into fields allocated in a bump arena (`bumpalo` feature)
 //!
 //! ## Limitations
 //! * Length-delimited value type is guessed based on content. It may not always be correct.
 //! * Repeated fields (with the same field number) may not be grouped into arrays when only one field is parsed.
+//!   Hint such fields with `Parser::with_repeated_fields` to always get arrays.
 //!
 //! ## Examples
 //!
@@ -36,10 +45,62 @@
 //! ```
 //!
 
+#[cfg(feature = "bumpalo")]
+mod arena;
+mod canonical;
+mod decode_raw;
+mod descriptor;
+mod diff;
+mod error;
+mod explain;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod hexdump;
+mod lazy;
 mod message;
+#[cfg(feature = "mmap")]
+mod mmap;
 mod parser;
+mod path;
+#[cfg(feature = "schema")]
+mod schema;
+mod ser;
+mod shape;
+mod spanned;
+mod stream;
+mod typed;
 mod varint;
+#[cfg(feature = "yaml")]
+mod yaml;
 
-pub use message::{Field, FieldValue, Message};
-pub use parser::{BytesEncoding, Parser};
-pub use varint::decode_var;
+#[cfg(feature = "bumpalo")]
+pub use arena::MessageArena;
+pub use descriptor::{Descriptor, FieldDescriptor, FieldType};
+pub use diff::MessageDiff;
+#[cfg(feature = "gzip")]
+pub use error::GzipError;
+#[cfg(feature = "schema")]
+pub use error::SchemaError;
+pub use error::{
+    DescriptorError, FieldValueError, FromJsonError, InvalidWireType, MissingFields, ParseError,
+    ShapeError, ShapeIssue, ShapeMismatch, UnknownBytesEncoding, VarintError,
+};
+pub use explain::{EncodingReport, FieldExplanation, FieldOutput, NestedRejection};
+pub use lazy::LazyValue;
+pub use message::{Field, FieldValue, Message, WireType};
+pub use parser::{
+    Ambiguity, BytesEncoding, DuplicatePolicy, ElementType, EnumRender, FieldFilter, IntRadix,
+    NonFinite, Parser, default_parser, parse,
+};
+pub use path::FieldPath;
+#[cfg(feature = "schema")]
+pub use schema::Schema;
+pub use ser::NumericKeyOrder;
+pub use shape::{FieldShape, ShapeDescriptor};
+pub use spanned::SpannedNode;
+pub use stream::{FrameSink, StreamParser};
+pub use typed::TypedValue;
+pub use varint::{
+    decode_var, decode_var_msb_first, decode_varint_i64, decode_zigzag_i32, decode_zigzag_i64,
+    encode_var, varint_len,
+};
@@ -1,4 +1,11 @@
-use crate::decode_var;
+use std::{fmt, mem};
+
+use serde_json::{Map, Value};
+
+use crate::{
+    FieldValueError, FromJsonError, InvalidWireType, Parser, decode_var, encode_var,
+    varint::VarintDecoder,
+};
 
 /// Protocol buffer message.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
@@ -9,10 +16,105 @@ pub struct Message<'a> {
     /// Garbage data at the end of the message.
     ///
     /// As opposed to an `UnknownValue::Invalid`, the garbage data did not have a valid field
-    /// number and for that reason cannot be placed into the `fields` vector.
+    /// number, e.g. a truncated tag or one above the protobuf maximum of 2^29 - 1, and for that
+    /// reason cannot be placed into the `fields` vector.
     pub garbage: Option<&'a [u8]>,
 }
 
+impl<'a> Message<'a> {
+    /// Rebuild the message structure from JSON keyed by field numbers.
+    ///
+    /// This is the inverse of the default JSON output without serializing anything to bytes:
+    /// * objects become nested [`FieldValue::Message`]s,
+    /// * arrays become repeated fields, one field per element,
+    /// * strings become [`FieldValue::LengthDelimited`] borrowing the string bytes,
+    /// * booleans and integers become [`FieldValue::Varint`], negative integers in two's
+    ///   complement as `int64` does,
+    /// * floating point numbers become [`FieldValue::Fixed64`] holding the `double` bits.
+    ///
+    /// Fields are ordered by field number, repeated fields keep their array order. Information
+    /// lost by the JSON conversion, such as bytes encoded as base64, is not recovered.
+    pub fn from_json(value: &'a Value) -> Result<Self, FromJsonError> {
+        match value {
+            Value::Object(map) => Self::from_json_map(map),
+            _ => Err(FromJsonError::NotAnObject),
+        }
+    }
+
+    /// Whether all of the data decoded into valid fields, without garbage, invalid or
+    /// incomplete values.
+    pub fn is_complete(&self) -> bool {
+        self.garbage.is_none()
+            && self.fields.iter().all(|f| {
+                !matches!(
+                    f.value,
+                    FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _)
+                )
+            })
+    }
+
+    /// Iterate over the fields that look like nested messages, decoding them lazily.
+    ///
+    /// Length-delimited fields are decoded with the same guessing heuristic the `parser` uses
+    /// for nested messages. This gives structural access to the tree for callers who want to
+    /// walk it with their own policy.
+    pub fn nested_messages<'p>(
+        &'p self,
+        parser: &'p Parser,
+    ) -> impl Iterator<Item = (u64, Message<'a>)> + 'p {
+        self.fields.iter().filter_map(|field| match &field.value {
+            FieldValue::LengthDelimited(bytes) if !bytes.is_empty() => parser
+                .guess_message(bytes, false)
+                .map(|msg| (field.number, msg)),
+            FieldValue::Message(msg) => Some((field.number, msg.clone())),
+            _ => None,
+        })
+    }
+
+    /// Serialize the message to protobuf bytes.
+    ///
+    /// Fields are written in order, with the shortest encoding of tags, lengths and varints.
+    /// Invalid and incomplete values and the garbage are written back as they were decoded, so
+    /// decoding and re-encoding data gives the same bytes unless it has overlong varints.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    /// Serialize the message like [`Message::encode`], appending to `out`.
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        for field in &self.fields {
+            field.encode_into(out);
+        }
+        if let Some(garbage) = self.garbage {
+            out.extend_from_slice(garbage);
+        }
+    }
+
+    fn from_json_map(map: &'a Map<String, Value>) -> Result<Self, FromJsonError> {
+        let mut fields = Vec::new();
+        for (key, value) in map {
+            let number = key
+                .parse()
+                .map_err(|_| FromJsonError::InvalidFieldNumber(key.clone()))?;
+            let values = match value {
+                Value::Array(values) => values.as_slice(),
+                _ => std::slice::from_ref(value),
+            };
+            for value in values {
+                let value = FieldValue::from_json(key, value)?;
+                fields.push(Field { number, value });
+            }
+        }
+        fields.sort_by_key(|f| f.number);
+        Ok(Self {
+            fields,
+            garbage: None,
+        })
+    }
+}
+
 /// Decoded protocol buffer field.
 ///
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -24,13 +126,40 @@ pub struct Field<'a> {
     pub value: FieldValue<'a>,
 }
 
+impl Field<'_> {
+    /// Append the tag and value of the field, see [`Message::encode`].
+    pub(crate) fn encode_into(&self, out: &mut Vec<u8>) {
+        encode_tag(self.number, self.value.wire_type(), out);
+        match &self.value {
+            FieldValue::Varint(v) => encode_var(*v as u64, out),
+            FieldValue::Fixed64(v) => out.extend_from_slice(&v.to_le_bytes()),
+            FieldValue::Fixed32(v) => out.extend_from_slice(&v.to_le_bytes()),
+            FieldValue::LengthDelimited(bytes) => {
+                encode_var(bytes.len() as u64, out);
+                out.extend_from_slice(bytes);
+            }
+            FieldValue::Message(msg) => {
+                let nested = msg.encode();
+                encode_var(nested.len() as u64, out);
+                out.extend_from_slice(&nested);
+            }
+            FieldValue::Invalid(_, rest) | FieldValue::Incomplete(_, rest) => {
+                out.extend_from_slice(rest)
+            }
+        }
+    }
+}
+
 /// Decoded protocol buffer value.
 ///
 ///
 /// The wire type allows the decoder to tell how large an unknown value is. This allows the
 /// unknown value to be skipped and decoding can continue from the next value.
 ///
+/// The enum is non-exhaustive: values built from JSON added [`FieldValue::Message`], and other
+/// representations may follow, so matches need a wildcard arm.
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
+#[non_exhaustive]
 pub enum FieldValue<'a> {
     /// Varint (wire type = 0).
     Varint(u128),
@@ -50,23 +179,66 @@ pub enum FieldValue<'a> {
     /// type will result in the remaining bytes to be consumed from the current variable length
     /// stream as it is imposible to tell how large such invalid value is.
     ///
-    /// The decoding will continue after the current variable length value.
+    /// The decoding will continue after the current variable length value. The wire type
+    /// number is kept, e.g. for [`ParseError::InvalidWireType`](crate::ParseError).
     Invalid(u8, &'a [u8]),
 
     /// Value which was incomplete due to missing bytes in the payload.
+    ///
+    /// The remaining bytes of the payload are consumed. They hold everything after the tag,
+    /// including the length prefix of a length-delimited value, so that re-encoding the field
+    /// gives back the original bytes. Up to version 0.1.1 the length prefix of a truncated
+    /// length-delimited value was not included.
     Incomplete(WireType, &'a [u8]),
+
+    /// Nested message (wire type = 2).
+    ///
+    /// Never produced by decoding, which keeps nested messages as `LengthDelimited` bytes. It is
+    /// built by [`Message::from_json`] for objects that have not been serialized.
+    Message(Message<'a>),
 }
 
 impl<'a> FieldValue<'a> {
+    /// Convert the JSON value of the field with the given key.
+    fn from_json(key: &str, value: &'a Value) -> Result<Self, FromJsonError> {
+        let value = match value {
+            Value::Bool(b) => FieldValue::Varint(*b as u128),
+            Value::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+                (Some(v), _, _) => FieldValue::Varint(v as u128),
+                (None, Some(v), _) => FieldValue::Varint(v as u64 as u128),
+                (None, None, Some(v)) => FieldValue::Fixed64(v.to_bits()),
+                (None, None, None) => return Err(FromJsonError::UnsupportedValue(key.to_string())),
+            },
+            Value::String(s) => FieldValue::LengthDelimited(s.as_bytes()),
+            Value::Object(map) => FieldValue::Message(Message::from_json_map(map)?),
+            Value::Null | Value::Array(_) => {
+                return Err(FromJsonError::UnsupportedValue(key.to_string()));
+            }
+        };
+        Ok(value)
+    }
+
+    /// Decode a value of the given wire type, advancing `data` past it.
+    ///
+    /// Invalid and incomplete values consume all remaining bytes.
     pub fn decode(data: &mut &'a [u8], wire_type: WireType) -> Self {
+        Self::decode_with(data, wire_type, decode_var)
+    }
+
+    /// Decode a value like [`FieldValue::decode`], reading varints and lengths with `decode_var`.
+    pub(crate) fn decode_with(
+        data: &mut &'a [u8],
+        wire_type: WireType,
+        decode_var: VarintDecoder,
+    ) -> Self {
         match wire_type {
             WireType::Varint => match decode_var(data) {
                 Ok(v) => FieldValue::Varint(v as u128),
-                Err(_) => FieldValue::Incomplete(wire_type, *data),
+                Err(_) => FieldValue::Incomplete(wire_type, mem::take(data)),
             },
             WireType::Fixed64 => {
                 if data.len() < 8 {
-                    FieldValue::Incomplete(wire_type, *data)
+                    FieldValue::Incomplete(wire_type, mem::take(data))
                 } else {
                     let (num_bytes, rest) = data.split_at(8);
                     *data = rest;
@@ -75,22 +247,26 @@ impl<'a> FieldValue<'a> {
                     FieldValue::Fixed64(u64::from_le_bytes(arr))
                 }
             }
-            WireType::LengthDelimited => match decode_var(data) {
-                Ok(len) => {
-                    let len = len as usize;
-                    if data.len() < len {
-                        FieldValue::Incomplete(wire_type, *data)
-                    } else {
-                        let (bytes, rest) = data.split_at(len);
-                        *data = rest;
-                        FieldValue::LengthDelimited(bytes)
+            WireType::LengthDelimited => {
+                let start = *data;
+                match decode_var(data) {
+                    Ok(len) => {
+                        let len = len as usize;
+                        if data.len() < len {
+                            *data = &[];
+                            FieldValue::Incomplete(wire_type, start)
+                        } else {
+                            let (bytes, rest) = data.split_at(len);
+                            *data = rest;
+                            FieldValue::LengthDelimited(bytes)
+                        }
                     }
+                    Err(_) => FieldValue::Incomplete(wire_type, mem::take(data)),
                 }
-                Err(_) => FieldValue::Incomplete(wire_type, *data),
-            },
+            }
             WireType::Fixed32 => {
                 if data.len() < 4 {
-                    FieldValue::Incomplete(wire_type, *data)
+                    FieldValue::Incomplete(wire_type, mem::take(data))
                 } else {
                     let (num_bytes, rest) = data.split_at(4);
                     *data = rest;
@@ -99,11 +275,164 @@ impl<'a> FieldValue<'a> {
                     FieldValue::Fixed32(u32::from_le_bytes(arr))
                 }
             }
-            WireType::Invalid(wt) => FieldValue::Invalid(wt, *data),
+            WireType::Invalid(wt) => FieldValue::Invalid(wt, mem::take(data)),
+        }
+    }
+
+    /// Wire type the value was decoded from.
+    pub fn wire_type(&self) -> WireType {
+        match self {
+            FieldValue::Varint(_) => WireType::Varint,
+            FieldValue::Fixed64(_) => WireType::Fixed64,
+            FieldValue::LengthDelimited(_) | FieldValue::Message(_) => WireType::LengthDelimited,
+            FieldValue::Fixed32(_) => WireType::Fixed32,
+            FieldValue::Invalid(wt, _) => WireType::Invalid(*wt),
+            FieldValue::Incomplete(wt, _) => *wt,
         }
     }
 }
 
+impl FieldValue<'_> {
+    /// Error for a value that can't be converted to the requested type.
+    fn mismatch(&self) -> FieldValueError {
+        match self {
+            FieldValue::Incomplete(_, _) => FieldValueError::Incomplete,
+            value => FieldValueError::UnexpectedWireType(value.wire_type()),
+        }
+    }
+
+    /// The varint value, if it fits in 64 bits.
+    fn varint(&self) -> Result<u64, FieldValueError> {
+        match self {
+            FieldValue::Varint(v) => u64::try_from(*v).map_err(|_| FieldValueError::OutOfRange),
+            value => Err(value.mismatch()),
+        }
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for u64 {
+    type Error = FieldValueError;
+
+    /// Decode a varint, `fixed64` or `fixed32` value as unsigned.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        match value {
+            FieldValue::Fixed64(v) => Ok(v),
+            FieldValue::Fixed32(v) => Ok(v as u64),
+            value => value.varint(),
+        }
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for i64 {
+    type Error = FieldValueError;
+
+    /// Decode an `int64` varint, `sfixed64` or `sfixed32` value.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        match value {
+            FieldValue::Fixed64(v) => Ok(v as i64),
+            FieldValue::Fixed32(v) => Ok(v as i32 as i64),
+            value => value.varint().map(|v| v as i64),
+        }
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for u32 {
+    type Error = FieldValueError;
+
+    /// Decode a `uint32` varint or a `fixed32` value, rejecting values beyond 32 bits.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        let v = u64::try_from(value)?;
+        u32::try_from(v).map_err(|_| FieldValueError::OutOfRange)
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for i32 {
+    type Error = FieldValueError;
+
+    /// Decode an `int32` varint or an `sfixed32` value, rejecting values beyond 32 bits.
+    ///
+    /// Negative `int32` values are sign-extended to 64 bits on the wire, so they decode as well.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        let v = i64::try_from(value)?;
+        i32::try_from(v).map_err(|_| FieldValueError::OutOfRange)
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for f64 {
+    type Error = FieldValueError;
+
+    /// Decode a `double` or `float` value.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        match value {
+            FieldValue::Fixed64(v) => Ok(f64::from_bits(v)),
+            FieldValue::Fixed32(v) => Ok(f32::from_bits(v).into()),
+            value => Err(value.mismatch()),
+        }
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for f32 {
+    type Error = FieldValueError;
+
+    /// Decode a `float` value.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        match value {
+            FieldValue::Fixed32(v) => Ok(f32::from_bits(v)),
+            value => Err(value.mismatch()),
+        }
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for bool {
+    type Error = FieldValueError;
+
+    /// Decode a `bool` varint, any non-zero value being `true`.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        value.varint().map(|v| v != 0)
+    }
+}
+
+impl<'a> TryFrom<FieldValue<'a>> for &'a [u8] {
+    type Error = FieldValueError;
+
+    /// Borrow the bytes of a length-delimited value.
+    fn try_from(value: FieldValue<'a>) -> Result<Self, Self::Error> {
+        match value {
+            FieldValue::LengthDelimited(bytes) => Ok(bytes),
+            FieldValue::Message(_) => Err(FieldValueError::Message),
+            value => Err(value.mismatch()),
+        }
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for Vec<u8> {
+    type Error = FieldValueError;
+
+    /// Copy the bytes of a length-delimited value.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        <&[u8]>::try_from(value).map(<[u8]>::to_vec)
+    }
+}
+
+impl<'a> TryFrom<FieldValue<'a>> for &'a str {
+    type Error = FieldValueError;
+
+    /// Borrow a length-delimited value holding a UTF-8 string.
+    fn try_from(value: FieldValue<'a>) -> Result<Self, Self::Error> {
+        let bytes = <&[u8]>::try_from(value)?;
+        std::str::from_utf8(bytes).map_err(|_| FieldValueError::InvalidUtf8)
+    }
+}
+
+impl TryFrom<FieldValue<'_>> for String {
+    type Error = FieldValueError;
+
+    /// Copy a length-delimited value holding a UTF-8 string.
+    fn try_from(value: FieldValue<'_>) -> Result<Self, Self::Error> {
+        <&str>::try_from(value).map(str::to_string)
+    }
+}
+
 /// Protocol buffer wire types.
 #[derive(Debug, PartialEq, Clone, Eq, Copy, Hash)]
 #[repr(u8)]
@@ -120,7 +449,10 @@ pub enum WireType {
     /// 32-bit (5)
     Fixed32 = 5,
 
-    /// Invalid wire type
+    /// Invalid wire type, holding its number.
+    ///
+    /// Wire types 6 and 7 are not defined by protobuf. The deprecated group wire types 3 and 4
+    /// are not decoded either and are reported the same way.
     Invalid(u8),
 }
 
@@ -135,3 +467,265 @@ impl From<u8> for WireType {
         }
     }
 }
+
+impl From<WireType> for u8 {
+    fn from(wire_type: WireType) -> Self {
+        match wire_type {
+            WireType::Varint => 0,
+            WireType::Fixed64 => 1,
+            WireType::LengthDelimited => 2,
+            WireType::Fixed32 => 5,
+            WireType::Invalid(wt) => wt,
+        }
+    }
+}
+
+/// Append the tag of a field.
+pub(crate) fn encode_tag(number: u64, wire_type: WireType, out: &mut Vec<u8>) {
+    encode_var(number << 3 | u8::from(wire_type) as u64, out);
+}
+
+impl fmt::Display for WireType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireType::Varint => f.write_str("varint"),
+            WireType::Fixed64 => f.write_str("fixed64"),
+            WireType::LengthDelimited => f.write_str("length-delimited"),
+            WireType::Fixed32 => f.write_str("fixed32"),
+            WireType::Invalid(wt) => write!(f, "invalid wire type {wt}"),
+        }
+    }
+}
+
+impl WireType {
+    /// Convert a wire type number, rejecting unknown ones.
+    ///
+    /// Unlike `From<u8>`, which maps unknown numbers to [`WireType::Invalid`], this returns an
+    /// error for them.
+    pub fn try_from(value: u8) -> Result<Self, InvalidWireType> {
+        match WireType::from(value) {
+            WireType::Invalid(other) => Err(InvalidWireType(other)),
+            wire_type => Ok(wire_type),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_wire_type_try_from() {
+        assert_eq!(WireType::try_from(0), Ok(WireType::Varint));
+        assert_eq!(WireType::try_from(1), Ok(WireType::Fixed64));
+        assert_eq!(WireType::try_from(2), Ok(WireType::LengthDelimited));
+        assert_eq!(WireType::try_from(3), Err(InvalidWireType(3)));
+        assert_eq!(WireType::try_from(4), Err(InvalidWireType(4)));
+        assert_eq!(WireType::try_from(5), Ok(WireType::Fixed32));
+        assert_eq!(WireType::try_from(6), Err(InvalidWireType(6)));
+        assert_eq!(WireType::try_from(7), Err(InvalidWireType(7)));
+        assert_eq!(WireType::from(7), WireType::Invalid(7));
+    }
+
+    #[test]
+    fn test_field_value_try_from() {
+        assert_eq!(u64::try_from(FieldValue::Varint(150)), Ok(150));
+        assert_eq!(u64::try_from(FieldValue::Fixed32(28)), Ok(28));
+        assert_eq!(i64::try_from(FieldValue::Varint(u64::MAX as u128)), Ok(-1));
+        assert_eq!(i64::try_from(FieldValue::Fixed32(u32::MAX)), Ok(-1));
+        assert_eq!(
+            f64::try_from(FieldValue::Fixed64(1.5f64.to_bits())),
+            Ok(1.5)
+        );
+        assert_eq!(
+            f32::try_from(FieldValue::Fixed32(1.5f32.to_bits())),
+            Ok(1.5)
+        );
+        assert_eq!(u32::try_from(FieldValue::Varint(150)), Ok(150));
+        assert_eq!(u32::try_from(FieldValue::Fixed32(u32::MAX)), Ok(u32::MAX));
+        assert_eq!(i32::try_from(FieldValue::Varint(u64::MAX as u128)), Ok(-1));
+        assert_eq!(
+            i32::try_from(FieldValue::Varint(i32::MIN as u64 as u128)),
+            Ok(i32::MIN)
+        );
+        assert_eq!(i32::try_from(FieldValue::Fixed32(u32::MAX)), Ok(-1));
+        assert_eq!(bool::try_from(FieldValue::Varint(1)), Ok(true));
+        assert_eq!(
+            String::try_from(FieldValue::LengthDelimited(b"You")),
+            Ok("You".to_string())
+        );
+        assert_eq!(
+            Vec::<u8>::try_from(FieldValue::LengthDelimited(&[0xff])),
+            Ok(vec![0xff])
+        );
+
+        assert_eq!(
+            u64::try_from(FieldValue::Varint(1 << 64)),
+            Err(FieldValueError::OutOfRange)
+        );
+        assert_eq!(
+            u32::try_from(FieldValue::Varint(1 << 32)),
+            Err(FieldValueError::OutOfRange)
+        );
+        assert_eq!(
+            u32::try_from(FieldValue::Fixed64(1 << 32)),
+            Err(FieldValueError::OutOfRange)
+        );
+        assert_eq!(
+            i32::try_from(FieldValue::Varint(1 << 31)),
+            Err(FieldValueError::OutOfRange)
+        );
+        assert_eq!(
+            i32::try_from(FieldValue::Varint(i32::MIN as i64 as u64 as u128 - 1)),
+            Err(FieldValueError::OutOfRange)
+        );
+        assert_eq!(
+            i32::try_from(FieldValue::LengthDelimited(b"1")),
+            Err(FieldValueError::UnexpectedWireType(
+                WireType::LengthDelimited
+            ))
+        );
+        assert_eq!(
+            u64::try_from(FieldValue::LengthDelimited(b"1")),
+            Err(FieldValueError::UnexpectedWireType(
+                WireType::LengthDelimited
+            ))
+        );
+        assert_eq!(
+            f32::try_from(FieldValue::Fixed64(0)),
+            Err(FieldValueError::UnexpectedWireType(WireType::Fixed64))
+        );
+        assert_eq!(
+            String::try_from(FieldValue::LengthDelimited(&[0xff])),
+            Err(FieldValueError::InvalidUtf8)
+        );
+        assert_eq!(
+            <&str>::try_from(FieldValue::Incomplete(WireType::LengthDelimited, b"ab")),
+            Err(FieldValueError::Incomplete)
+        );
+        assert_eq!(
+            Vec::<u8>::try_from(FieldValue::Message(Message::default())),
+            Err(FieldValueError::Message)
+        );
+    }
+
+    #[test]
+    fn test_from_json() {
+        let value = json!({
+            "1": 28,
+            "2": "You",
+            "4": [-1, true],
+            "5": {"1": "abc123"},
+            "10": 1.5
+        });
+        let msg = Message::from_json(&value).unwrap();
+        let expected = Message {
+            fields: vec![
+                Field {
+                    number: 1,
+                    value: FieldValue::Varint(28),
+                },
+                Field {
+                    number: 2,
+                    value: FieldValue::LengthDelimited(b"You"),
+                },
+                Field {
+                    number: 4,
+                    value: FieldValue::Varint(u64::MAX as u128),
+                },
+                Field {
+                    number: 4,
+                    value: FieldValue::Varint(1),
+                },
+                Field {
+                    number: 5,
+                    value: FieldValue::Message(Message {
+                        fields: vec![Field {
+                            number: 1,
+                            value: FieldValue::LengthDelimited(b"abc123"),
+                        }],
+                        garbage: None,
+                    }),
+                },
+                Field {
+                    number: 10,
+                    value: FieldValue::Fixed64(1.5f64.to_bits()),
+                },
+            ],
+            garbage: None,
+        };
+        assert_eq!(msg, expected);
+    }
+
+    #[test]
+    fn test_decode_invalid_wire_types() {
+        for wire_type in [3, 4, 6, 7] {
+            let mut data = &[0x01, 0x02][..];
+            let value = FieldValue::decode(&mut data, WireType::from(wire_type));
+            assert_eq!(value, FieldValue::Invalid(wire_type, &[0x01, 0x02]));
+            assert_eq!(value.wire_type(), WireType::Invalid(wire_type));
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let parser = Parser::new();
+        for data in [
+            &hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200")[..],
+            &hex!("0801 0f00")[..],
+            &hex!("0801 ff")[..],
+            &hex!("0801 120559")[..],
+            &hex!("0801 10ff")[..],
+        ] {
+            assert_eq!(parser.parse_once(data).encode(), data);
+        }
+        // Overlong varints are shortened.
+        assert_eq!(parser.parse_once(&hex!("088100")).encode(), hex!("0801"));
+
+        let value = json!({"1": 1, "5": {"1": "abc"}});
+        let msg = Message::from_json(&value).unwrap();
+        assert_eq!(msg.encode(), hex!("0801 2a05 0a03616263"));
+    }
+
+    #[test]
+    fn test_nested_messages() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new();
+        let msg = parser.parse_once(&data);
+        let nested: Vec<_> = msg.nested_messages(&parser).collect();
+        let expected = Message {
+            fields: vec![
+                Field {
+                    number: 1,
+                    value: FieldValue::LengthDelimited(b"abc123"),
+                },
+                Field {
+                    number: 2,
+                    value: FieldValue::LengthDelimited(b""),
+                },
+            ],
+            garbage: None,
+        };
+        assert_eq!(nested, vec![(5, expected)]);
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        assert_eq!(
+            Message::from_json(&json!([1])),
+            Err(FromJsonError::NotAnObject)
+        );
+        assert_eq!(
+            Message::from_json(&json!({"a": 1})),
+            Err(FromJsonError::InvalidFieldNumber("a".to_string()))
+        );
+        assert_eq!(
+            Message::from_json(&json!({"1": {"2": null}})),
+            Err(FromJsonError::UnsupportedValue("2".to_string()))
+        );
+    }
+}
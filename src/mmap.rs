@@ -0,0 +1,41 @@
+//! Parsing of memory-mapped files.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+use serde_json::Value;
+
+use crate::Parser;
+
+impl Parser {
+    /// Parse a protobuf message from a file, mapping it into memory instead of reading it.
+    ///
+    /// The JSON output owns all its data, so the mapping is released before this returns. An
+    /// empty file is not a message and yields `Ok(None)`.
+    ///
+    /// # Safety
+    ///
+    /// The file is mapped with [`Mmap::map`], and the same rules apply: the file must not be
+    /// modified, truncated or resized, by this or any other process, until this returns.
+    /// Otherwise the bytes being parsed change underneath the parser, which is undefined
+    /// behavior, or reading them fails with a `SIGBUS` signal on Unix.
+    ///
+    /// ``` rust
+    /// use protobuf_to_json::Parser;
+    ///
+    /// let path = std::env::temp_dir().join("parse_mmap_doc.pb");
+    /// std::fs::write(&path, [0x08, 0x01]).unwrap();
+    /// // SAFETY: the file is private to this example and not modified while it is mapped.
+    /// let json = unsafe { Parser::new().parse_mmap(&path) }.unwrap();
+    /// assert_eq!(json, Some(serde_json::json!({"1": 1})));
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub unsafe fn parse_mmap(&self, path: impl AsRef<Path>) -> io::Result<Option<Value>> {
+        let file = File::open(path)?;
+        // SAFETY: upheld by the caller.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(self.parse(&map))
+    }
+}
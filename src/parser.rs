@@ -1,250 +1,4217 @@
 //! Protobuf parser.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::ops::Range;
+use std::panic::RefUnwindSafe;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
 
 use base64::prelude::*;
-use serde_json::{Map, Value, json};
+use serde_json::{Map, Value, json, map};
 
-use crate::{Field, FieldValue, Message, message::WireType, varint::decode_var};
+use crate::{
+    Field, FieldExplanation, FieldOutput, FieldPath, FieldType, FieldValue, Message, MissingFields,
+    NestedRejection, ParseError, TypedValue, UnknownBytesEncoding,
+    message::WireType,
+    varint::{VarintDecoder, decode_var, decode_var_msb_first, zigzag_decode},
+};
+
+/// Default of [`Parser::with_max_decompressed_len`].
+#[cfg(feature = "gzip")]
+const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 64 << 20;
 
 const RESERVED_FIELD_NUMBER: Range<u64> = 19000..20000;
 
-/// A protobuf parser that converts protobuf messages to JSON.
-#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
-pub struct Parser {
-    /// How to encode bytes fields when converting to JSON.
-    pub bytes_encoding: BytesEncoding,
-}
+/// Largest field number protobuf allows.
+pub(crate) const MAX_FIELD_NUMBER: u64 = (1 << 29) - 1;
+
+/// Parser with the default configuration, see [`default_parser`].
+static DEFAULT_PARSER: LazyLock<Parser> = LazyLock::new(Parser::new);
+
+/// Shared parser with the default configuration.
+///
+/// Saves constructing a [`Parser`] for one-off conversions.
+pub fn default_parser() -> &'static Parser {
+    &DEFAULT_PARSER
+}
+
+/// Parse a protobuf message with the default configuration and convert it to JSON.
+///
+/// Shorthand for [`Parser::parse`] on the [`default_parser`].
+///
+/// ``` rust
+/// use hex_literal::hex;
+/// use serde_json::json;
+///
+/// let json = protobuf_to_json::parse(&hex!("082a1203596f75"));
+/// assert_eq!(json, Some(json!({"1": 42, "2": "You"})));
+/// ```
+pub fn parse(data: &[u8]) -> Option<Value> {
+    default_parser().parse(data)
+}
+
+/// A protobuf parser that converts protobuf messages to JSON.
+///
+/// All parsing methods take `&self`, so a single parser can be shared across threads, e.g.
+/// behind an `Arc` or in a `static`. Field hints are stored behind an `Arc` as well, which keeps
+/// cloning a configured parser cheap.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Parser {
+    /// How to encode bytes fields when converting to JSON.
+    pub bytes_encoding: BytesEncoding,
+
+    /// How to render integer values.
+    int_radix: IntRadix,
+
+    /// Reject garbage, invalid and incomplete fields instead of skipping them.
+    strict: bool,
+
+    /// Only accept a first layer that consists of valid fields entirely.
+    require_full_parse: bool,
+
+    /// Reject a first layer with garbage or reserved field numbers.
+    top_level_rejection: bool,
+
+    /// Annotate values with alternative interpretations for analysis.
+    verbose: bool,
+
+    /// Emit floating point interpretations as shortest round-trip strings.
+    canonical_floats: bool,
+
+    /// How to render NaN and infinite floating point interpretations.
+    pub(crate) nonfinite_floats: NonFinite,
+
+    /// Wrap array elements with their field number and index in the verbose output.
+    array_element_tags: bool,
+
+    /// Include the hex of the wire bytes of each field in the verbose output.
+    raw_hex: bool,
+
+    /// Wrap every value with its type.
+    typed_json: bool,
+
+    /// Serialize object keys in numeric order.
+    pub(crate) numeric_key_order: bool,
+
+    /// Group the digits of integers in the text output.
+    pub(crate) digit_grouping: bool,
+
+    /// Emit single-element packed arrays as bare values.
+    unwrap_singletons: bool,
+
+    /// Which occurrences of a repeated field to keep.
+    duplicate_policy: DuplicatePolicy,
+
+    /// Key under which trailing garbage is emitted as hex.
+    garbage_field: Option<String>,
+
+    /// Key under which the encoded length of each message is emitted.
+    length_field: Option<String>,
+
+    /// Accept nested messages whose beginning only decodes into valid fields.
+    partial_nested: bool,
+
+    /// Reject nested candidates that don't decode into fields up to their last byte.
+    strict_nested: bool,
+
+    /// Decode varints with their 7-bit groups in most significant first order.
+    msb_first_varints: bool,
+
+    /// Transform applied to every decoded tag.
+    tag_transform: Option<TagTransform>,
+
+    /// Emit a flat object keyed by dotted paths.
+    flatten: bool,
+
+    /// Inline nested messages of a single field under a dotted key.
+    collapse_single_field: bool,
+
+    /// Nesting depth of the output beyond which messages are emitted as base64.
+    max_output_depth: Option<usize>,
+
+    /// Number of bytes of a string or bytes value beyond which it is truncated.
+    max_string_len: Option<usize>,
+
+    /// How to convert values that are both valid strings and valid messages.
+    ambiguity: Ambiguity,
+
+    /// How to render varints with a name in the enum map.
+    enum_render: EnumRender,
+
+    /// Maximum number of operations per parse.
+    op_budget: Option<u64>,
+
+    /// Length-delimited values shorter than this are never treated as nested messages.
+    min_nested_len: usize,
+
+    /// Maximum size of decompressed data.
+    #[cfg(feature = "gzip")]
+    pub(crate) max_decompressed_len: usize,
+
+    /// Per-field hints, shared between clones.
+    pub(crate) hints: Arc<Hints>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            bytes_encoding: BytesEncoding::default(),
+            int_radix: IntRadix::default(),
+            strict: false,
+            require_full_parse: false,
+            top_level_rejection: false,
+            verbose: false,
+            canonical_floats: false,
+            nonfinite_floats: NonFinite::default(),
+            array_element_tags: false,
+            raw_hex: false,
+            typed_json: false,
+            numeric_key_order: false,
+            digit_grouping: false,
+            unwrap_singletons: false,
+            duplicate_policy: DuplicatePolicy::default(),
+            garbage_field: None,
+            length_field: None,
+            partial_nested: false,
+            strict_nested: false,
+            msb_first_varints: false,
+            tag_transform: None,
+            flatten: false,
+            collapse_single_field: false,
+            max_output_depth: None,
+            max_string_len: None,
+            ambiguity: Ambiguity::default(),
+            enum_render: EnumRender::default(),
+            op_budget: None,
+            min_nested_len: 2,
+            #[cfg(feature = "gzip")]
+            max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+            hints: Arc::default(),
+        }
+    }
+}
+
+/// Per-field hints of a [`Parser`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Hints {
+    /// Fields hinted as packed repeated varints.
+    packed: BTreeSet<FieldPath>,
+
+    /// Element types of packed repeated fields.
+    packed_specs: BTreeMap<FieldPath, ElementType>,
+
+    /// Fields hinted as zigzag encoded (`sint32`/`sint64`).
+    zigzag: BTreeSet<FieldPath>,
+
+    /// Varint fields hinted as `int32`.
+    int32: BTreeSet<FieldPath>,
+
+    /// Decimal scale of fixed-point varint fields.
+    scales: BTreeMap<FieldPath, i32>,
+
+    /// Fields always emitted as JSON arrays.
+    repeated: BTreeSet<FieldPath>,
+
+    /// Length-delimited fields always parsed as nested messages.
+    nested: BTreeSet<FieldPath>,
+
+    /// Length-delimited fields never parsed as nested messages.
+    strings: BTreeSet<FieldPath>,
+
+    /// Length-delimited fields always emitted as bytes.
+    opaque: BTreeSet<FieldPath>,
+
+    /// Nested messages wrapping a single value in field 1.
+    wrappers: BTreeSet<FieldPath>,
+
+    /// Length-delimited fields holding IP addresses.
+    ip: BTreeSet<FieldPath>,
+
+    /// Length-delimited fields holding UUIDs.
+    uuid: BTreeSet<FieldPath>,
+
+    /// Length-delimited fields holding JSON text.
+    json: BTreeSet<FieldPath>,
+
+    /// Top-level fields to keep.
+    field_filter: Option<FieldFilter>,
+
+    /// Predicate on the converted values of fields to keep.
+    value_filter: Option<ValueFilter>,
+
+    /// Known field names, used as keys instead of field numbers.
+    names: BTreeMap<FieldPath, String>,
+
+    /// Top-level field groups presented as oneofs, by group name.
+    oneofs: Vec<(String, BTreeSet<u64>)>,
+
+    /// Enum value names of top-level varint fields.
+    enums: BTreeMap<u64, BTreeMap<i64, String>>,
+
+    /// Partial schema of names and types.
+    pub(crate) descriptor: Option<Arc<crate::Descriptor>>,
+
+    /// Schema for [`Parser::parse_to_protojson`].
+    #[cfg(feature = "schema")]
+    pub(crate) schema: Option<Arc<crate::Schema>>,
+}
+
+impl Parser {
+    /// Create a new parser.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new parser with the given bytes encoding method.
+    pub fn with_bytes_encoding(bytes_encoding: BytesEncoding) -> Self {
+        Self {
+            bytes_encoding,
+            ..Self::default()
+        }
+    }
+
+    /// Hint that the given length-delimited fields are packed repeated varints.
+    ///
+    /// A hinted field is emitted as a JSON array of its elements. If the blob doesn't end on a
+    /// varint boundary it is emitted as bytes instead.
+    pub fn with_packed_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .packed
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that a length-delimited field is a packed repeated field of the given element type.
+    ///
+    /// The field is emitted as a JSON array of typed elements, e.g. floats for
+    /// [`ElementType::Float`]. If the blob doesn't hold a whole number of elements it is emitted
+    /// as bytes instead. The spec replaces a hint of [`Parser::with_packed_fields`] for the
+    /// same field.
+    pub fn with_packed_field_spec(
+        mut self,
+        path: impl Into<FieldPath>,
+        element: ElementType,
+    ) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .packed_specs
+            .insert(path.into(), element);
+        self
+    }
+
+    /// Hint that the given varint fields are zigzag encoded (`sint32`/`sint64`).
+    ///
+    /// Combined with [`Parser::with_packed_fields`], each element of the packed array is
+    /// zigzag decoded.
+    pub fn with_zigzag_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .zigzag
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that the given fields hold wrapper messages such as `google.protobuf.StringValue`.
+    ///
+    /// A hinted nested message holding nothing but field 1 is replaced by the value of that
+    /// field, e.g. `{"1": "x"}` becomes `"x"`. Messages with other fields, or with field 1
+    /// found several times, are emitted as usual.
+    pub fn with_wrapper_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .wrappers
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that the given varint fields are `int32`.
+    ///
+    /// Negative `int32` values are sign-extended to 10-byte varints on the wire. The low 32 bits
+    /// of a hinted field are read as `i32`, so `-1` is emitted as `-1` instead of
+    /// `18446744073709551615`. Fields also hinted with [`Parser::with_zigzag_fields`] are
+    /// zigzag decoded instead.
+    pub fn with_int32_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .int32
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that the given varint fields hold fixed-point decimals with an implied scale.
+    ///
+    /// The value is divided by `10^scale` and emitted as a string, avoiding float imprecision,
+    /// e.g. `12345` with a scale of 2 becomes `"123.45"`. A negative scale multiplies instead.
+    /// Values are read as `int64`, or as `sint64` if the field is also hinted with
+    /// [`Parser::with_zigzag_fields`]. Scales beyond ±19, the number of digits of an `int64`, are
+    /// ignored.
+    pub fn with_scaled_fields(mut self, fields: &[(FieldPath, i32)]) -> Self {
+        Arc::make_mut(&mut self.hints).scales.extend(
+            fields
+                .iter()
+                .filter(|(_, scale)| (-19..=19).contains(scale))
+                .cloned(),
+        );
+        self
+    }
+
+    /// Render varint and fixed integer values in the given radix.
+    ///
+    /// Any radix other than [`IntRadix::Decimal`] emits integers as strings such as `"0x1c"`,
+    /// which also keeps integers above 2^53 exact for JSON consumers using doubles. Floating
+    /// point interpretations of the verbose output are not affected.
+    pub fn with_int_radix(mut self, int_radix: IntRadix) -> Self {
+        self.int_radix = int_radix;
+        self
+    }
+
+    /// Only accept well-formed protobuf.
+    ///
+    /// By default the parser is lenient: trailing garbage is dropped and the first layer keeps
+    /// the fields before an invalid or incomplete field. In strict mode [`Parser::try_parse`]
+    /// returns an error for any of them instead.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Choose which occurrences of a field found several times in a message are kept.
+    ///
+    /// The policy applies at every nesting level. Fields hinted with
+    /// [`Parser::with_repeated_fields`] always keep all occurrences. Defaults to
+    /// [`DuplicatePolicy::Array`].
+    pub fn with_duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    /// Emit the garbage at the end of a message as hex under the given key, e.g.
+    /// `"__garbage__": "ffff"`, instead of dropping it.
+    ///
+    /// Garbage is data that doesn't start with a valid tag, see [`Message::garbage`]. It is kept
+    /// at every nesting level. Disabled by default.
+    pub fn with_garbage_field(mut self, key: Option<&str>) -> Self {
+        self.garbage_field = key.map(str::to_string);
+        self
+    }
+
+    /// Emit the number of bytes of each message under the given key, e.g. `"__len__": 28`.
+    ///
+    /// The length covers the whole encoded message, nested messages and garbage included, which
+    /// helps to check the framing of the input. Disabled by default.
+    pub fn with_length_field(mut self, key: Option<&str>) -> Self {
+        self.length_field = key.map(str::to_string);
+        self
+    }
+
+    /// Accept a nested message whose valid fields are followed by an invalid field or garbage,
+    /// keeping the valid fields.
+    ///
+    /// The valid fields must cover at least half of the value. This recovers the structure of
+    /// slightly corrupt data, but also makes bytes and strings more likely to be mistaken for
+    /// messages, as many short byte sequences start with something that decodes as a field.
+    /// The dropped rest is emitted with [`Parser::with_garbage_field`]. Disabled by default.
+    pub fn with_partial_nested(mut self, partial_nested: bool) -> Self {
+        self.partial_nested = partial_nested;
+        self
+    }
+
+    /// Only take a length-delimited value as a nested message if its fields consume all its
+    /// bytes.
+    ///
+    /// By default a candidate that is not valid UTF-8 is accepted even if it ends with garbage,
+    /// which is dropped. In strict mode such a value is emitted as bytes, so binary data that
+    /// happens to start like a message is not mistaken for one. Takes precedence over
+    /// [`Parser::with_partial_nested`]. Disabled by default.
+    pub fn with_strict_nested(mut self, strict_nested: bool) -> Self {
+        self.strict_nested = strict_nested;
+        self
+    }
+
+    /// Decode all varints, including tags and lengths, with their 7-bit groups in most
+    /// significant first order, see [`decode_var_msb_first`](crate::decode_var_msb_first).
+    ///
+    /// This is not valid protobuf: it is meant for analyzing data from non-standard or
+    /// obfuscated serializers that order the groups the other way. Varints of a single byte,
+    /// such as the tags of fields 1 to 15, decode the same either way. Disabled by default.
+    pub fn with_msb_first_varints(mut self, msb_first_varints: bool) -> Self {
+        self.msb_first_varints = msb_first_varints;
+        self
+    }
+
+    /// Apply a transform to every decoded tag, e.g. to undo a known XOR or offset of the tags of
+    /// obfuscated data.
+    ///
+    /// The transform runs on the whole tag varint, before it is split into the field number and
+    /// the wire type, so it may change both. The transform gets every tag varint of the data,
+    /// including those of garbage and of candidate nested messages, so it must not panic on any
+    /// value, e.g. use wrapping arithmetic. Disabled by default.
+    ///
+    /// ``` rust
+    /// use protobuf_to_json::Parser;
+    /// use serde_json::json;
+    ///
+    /// // Tags offset by 0x10, so field 1 is tagged as field 3.
+    /// let parser = Parser::new().with_tag_transform(|tag| tag.wrapping_sub(0x10));
+    /// assert_eq!(parser.parse(&[0x18, 0x2a]), Some(json!({"1": 42})));
+    /// ```
+    pub fn with_tag_transform(
+        mut self,
+        transform: impl Fn(u64) -> u64 + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Self {
+        self.tag_transform = Some(Callback(Arc::new(transform)));
+        self
+    }
+
+    /// Emit a flat object whose keys are dotted paths, e.g. `{"5.1": "abc123"}` instead of
+    /// `{"5": {"1": "abc123"}}`.
+    ///
+    /// Array elements are keyed by their index, e.g. `"9.0"` and `"9.1"`. Field numbers never
+    /// contain dots, but names from [`Parser::with_field_names`] might: dots in keys are escaped
+    /// as `\.` and backslashes as `\\`, so splitting a path on unescaped dots is unambiguous.
+    pub fn with_flatten(mut self, flatten: bool) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    /// Inline nested messages that hold a single field under a dotted key, e.g. `{"5.1": 1}`
+    /// instead of `{"5": {"1": 1}}`.
+    ///
+    /// Chains of such messages are collapsed into one key, e.g. `"1.2.3"`, with the names escaped
+    /// as with [`Parser::with_flatten`], which takes precedence. Only nested messages are
+    /// collapsed, not other objects such as embedded JSON, and neither are repeated fields or
+    /// typed and raw hex output. This removes the noise of deeply
+    /// wrapped values, but the output no longer tells a message with a single field from one
+    /// that only happens to have a single field set, so the same field may have a different
+    /// shape in two messages. Disabled by default.
+    pub fn with_collapse_single_field(mut self, collapse_single_field: bool) -> Self {
+        self.collapse_single_field = collapse_single_field;
+        self
+    }
+
+    /// Limit the nesting of objects in the output to `depth` levels, the top-level object being
+    /// the first.
+    ///
+    /// Nested messages that would go deeper are emitted as base64 of their bytes instead, URL-safe
+    /// with [`BytesEncoding::Base64Url`], which keeps the output of very deep messages
+    /// manageable, e.g. for tree views. Strings and bytes are unaffected. The depth counts nested
+    /// messages, before [`Parser::with_flatten`] is applied.
+    pub fn with_max_output_depth(mut self, depth: usize) -> Self {
+        self.max_output_depth = Some(depth);
+        self
+    }
+
+    /// Truncate string and bytes values longer than `len` bytes, e.g. for logging.
+    ///
+    /// The first `len` bytes are rendered in the configured [`BytesEncoding`] and followed by a
+    /// marker of the number of dropped bytes, e.g. `"abc…(+1024 bytes)"`. Strings are cut at a
+    /// character boundary. [`BytesEncoding::ByteArray`] values are not truncated, since the marker
+    /// would make them arrays of mixed types. Truncated output can't be converted back to the
+    /// original bytes.
+    pub fn with_max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = Some(len);
+        self
+    }
+
+    /// Choose how to convert length-delimited values that are valid UTF-8 and look like a
+    /// nested message at the same time.
+    ///
+    /// Such values are guessed to be messages by default. [`Ambiguity::Error`] makes
+    /// [`Parser::try_parse`] fail with the path of the first ambiguous field instead, so it can be
+    /// hinted with [`Parser::force_nested`] or [`Parser::with_string_fields`]. Hinted and
+    /// declared fields are never ambiguous.
+    pub fn with_ambiguity(mut self, ambiguity: Ambiguity) -> Self {
+        self.ambiguity = ambiguity;
+        self
+    }
+
+    /// Hint that the given fields are repeated.
+    ///
+    /// A hinted field is always emitted as a JSON array, even if it occurs only once. Without
+    /// the hint a single occurrence stays a bare value.
+    pub fn with_repeated_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .repeated
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Emit a packed field holding a single element as a bare value instead of an array.
+    ///
+    /// This drops the distinction between a packed repeated field with one element and a
+    /// singular field, so the output can't tell them apart anymore. Fields occurring more than
+    /// once still become arrays, and [`Parser::with_repeated_fields`] takes precedence.
+    pub fn with_unwrap_singletons(mut self, unwrap_singletons: bool) -> Self {
+        self.unwrap_singletons = unwrap_singletons;
+        self
+    }
+
+    /// Always parse the given length-delimited fields as nested messages.
+    ///
+    /// This skips the heuristics that decide whether a value is a message, e.g. for a message
+    /// that happens to look like text. A value that doesn't decode into valid fields entirely
+    /// falls back to the usual string or bytes output.
+    pub fn force_nested(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .nested
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Always emit the given length-delimited fields as bytes, never as nested messages or
+    /// strings.
+    ///
+    /// This is the inverse of [`Parser::force_nested`], for opaque tokens or signatures. In
+    /// [`BytesEncoding::Auto`] mode the bytes are base64 encoded even if they are valid UTF-8.
+    pub fn with_opaque_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .opaque
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that the given length-delimited fields are strings or bytes, never nested messages.
+    ///
+    /// Text can happen to decode as a message, e.g. a string holding `"\n\x03abc\n\x03def"`
+    /// looks like a message with a repeated field 1 of two strings. Hinted fields skip that
+    /// guess and are emitted in the configured [`BytesEncoding`]. Combine with
+    /// [`Parser::with_repeated_fields`] for `repeated string` fields to always get arrays.
+    pub fn with_string_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .strings
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that the given length-delimited fields hold IP addresses.
+    ///
+    /// A hinted value of 4 bytes is rendered as an IPv4 address, e.g. `"192.168.0.1"`, and one of
+    /// 16 bytes as an IPv6 address, e.g. `"2001:db8::1"`. Values of other lengths are emitted as
+    /// bytes in the configured encoding.
+    pub fn with_ip_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .ip
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that the given length-delimited fields hold UUIDs.
+    ///
+    /// A hinted value of 16 bytes is rendered in the canonical form, e.g.
+    /// `"550e8400-e29b-41d4-a716-446655440000"`. Values of other lengths are emitted as bytes in
+    /// the configured encoding.
+    pub fn with_uuid_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .uuid
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Hint that the given length-delimited fields hold JSON text.
+    ///
+    /// A hinted value that parses as JSON is inlined into the output instead of being emitted as
+    /// a string. Any other value is emitted as usual.
+    pub fn with_json_fields(mut self, paths: &[FieldPath]) -> Self {
+        Arc::make_mut(&mut self.hints)
+            .json
+            .extend(paths.iter().cloned());
+        self
+    }
+
+    /// Only emit the top-level fields accepted by the filter.
+    ///
+    /// Filtered-out fields are still decoded to stay in sync with the input, but are left out of
+    /// the JSON output.
+    pub fn with_field_filter(mut self, filter: FieldFilter) -> Self {
+        Arc::make_mut(&mut self.hints).field_filter = Some(filter);
+        self
+    }
+
+    /// Only emit the fields whose converted value is accepted by the predicate, e.g. to omit
+    /// zero or empty values.
+    ///
+    /// The predicate gets the path of the field and its JSON value, after nested messages were
+    /// filtered themselves, and before repeated occurrences are merged into an array. Each
+    /// element of a packed repeated field is checked on its own.
+    ///
+    /// ``` rust
+    /// use protobuf_to_json::Parser;
+    /// use serde_json::json;
+    ///
+    /// let parser = Parser::new().with_value_filter(|_, value| value != &json!(0));
+    /// assert_eq!(parser.parse(&[0x08, 0x00, 0x10, 0x01]), Some(json!({"2": 1})));
+    /// ```
+    pub fn with_value_filter(
+        mut self,
+        filter: impl Fn(&FieldPath, &Value) -> bool + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.hints).value_filter = Some(Callback(Arc::new(filter)));
+        self
+    }
+
+    /// Use the given names as keys of the fields instead of their numbers.
+    ///
+    /// Fields without a name keep their numeric key. Repeated occurrences of a named field are
+    /// merged into an array under the name.
+    ///
+    /// Several fields may be given the same name. Their values are then merged into an array
+    /// under that name in the order they occur in the message, the way repeated occurrences of a
+    /// single field are, and [`Parser::with_duplicate_policy`] applies to them the same way.
+    pub fn with_field_names(mut self, names: &[(FieldPath, &str)]) -> Self {
+        Arc::make_mut(&mut self.hints).names.extend(
+            names
+                .iter()
+                .map(|(path, name)| (path.clone(), name.to_string())),
+        );
+        self
+    }
+
+    /// Alias of [`Parser::with_field_names`] taking an owned map, e.g. one loaded from a
+    /// configuration file. Fields mapped to the same name are merged the same way.
+    pub fn with_rename_map(mut self, names: HashMap<FieldPath, String>) -> Self {
+        Arc::make_mut(&mut self.hints).names.extend(names);
+        self
+    }
+
+    /// Present groups of top-level fields as oneofs.
+    ///
+    /// Each entry names a group and lists its field numbers. The member present in the message is
+    /// emitted under the group name as `{"field": 3, "value": ...}` instead of its field number.
+    /// Exclusivity is not enforced: if several members occur, the last one in the data is
+    /// presented, like a protobuf parser would keep it, and the others keep their field numbers.
+    pub fn with_oneof_groups(mut self, groups: &[(&str, &[u64])]) -> Self {
+        Arc::make_mut(&mut self.hints).oneofs.extend(
+            groups
+                .iter()
+                .map(|(name, fields)| (name.to_string(), fields.iter().copied().collect())),
+        );
+        self
+    }
+
+    /// Render top-level varint fields as enum value names.
+    ///
+    /// Each entry maps a field number to the names of its values. Values without a name are
+    /// emitted as numbers as usual. [`Parser::with_enum_render`] keeps the number as well.
+    pub fn with_enum_map(mut self, enums: &[(u64, HashMap<i64, String>)]) -> Self {
+        let hints = Arc::make_mut(&mut self.hints);
+        for (field, names) in enums {
+            hints
+                .enums
+                .entry(*field)
+                .or_default()
+                .extend(names.iter().map(|(v, name)| (*v, name.clone())));
+        }
+        self
+    }
+
+    /// Set how varints with a name in the enum map are rendered, see [`EnumRender`].
+    pub fn with_enum_render(mut self, enum_render: EnumRender) -> Self {
+        self.enum_render = enum_render;
+        self
+    }
+
+    /// Only treat the data as a message if all of it decodes into valid fields.
+    ///
+    /// By default random bytes often yield a partial message of a field or two. With this option
+    /// set, [`Parser::parse`] returns `None` for any data with garbage, invalid or incomplete
+    /// fields, which avoids false positives when sniffing content.
+    pub fn with_require_full_parse(mut self, require_full_parse: bool) -> Self {
+        self.require_full_parse = require_full_parse;
+        self
+    }
+
+    /// Reject the first layer on the same signals that reject nested messages.
+    ///
+    /// Nested candidates with trailing garbage or reserved field numbers (19000 to 19999) are not
+    /// treated as messages. The first layer is accepted regardless by default; with this option
+    /// set such data is treated as non-protobuf and [`Parser::parse`] returns `None`.
+    pub fn with_top_level_rejection(mut self, top_level_rejection: bool) -> Self {
+        self.top_level_rejection = top_level_rejection;
+        self
+    }
+
+    /// Enable the verbose analysis output.
+    ///
+    /// Fixed32 and fixed64 values are emitted with both their integer and floating point
+    /// interpretation, e.g. `{"uint": 1069547520, "float": 1.5}`, at every nesting level. Fixed64
+    /// values are also split into two fixed32 halves, low first, under `"two_u32"`. Bytes
+    /// starting with gzip, zlib or zstd magic bytes are noted as compressed, e.g.
+    /// `{"encoding": "base64", "compressed": "gzip", "value": "H4sI..."}`. With the `decompress`
+    /// feature, gzip and zlib values are also decompressed, up to
+    /// [`Parser::with_max_decompressed_len`], and the result is added under `"decompressed"`, as
+    /// a nested message if it is one. Zstd values are only noted. The default output is
+    /// unaffected when disabled.
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Wrap each element of arrays in the verbose output with its source.
+    ///
+    /// Elements become `{"field": 5, "index": 0, "value": ...}`, keeping track of the field a
+    /// value came from in tree views, e.g. once renamed with [`Parser::with_field_names`]. Only
+    /// takes effect together with [`Parser::with_verbose`].
+    pub fn with_array_element_tags(mut self, array_element_tags: bool) -> Self {
+        self.array_element_tags = array_element_tags;
+        self
+    }
+
+    /// Include the wire bytes of each field, tag included, as hex in the verbose output.
+    ///
+    /// Each occurrence of a field becomes `{"value": 28, "raw": "0d1c000000"}`, which helps
+    /// matching the output with the input bytes. With [`Parser::with_typed_json`] the `raw` key
+    /// is added to the typed object instead, and the elements of a packed field stay together
+    /// under `value`. Only takes effect together with [`Parser::with_verbose`].
+    pub fn with_raw_hex(mut self, raw_hex: bool) -> Self {
+        self.raw_hex = raw_hex;
+        self
+    }
+
+    /// Wrap every value with its type, keeping the type information in plain JSON.
+    ///
+    /// Scalars become `{"type": "varint", "value": 28}` with the types `varint`, `fixed64` and
+    /// `fixed32`, strings `{"type": "string", "value": "You"}` and bytes
+    /// `{"type": "bytes", "encoding": "base64", "value": "/w=="}`, the encoding naming the
+    /// configured [`BytesEncoding`]. Nested messages stay plain objects, each element of a
+    /// packed field is wrapped on its own.
+    pub fn with_typed_json(mut self, typed_json: bool) -> Self {
+        self.typed_json = typed_json;
+        self
+    }
+
+    /// Serialize object keys by numeric field number instead of as strings, e.g. `"2"` before
+    /// `"10"`, in [`Parser::parse_to_string`].
+    ///
+    /// A [`Value`] always keeps its keys sorted as strings, so this only applies to serialized
+    /// output. Wrap a value in [`NumericKeyOrder`](crate::NumericKeyOrder) to serialize it the
+    /// same way.
+    pub fn with_numeric_key_order(mut self, numeric_key_order: bool) -> Self {
+        self.numeric_key_order = numeric_key_order;
+        self
+    }
+
+    /// Group the digits of decimal integers by thousands in the text output of
+    /// [`Parser::parse_to_decode_raw`], e.g. `3,029,774,971,578`.
+    ///
+    /// This is for reading large IDs only: the text no longer matches protoc, and the JSON
+    /// output is never affected.
+    pub fn with_digit_grouping(mut self, digit_grouping: bool) -> Self {
+        self.digit_grouping = digit_grouping;
+        self
+    }
+
+    /// Emit the floating point interpretations of the verbose output as strings.
+    ///
+    /// Values are formatted as the shortest string that round-trips to the same float, e.g.
+    /// `"0.1"` for a fixed32 holding `0.1f32` instead of `0.10000000149011612`, and non-finite
+    /// values are rendered according to [`Parser::with_nonfinite_floats`]. The output is then
+    /// identical on every platform, which keeps snapshots stable.
+    pub fn with_canonical_floats(mut self, canonical_floats: bool) -> Self {
+        self.canonical_floats = canonical_floats;
+        self
+    }
+
+    /// Render NaN and infinite floating point interpretations, which JSON numbers can't hold.
+    ///
+    /// This applies to the verbose output, packed float and double fields, fields declared as
+    /// `float` or `double` by a descriptor and the schema output of `parse_to_protojson`. With
+    /// [`NonFinite::Error`], a value guessed to be a nested message is emitted as bytes rather
+    /// than failing the parse. Defaults to [`NonFinite::String`].
+    pub fn with_nonfinite_floats(mut self, nonfinite_floats: NonFinite) -> Self {
+        self.nonfinite_floats = nonfinite_floats;
+        self
+    }
+
+    /// Limit the number of operations a single parse may perform.
+    ///
+    /// Every decoded field, every converted field and every nested message candidate costs one
+    /// operation, so a field is charged both when decoded and when converted. Once the budget is
+    /// spent, [`Parser::try_parse`] returns [`ParseError::BudgetExhausted`]. This bounds the work
+    /// spent on untrusted input regardless of its size.
+    pub fn with_op_budget(mut self, budget: u64) -> Self {
+        self.op_budget = Some(budget);
+        self
+    }
+
+    /// Limit the size of decompressed data, see [`Parser::parse_gzip`] and
+    /// [`Parser::with_verbose`].
+    ///
+    /// Deflate compresses up to about 1000:1, so a small input may decompress to gigabytes.
+    /// Decompression stops with [`GzipError::TooLarge`](crate::GzipError::TooLarge) once the
+    /// output exceeds `len` bytes. Defaults to 64 MiB.
+    #[cfg(feature = "gzip")]
+    pub fn with_max_decompressed_len(mut self, len: usize) -> Self {
+        self.max_decompressed_len = len;
+        self
+    }
+
+    /// Never treat length-delimited values shorter than `len` bytes as nested messages.
+    ///
+    /// Very short values are rarely nested messages but often happen to decode as one. Defaults
+    /// to 2, the smallest possible non-empty message.
+    pub fn with_min_nested_len(mut self, len: usize) -> Self {
+        self.min_nested_len = len;
+        self
+    }
+
+    /// Parse a protobuf message from the given byte slice and convert it to JSON.
+    pub fn parse(&self, data: &[u8]) -> Option<Value> {
+        self.try_parse(data).ok()
+    }
+
+    /// Parse a protobuf message from the given byte slice and convert it to JSON, reporting why
+    /// the conversion failed.
+    pub fn try_parse(&self, data: &[u8]) -> Result<Value, ParseError> {
+        let mut walk = Walk::new(self);
+        self.parse_to_json(data, &mut walk)?
+            .ok_or(ParseError::NotAMessage)
+    }
+
+    /// Parse a message that is a list, converting every occurrence of the given top-level field
+    /// in wire order and ignoring the other fields.
+    ///
+    /// This suits list responses made of a single repeated field. The elements of a packed field
+    /// are added one by one. Returns `None` if the data is not a message, and an empty list if it
+    /// doesn't have the field.
+    ///
+    /// ``` rust
+    /// use protobuf_to_json::Parser;
+    /// use serde_json::json;
+    ///
+    /// let list = Parser::new().parse_as_list(&[0x08, 0x01, 0x10, 0x05, 0x08, 0x02], 1);
+    /// assert_eq!(list, Some(vec![json!(1), json!(2)]));
+    /// ```
+    pub fn parse_as_list(&self, data: &[u8], field: u64) -> Option<Vec<Value>> {
+        let msg = self.root_message(data)?;
+        let mut walk = Walk::new(self);
+        walk.path.push(field);
+        let mut values = Vec::new();
+        for value in msg.fields.iter().filter(|f| f.number == field) {
+            // The first layer may end with an invalid field, which stops the list.
+            match self.field_to_json(&value.value, None, &mut walk).ok()? {
+                Some(FieldJson::Single(value)) => values.push(value),
+                Some(FieldJson::Packed(elements)) => values.extend(elements),
+                None => break,
+            }
+        }
+        Some(values)
+    }
+
+    /// Convert the value of the field at `path` to JSON the way it is in the output of its
+    /// message, packed elements being an array. Returns `None` if the value is invalid.
+    pub(crate) fn field_value_to_json(&self, value: &FieldValue, path: &[u64]) -> Option<Value> {
+        let mut walk = Walk::new(self);
+        walk.path.extend_from_slice(path);
+        match self.field_to_json(value, None, &mut walk).ok()?? {
+            FieldJson::Single(value) => Some(value),
+            FieldJson::Packed(elements) => Some(Value::Array(elements)),
+        }
+    }
+
+    /// Parse data holding a bare packed repeated field, i.e. the elements only without any tag.
+    ///
+    /// Elements are rendered like a field hinted with [`Parser::with_packed_field_spec`]. Returns
+    /// `None` if the data doesn't end on an element boundary.
+    pub fn parse_packed_scalars(&self, data: &[u8], element: ElementType) -> Option<Vec<Value>> {
+        self.packed_elements(data, element, &[]).ok()?
+    }
+
+    /// Element types a packed repeated field holding `bytes` may have, to help choosing a hint
+    /// for [`Parser::with_packed_field_spec`].
+    ///
+    /// Reports [`ElementType::Varint`] if the blob decodes into varints exactly,
+    /// [`ElementType::Fixed32`] if its length is a multiple of 4 and [`ElementType::Fixed64`] if
+    /// it is a multiple of 8. Each of them stands for all the types of the same encoding, e.g.
+    /// `Fixed32` for `sfixed32` and `float` as well.
+    pub fn packed_candidates(&self, bytes: &[u8]) -> Vec<ElementType> {
+        let mut candidates = Vec::new();
+        let mut rest = bytes;
+        let decode_var = self.varint_decoder();
+        while !rest.is_empty() && decode_var(&mut rest).is_ok() {}
+        if rest.is_empty() {
+            candidates.push(ElementType::Varint);
+        }
+        if bytes.len().is_multiple_of(4) {
+            candidates.push(ElementType::Fixed32);
+        }
+        if bytes.len().is_multiple_of(8) {
+            candidates.push(ElementType::Fixed64);
+        }
+        candidates
+    }
+
+    /// Collect the wire types each top-level field number has across a set of sample messages.
+    ///
+    /// A field found with several wire types hints at a oneof-like reuse of the number or at
+    /// schema drift between the samples. Fields are taken from [`Parser::parse_once`], invalid
+    /// and incomplete values are left out.
+    pub fn field_type_profile(&self, samples: &[&[u8]]) -> HashMap<u64, HashSet<WireType>> {
+        let mut profile: HashMap<u64, HashSet<WireType>> = HashMap::new();
+        let mut msg = Message::default();
+        for sample in samples {
+            self.parse_once_into(sample, &mut msg);
+            for field in &msg.fields {
+                if !matches!(
+                    field.value,
+                    FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _)
+                ) {
+                    profile
+                        .entry(field.number)
+                        .or_default()
+                        .insert(field.value.wire_type());
+                }
+            }
+        }
+        profile
+    }
+
+    /// Parse a protobuf message and check that it holds all the `expected` top-level fields.
+    ///
+    /// A field is present if the data holds a valid value for it, whether or not it is part of
+    /// the output. Data that isn't a message holds no field, and converts to an empty object if
+    /// no field is expected.
+    pub fn parse_expecting(&self, data: &[u8], expected: &[u64]) -> Result<Value, MissingFields> {
+        let Some(json) = self.parse(data) else {
+            return match expected {
+                [] => Ok(Value::Object(Map::new())),
+                _ => Err(MissingFields {
+                    missing: expected.to_vec(),
+                }),
+            };
+        };
+        let msg = self.parse_once(data);
+        let missing: Vec<u64> = expected
+            .iter()
+            .filter(|&&number| {
+                !msg.fields.iter().any(|f| {
+                    f.number == number
+                        && !matches!(
+                            f.value,
+                            FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _)
+                        )
+                })
+            })
+            .copied()
+            .collect();
+        match missing.is_empty() {
+            true => Ok(json),
+            false => Err(MissingFields { missing }),
+        }
+    }
+
+    /// Skip a fixed-size framing header, then parse the protobuf message that follows.
+    ///
+    /// Returns [`ParseError::HeaderOutOfBounds`] if `skip` is larger than the data.
+    pub fn parse_with_header_skip(&self, data: &[u8], skip: usize) -> Result<Value, ParseError> {
+        let body = data.get(skip..).ok_or(ParseError::HeaderOutOfBounds {
+            skip,
+            len: data.len(),
+        })?;
+        self.try_parse(body)
+    }
+
+    /// Parse a base64 encoded protobuf message held by a string of a JSON document.
+    ///
+    /// `pointer` is a JSON pointer such as `/payload/data`. Standard and URL-safe base64 are
+    /// accepted, with or without padding. Returns `None` if there is no string at the pointer,
+    /// it isn't base64 or doesn't hold a message.
+    pub fn parse_embedded(&self, json: &Value, pointer: &str) -> Option<Value> {
+        let encoded = json.pointer(pointer)?.as_str()?;
+        let data = [
+            &BASE64_STANDARD,
+            &BASE64_STANDARD_NO_PAD,
+            &BASE64_URL_SAFE,
+            &BASE64_URL_SAFE_NO_PAD,
+        ]
+        .iter()
+        .find_map(|engine| engine.decode(encoded).ok())?;
+        self.parse(&data)
+    }
+
+    /// Recursively parse a protobuf message and convert it to JSON.
+    ///
+    /// Returns `Ok(None)` if the data doesn't look like a message. The reason is left in
+    /// [`Walk::rejection`].
+    pub(crate) fn parse_to_json(
+        &self,
+        data: &[u8],
+        walk: &mut Walk,
+    ) -> Result<Option<Value>, ParseError> {
+        if data.is_empty() {
+            walk.rejection = Some(NestedRejection::Empty);
+            return Ok(None);
+        }
+        walk.spend()?;
+        let first_layer = walk.path.is_empty();
+        if first_layer
+            && self.plain_output(walk)
+            && let Some(object) = self.scalar_message_to_json(data)
+        {
+            return Ok(Some(object));
+        }
+        let classified = match self.precheck_message(data, first_layer) {
+            Ok(utf8) => {
+                let msg = self.budgeted_parse_once(data, walk)?;
+                self.classify_fields(msg, data, first_layer, utf8)
+            }
+            Err(rejection) => Err(rejection),
+        };
+        let msg = match classified {
+            Ok(msg) => msg,
+            Err(rejection) => {
+                walk.rejection = Some(rejection);
+                return Ok(None);
+            }
+        };
+        if self.require_full_parse && first_layer && !msg.is_complete() {
+            return Ok(None);
+        }
+        if self.strict
+            && first_layer
+            && let Some(garbage) = msg.garbage
+        {
+            return Err(ParseError::Garbage {
+                offset: data.len() - garbage.len(),
+            });
+        }
+        self.message_to_json(&msg, Some(data), walk)
+    }
+
+    /// Whether the output of scalar fields only depends on their wire value, so
+    /// [`Parser::scalar_message_to_json`] gives the same output as the general path.
+    fn plain_output(&self, walk: &Walk) -> bool {
+        !self.verbose
+            && !self.typed_json
+            && !self.top_level_rejection
+            && !self.flatten
+            && self.length_field.is_none()
+            && self.op_budget.is_none()
+            && walk.explanations.is_none()
+            && walk.typed.is_none()
+            && *self.hints == Hints::default()
+    }
+
+    /// Whether the output only depends on the wire values and the guessed message structure, so
+    /// [`Parser::parse_to_writer`] can write it without building it.
+    pub(crate) fn streamable(&self) -> bool {
+        !self.verbose
+            && !self.typed_json
+            && !self.strict
+            && !self.flatten
+            && !self.collapse_single_field
+            && self.op_budget.is_none()
+            && self.duplicate_policy == DuplicatePolicy::Array
+            && self.garbage_field.is_none()
+            && self.length_field.is_none()
+            && self.max_output_depth.is_none()
+            && self.ambiguity == Ambiguity::Guess
+            && *self.hints == Hints::default()
+    }
+
+    /// Parse the first layer of the output the way [`Parser::parse_to_json`] does.
+    pub(crate) fn root_message<'a>(&self, data: &'a [u8]) -> Option<Message<'a>> {
+        if data.is_empty() {
+            return None;
+        }
+        let msg = self.classify_message(data, true).ok()?;
+        if self.require_full_parse && !msg.is_complete() {
+            return None;
+        }
+        Some(msg)
+    }
+
+    /// Fast path converting a message made of varint and fixed fields only, without building
+    /// the list of fields or checking for UTF-8.
+    ///
+    /// Returns `None` if the data holds any other field, so the general path handles it.
+    fn scalar_message_to_json(&self, mut data: &[u8]) -> Option<Value> {
+        let decode_var = self.varint_decoder();
+        let mut object = Map::new();
+        while !data.is_empty() {
+            let tag = self.decode_tag(&mut data)?;
+            let value = match tag & 0x07 {
+                0 => decode_var(&mut data).ok()?,
+                1 => {
+                    let (bytes, rest) = data.split_first_chunk::<8>()?;
+                    data = rest;
+                    u64::from_le_bytes(*bytes)
+                }
+                5 => {
+                    let (bytes, rest) = data.split_first_chunk::<4>()?;
+                    data = rest;
+                    u32::from_le_bytes(*bytes) as u64
+                }
+                _ => return None,
+            };
+            let value = self.uint_to_json(value);
+            // Scalars are never arrays, so an array is the merged occurrences of a field.
+            match object.entry((tag >> 3).to_string()) {
+                map::Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+                map::Entry::Occupied(mut entry) => match self.duplicate_policy {
+                    DuplicatePolicy::First => {}
+                    DuplicatePolicy::Last => *entry.get_mut() = value,
+                    DuplicatePolicy::Array => match entry.get_mut() {
+                        Value::Array(values) => values.push(value),
+                        first => *first = Value::Array(vec![first.take(), value]),
+                    },
+                },
+            }
+        }
+        Some(Value::Object(object))
+    }
+
+    /// Parse the data as a message if it looks like one.
+    ///
+    /// Nested messages (`first_layer == false`) are rejected if they are too short, look like
+    /// text, contain invalid or incomplete fields, or look like text and contain garbage or
+    /// reserved field numbers. The first layer is rejected for garbage or reserved field numbers
+    /// only with [`Parser::with_top_level_rejection`].
+    pub(crate) fn guess_message<'a>(
+        &self,
+        data: &'a [u8],
+        first_layer: bool,
+    ) -> Option<Message<'a>> {
+        self.classify_message(data, first_layer).ok()
+    }
+
+    /// Apply the heuristics of [`Parser::guess_message`], reporting why data was rejected.
+    pub(crate) fn classify_message<'a>(
+        &self,
+        data: &'a [u8],
+        first_layer: bool,
+    ) -> Result<Message<'a>, NestedRejection> {
+        let utf8 = self.precheck_message(data, first_layer)?;
+        self.classify_fields(self.parse_once(data), data, first_layer, utf8)
+    }
+
+    /// Checks of [`Parser::classify_message`] done before decoding the fields, returning whether
+    /// the data is valid UTF-8.
+    fn precheck_message(&self, data: &[u8], first_layer: bool) -> Result<bool, NestedRejection> {
+        if !first_layer && data.len() < self.min_nested_len {
+            return Err(NestedRejection::TooShort);
+        }
+
+        // Check if the data is valid UTF-8 and not control characters
+        let utf8_str = from_utf8(data);
+        if !first_layer && utf8_str.is_some_and(|s| s.chars().all(|c| !c.is_control())) {
+            return Err(NestedRejection::Text);
+        }
+        Ok(utf8_str.is_some())
+    }
+
+    /// Checks of [`Parser::classify_message`] done on the decoded fields of `data`.
+    fn classify_fields<'a>(
+        &self,
+        mut msg: Message<'a>,
+        data: &'a [u8],
+        first_layer: bool,
+        utf8: bool,
+    ) -> Result<Message<'a>, NestedRejection> {
+        if msg.fields.is_empty() {
+            return Err(NestedRejection::NoFields);
+        }
+        if !first_layer
+            && let Some(invalid) = msg.fields.iter().position(|f| {
+                matches!(
+                    f.value,
+                    FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _)
+                )
+            })
+        {
+            self.keep_valid_prefix(&mut msg, data, invalid)
+                .ok_or(NestedRejection::InvalidField)?;
+        }
+        if !first_layer && self.strict_nested && msg.garbage.is_some() {
+            return Err(NestedRejection::Garbage);
+        }
+        // If not the first layer, and the data is valid UTF-8 and contains garbage or reserved fields, reject it.
+        // The first layer is only checked on request.
+        let suspicious = match first_layer {
+            true => self.top_level_rejection,
+            false => utf8,
+        };
+        if suspicious {
+            if msg.garbage.is_some() {
+                let end = msg.fields.len();
+                if first_layer || self.keep_valid_prefix(&mut msg, data, end).is_none() {
+                    return Err(NestedRejection::Garbage);
+                }
+            }
+            if msg
+                .fields
+                .iter()
+                .any(|f| RESERVED_FIELD_NUMBER.contains(&f.number))
+            {
+                return Err(NestedRejection::ReservedField);
+            }
+        }
+        Ok(msg)
+    }
+
+    /// Accept the first `end` fields of a nested candidate with [`Parser::with_partial_nested`],
+    /// turning the rest of the data into garbage.
+    ///
+    /// Returns `None` if partial messages are not accepted or the fields cover less than half of
+    /// the data.
+    fn keep_valid_prefix<'a>(
+        &self,
+        msg: &mut Message<'a>,
+        data: &'a [u8],
+        end: usize,
+    ) -> Option<()> {
+        if !self.partial_nested || end == 0 {
+            return None;
+        }
+        let len: usize = self.field_spans(data)[..end]
+            .iter()
+            .map(|span| span.len())
+            .sum();
+        if len * 2 < data.len() {
+            return None;
+        }
+        msg.fields.truncate(end);
+        msg.garbage = Some(&data[len..]).filter(|rest| !rest.is_empty());
+        Some(())
+    }
+
+    /// Convert the fields of a message to a JSON object.
+    ///
+    /// `data` is the encoded message, if any, for [`Parser::with_raw_hex`]. Returns `Ok(None)` if
+    /// a nested message contains an invalid or incomplete field. The first layer keeps the fields
+    /// before such a field instead.
+    fn message_to_json(
+        &self,
+        msg: &Message,
+        data: Option<&[u8]>,
+        walk: &mut Walk,
+    ) -> Result<Option<Value>, ParseError> {
+        let first_layer = walk.path.is_empty();
+        let spans = match data {
+            Some(data) if self.verbose && self.raw_hex => self.field_spans(data),
+            _ => Vec::new(),
+        };
+        let mut object = ObjectBuilder::default();
+        if let Some(frames) = &mut walk.typed {
+            frames.push(Vec::new());
+        }
+        // Occurrences of each field number, as repeated fields are never collapsed.
+        let mut occurrences = HashMap::new();
+        if self.collapse_single_field && !self.flatten {
+            for field in &msg.fields {
+                *occurrences.entry(field.number).or_insert(0) += 1;
+            }
+        }
+        // Keys of collapsed fields, which are dotted paths already escaped.
+        let mut collapsed_keys = HashSet::new();
+        for (i, field) in msg.fields.iter().enumerate() {
+            walk.spend()?;
+            if self.strict {
+                match field.value {
+                    FieldValue::Invalid(wire_type, _) => {
+                        return Err(ParseError::InvalidWireType {
+                            field: field.number,
+                            wire_type,
+                        });
+                    }
+                    FieldValue::Incomplete(wire_type, _) => {
+                        return Err(ParseError::Incomplete {
+                            field: field.number,
+                            wire_type,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            if first_layer
+                && let Some(filter) = &self.hints.field_filter
+                && !filter.accepts(field.number)
+            {
+                continue;
+            }
+            walk.path.push(field.number);
+            let mut key = self.field_key(&walk.path);
+            let value = self.field_to_json(&field.value, spans.get(i).copied(), walk);
+            let always_array = self.hints.repeated.contains(walk.path.as_slice());
+            let value = match (value?, &self.hints.value_filter) {
+                (Some(json), Some(Callback(filter))) => {
+                    let path = FieldPath::from(walk.path.as_slice());
+                    match json {
+                        FieldJson::Single(value) if !filter(&path, &value) => {
+                            walk.path.pop();
+                            continue;
+                        }
+                        FieldJson::Packed(mut values) => {
+                            values.retain(|value| filter(&path, value));
+                            Some(FieldJson::Packed(values))
+                        }
+                        json => Some(json),
+                    }
+                }
+                (value, _) => value,
+            };
+            walk.path.pop();
+            let value = match (walk.collapsed.take(), value) {
+                (Some(path), Some(FieldJson::Single(Value::Object(map))))
+                    if map.len() == 1 && !always_array && occurrences[&field.number] == 1 =>
+                {
+                    key = format!("{}.{path}", escape_path_key(&key));
+                    collapsed_keys.insert(key.clone());
+                    map.into_iter()
+                        .next()
+                        .map(|(_, value)| FieldJson::Single(value))
+                }
+                (_, value) => value,
+            };
+            let duplicates = match always_array {
+                true => DuplicatePolicy::Array,
+                false => self.duplicate_policy,
+            };
+            match value {
+                Some(FieldJson::Single(value)) => {
+                    object.push(key, field.number, [value], always_array, duplicates)
+                }
+                Some(FieldJson::Packed(values)) => object.push(
+                    key,
+                    field.number,
+                    values,
+                    always_array || !self.unwrap_singletons,
+                    duplicates,
+                ),
+                None => match first_layer {
+                    true => break,
+                    false => {
+                        walk.close_typed();
+                        return Ok(None);
+                    }
+                },
+            }
+        }
+        walk.close_typed();
+
+        let mut object = object.finish(self.verbose && self.array_element_tags);
+        if let Value::Object(map) = &mut object {
+            if first_layer {
+                self.group_oneofs(msg, map);
+            }
+            if first_layer && self.flatten {
+                let mut flat = Map::new();
+                flatten_into(&mut flat, &mut String::new(), Value::Object(mem::take(map)));
+                *map = flat;
+            }
+            if let Some(key) = &self.garbage_field
+                && let Some(garbage) = msg.garbage
+            {
+                map.insert(key.clone(), Value::String(hex_string(garbage)));
+            }
+            if let Some(key) = &self.length_field {
+                let len = data.map_or_else(|| msg.encode().len(), <[u8]>::len);
+                map.insert(key.clone(), len.into());
+            }
+            walk.collapsed = None;
+            if !first_layer
+                && self.collapse_single_field
+                && !self.flatten
+                && map.len() == 1
+                && let Some(key) = map.keys().next()
+            {
+                walk.collapsed = Some(match collapsed_keys.contains(key) {
+                    true => key.clone(),
+                    false => escape_path_key(key),
+                });
+            }
+        }
+        Ok(Some(object))
+    }
+
+    /// JSON key of the field at the given path, its name if known or else its number.
+    fn field_key(&self, path: &[u64]) -> String {
+        let declared = || self.declared_field(path)?.name.as_ref();
+        match self.hints.names.get(path).or_else(declared) {
+            Some(name) => name.clone(),
+            None => path.last().unwrap().to_string(),
+        }
+    }
+
+    /// Move the present member of each oneof group under the group name.
+    fn group_oneofs(&self, msg: &Message, map: &mut Map<String, Value>) {
+        for (name, members) in &self.hints.oneofs {
+            let present = msg.fields.iter().rev().find_map(|field| {
+                let key = self.field_key(&[field.number]);
+                (members.contains(&field.number) && map.contains_key(&key))
+                    .then_some((field.number, key))
+            });
+            if let Some((number, key)) = present {
+                let value = map.remove(&key).unwrap();
+                map.insert(name.clone(), json!({"field": number, "value": value}));
+            }
+        }
+    }
+
+    /// Convert a single field value to JSON.
+    ///
+    /// `span` holds the wire bytes of the field if they are to be included. Returns `Ok(None)` if
+    /// the value is invalid or incomplete.
+    fn field_to_json(
+        &self,
+        value: &FieldValue,
+        span: Option<&[u8]>,
+        walk: &mut Walk,
+    ) -> Result<Option<FieldJson>, ParseError> {
+        let mark = walk.mark();
+        walk.rejection = None;
+        walk.collapsed = None;
+        let declared = self.declared_to_json(value, &walk.path)?;
+        let (json, output) = if let Some((json, output)) = declared {
+            (FieldJson::Single(json), output)
+        } else {
+            match value {
+                FieldValue::Varint(v) => (
+                    FieldJson::Single(self.varint_to_json(*v as u64, &walk.path)),
+                    FieldOutput::Scalar,
+                ),
+                FieldValue::Fixed64(v) if self.verbose => {
+                    let double =
+                        self.float_to_json(f64::from_bits(*v), f64::from_bits(*v), &walk.path)?;
+                    let two_u32 =
+                        [*v as u32, (*v >> 32) as u32].map(|v| self.uint_to_json(v as u64));
+                    let value = json!({"uint": self.uint_to_json(*v), "two_u32": two_u32, "double": double});
+                    (FieldJson::Single(value), FieldOutput::Scalar)
+                }
+                FieldValue::Fixed64(v) => (
+                    FieldJson::Single(self.uint_to_json(*v)),
+                    FieldOutput::Scalar,
+                ),
+                FieldValue::Fixed32(v) if self.verbose => {
+                    let float = self.float_to_json(
+                        f32::from_bits(*v),
+                        f32::from_bits(*v).into(),
+                        &walk.path,
+                    )?;
+                    let value = json!({"uint": self.uint_to_json(*v as u64), "float": float});
+                    (FieldJson::Single(value), FieldOutput::Scalar)
+                }
+                FieldValue::Fixed32(v) => (
+                    FieldJson::Single(self.uint_to_json(*v as u64)),
+                    FieldOutput::Scalar,
+                ),
+                FieldValue::LengthDelimited(bytes) => {
+                    if self.hints.opaque.contains(walk.path.as_slice()) {
+                        (
+                            FieldJson::Single(self.opaque_to_json(bytes)),
+                            FieldOutput::Bytes,
+                        )
+                    } else if let Some(format) = self.bytes_format(&walk.path) {
+                        match format(bytes) {
+                            Some(s) => (FieldJson::Single(Value::String(s)), FieldOutput::String),
+                            None => (
+                                FieldJson::Single(self.bytes_to_json(bytes)),
+                                self.bytes_output(bytes),
+                            ),
+                        }
+                    } else if let Some(values) = self.packed_to_json(bytes, &walk.path)? {
+                        (FieldJson::Packed(values), FieldOutput::Packed)
+                    } else if self.beyond_output_depth(bytes, &walk.path) {
+                        let encoding = match self.bytes_encoding {
+                            BytesEncoding::Base64Url => BytesEncoding::Base64Url,
+                            _ => BytesEncoding::Base64,
+                        };
+                        let blob = self.limited_bytes(bytes, encoding);
+                        (FieldJson::Single(blob), FieldOutput::Bytes)
+                    } else if let Some(nested) = self.forced_nested_to_json(bytes, walk)? {
+                        (FieldJson::Single(nested), FieldOutput::Message)
+                    } else if let Some(inlined) = self.embedded_json(bytes, &walk.path) {
+                        (FieldJson::Single(inlined), FieldOutput::Json)
+                    } else if let Some((json, output)) =
+                        self.ambiguous_to_json(bytes, &walk.path)?
+                    {
+                        (FieldJson::Single(json), output)
+                    } else if !self.hints.strings.contains(walk.path.as_slice())
+                        && let Some(nested) = self.guessed_nested_to_json(bytes, walk)?
+                    {
+                        (FieldJson::Single(nested), FieldOutput::Message)
+                    } else {
+                        (
+                            FieldJson::Single(self.bytes_to_json(bytes)),
+                            self.bytes_output(bytes),
+                        )
+                    }
+                }
+                FieldValue::Message(msg) => match self.message_to_json(msg, None, walk)? {
+                    Some(nested) => (FieldJson::Single(nested), FieldOutput::Message),
+                    None => return Ok(None),
+                },
+                FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _) => {
+                    walk.explain(mark, value.wire_type(), FieldOutput::Dropped);
+                    return Ok(None);
+                }
+            }
+        };
+        if output != FieldOutput::Message
+            || self.typed_json
+            || span.is_some()
+            || self.hints.wrappers.contains(walk.path.as_slice())
+        {
+            walk.collapsed = None;
+        }
+        let json = match json {
+            FieldJson::Single(json) if output == FieldOutput::Message => {
+                FieldJson::Single(self.unwrapped(json, &walk.path))
+            }
+            json => json,
+        };
+        walk.explain(mark, value.wire_type(), output);
+        walk.record_typed(value, output);
+        let json = match json {
+            _ if !self.typed_json => json,
+            FieldJson::Single(json) => FieldJson::Single(self.type_tagged(json, value, output)),
+            FieldJson::Packed(values) => FieldJson::Packed(
+                values
+                    .into_iter()
+                    .map(|json| json!({"type": "varint", "value": json}))
+                    .collect(),
+            ),
+        };
+        let json = match (json, value) {
+            (FieldJson::Single(json), FieldValue::LengthDelimited(bytes))
+                if self.verbose && output == FieldOutput::Bytes =>
+            {
+                FieldJson::Single(self.compression_annotated(json, bytes, walk)?)
+            }
+            (json, _) => json,
+        };
+        let Some(span) = span else {
+            return Ok(Some(json));
+        };
+        let raw = Value::String(hex_string(span));
+        let json = match json {
+            FieldJson::Single(Value::Object(mut map))
+                if self.typed_json && output != FieldOutput::Message =>
+            {
+                map.insert("raw".to_string(), raw);
+                Value::Object(map)
+            }
+            FieldJson::Single(json) => json!({"value": json, "raw": raw}),
+            FieldJson::Packed(values) => json!({"value": values, "raw": raw}),
+        };
+        Ok(Some(FieldJson::Single(json)))
+    }
+
+    /// Replace a nested message hinted with [`Parser::with_wrapper_fields`] by its field 1.
+    fn unwrapped(&self, json: Value, path: &[u64]) -> Value {
+        if !self.hints.wrappers.contains(path) {
+            return json;
+        }
+        match json {
+            Value::Object(map) if map.len() == 1 && map.get("1").is_some_and(|v| !v.is_array()) => {
+                map.into_iter().next().unwrap().1
+            }
+            json => json,
+        }
+    }
+
+    /// Note the compression format of a bytes value in the verbose output, e.g.
+    /// `{"encoding": "base64", "compressed": "gzip", "value": "H4sI..."}`, and add the
+    /// decompressed value with the `decompress` feature.
+    #[cfg_attr(not(feature = "decompress"), allow(unused_variables))]
+    fn compression_annotated(
+        &self,
+        json: Value,
+        bytes: &[u8],
+        walk: &mut Walk,
+    ) -> Result<Value, ParseError> {
+        let Some(compression) = sniff_compression(bytes) else {
+            return Ok(json);
+        };
+        let mut map = match json {
+            Value::Object(map) if self.typed_json => map,
+            json => Map::from_iter([
+                ("encoding".to_string(), self.encoding_name().into()),
+                ("value".to_string(), json),
+            ]),
+        };
+        map.insert("compressed".to_string(), compression.into());
+        #[cfg(feature = "decompress")]
+        if let Some(data) = crate::gzip::decompress(bytes, compression, self.max_decompressed_len) {
+            let decompressed = self.decompressed_to_json(&data, walk)?;
+            map.insert("decompressed".to_string(), decompressed);
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// Convert the decompressed data of a bytes field, as a message if it is one.
+    #[cfg(feature = "decompress")]
+    fn decompressed_to_json(&self, data: &[u8], walk: &mut Walk) -> Result<Value, ParseError> {
+        // A walk of its own, as the data is not part of the wire fields reported by `explain`
+        // and `parse_both`, sharing the budget.
+        let mut inner = Walk {
+            path: walk.path.clone(),
+            budget: walk.budget,
+            ..Walk::new(self)
+        };
+        let nested = self.guessed_nested_to_json(data, &mut inner);
+        walk.budget = inner.budget;
+        Ok(nested?.unwrap_or_else(|| self.bytes_to_json(data)))
+    }
+
+    /// Name of the configured bytes encoding in annotated output, where bytes are never strings
+    /// as with [`BytesEncoding::Auto`].
+    fn encoding_name(&self) -> &'static str {
+        match self.bytes_encoding {
+            BytesEncoding::Auto => BytesEncoding::Base64.name(),
+            encoding => encoding.name(),
+        }
+    }
+
+    /// Wrap a converted value with its type for [`Parser::with_typed_json`].
+    fn type_tagged(&self, json: Value, value: &FieldValue, output: FieldOutput) -> Value {
+        let ty = match output {
+            FieldOutput::Message => return json,
+            FieldOutput::String => "string",
+            FieldOutput::Json => "json",
+            FieldOutput::Bytes => {
+                return json!({"type": "bytes", "encoding": self.encoding_name(), "value": json});
+            }
+            FieldOutput::Scalar | FieldOutput::Packed | FieldOutput::Dropped => {
+                match value.wire_type() {
+                    WireType::Varint => "varint",
+                    WireType::Fixed64 => "fixed64",
+                    WireType::Fixed32 => "fixed32",
+                    _ => "bytes",
+                }
+            }
+        };
+        json!({"type": ty, "value": json})
+    }
+
+    /// Convert a varint to JSON, zigzag decoding it if the field is hinted so.
+    fn varint_to_json(&self, v: u64, path: &[u64]) -> Value {
+        let number = self.varint_number_to_json(v, path);
+        if let [field] = path
+            && let Some(name) = self
+                .hints
+                .enums
+                .get(field)
+                .and_then(|names| names.get(&(v as i64)))
+        {
+            return match self.enum_render {
+                EnumRender::NameOnly => Value::String(name.clone()),
+                EnumRender::ValueOnly => number,
+                EnumRender::Both => json!({"value": number, "name": name}),
+            };
+        }
+        number
+    }
+
+    /// Convert a varint to a number according to the hints of its field.
+    fn varint_number_to_json(&self, v: u64, path: &[u64]) -> Value {
+        if let Some(scale) = self.hints.scales.get(path) {
+            let v = match self.hints.zigzag.contains(path) {
+                true => zigzag_decode(v),
+                false => v as i64,
+            };
+            return Value::String(format_scaled(v, *scale));
+        }
+        if self.hints.zigzag.contains(path) {
+            self.sint_to_json(zigzag_decode(v))
+        } else if self.hints.int32.contains(path) {
+            self.sint_to_json(v as i32 as i64)
+        } else {
+            self.uint_to_json(v)
+        }
+    }
+
+    /// Resolve a length-delimited value that is valid UTF-8 and looks like a nested message
+    /// according to [`Parser::with_ambiguity`].
+    ///
+    /// Returns `Ok(None)` if the value is not ambiguous or ambiguous values are guessed.
+    fn ambiguous_to_json(
+        &self,
+        bytes: &[u8],
+        path: &[u64],
+    ) -> Result<Option<(Value, FieldOutput)>, ParseError> {
+        // The value as a string, if it is ambiguous. Only checked when the policy resolves
+        // ambiguous values, as it parses the value.
+        let ambiguous = || {
+            let s = std::str::from_utf8(bytes).ok()?;
+            let nested =
+                !self.hints.strings.contains(path) && self.guess_message(bytes, false).is_some();
+            nested.then_some(s)
+        };
+        match self.ambiguity {
+            Ambiguity::Guess => Ok(None),
+            Ambiguity::Error => ambiguous().map_or(Ok(None), |_| {
+                Err(ParseError::Ambiguous {
+                    path: FieldPath::from(path),
+                })
+            }),
+            Ambiguity::PreferBytes => {
+                Ok(ambiguous().map(|_| (self.opaque_to_json(bytes), FieldOutput::Bytes)))
+            }
+            Ambiguity::PreferString => {
+                Ok(ambiguous().map(|s| (self.string_to_json(s), FieldOutput::String)))
+            }
+        }
+    }
+
+    /// Formatter of the length-delimited field at `path` if it is hinted to hold an address or
+    /// identifier.
+    fn bytes_format(&self, path: &[u64]) -> Option<BytesFormat> {
+        if self.hints.ip.contains(path) {
+            Some(ip_address)
+        } else if self.hints.uuid.contains(path) {
+            Some(uuid_string)
+        } else {
+            None
+        }
+    }
+
+    /// Kind of output [`Parser::bytes_to_json`] gives for `bytes`.
+    fn bytes_output(&self, bytes: &[u8]) -> FieldOutput {
+        match self.bytes_encoding {
+            BytesEncoding::Auto if std::str::from_utf8(bytes).is_ok() => FieldOutput::String,
+            _ => FieldOutput::Bytes,
+        }
+    }
+
+    /// Render bytes known not to be text, base64 encoding them in [`BytesEncoding::Auto`] mode.
+    pub(crate) fn opaque_to_json(&self, bytes: &[u8]) -> Value {
+        match self.bytes_encoding {
+            BytesEncoding::Auto => self.limited_bytes(bytes, BytesEncoding::Base64),
+            _ => self.bytes_to_json(bytes),
+        }
+    }
+
+    /// Whether the length-delimited field at `path` would be a message nested deeper than
+    /// [`Parser::with_max_output_depth`] allows.
+    fn beyond_output_depth(&self, bytes: &[u8], path: &[u64]) -> bool {
+        if self.max_output_depth.is_none_or(|depth| path.len() < depth) {
+            return false;
+        }
+        let declared = self
+            .declared_field(path)
+            .is_some_and(|field| field.message.is_some());
+        if declared || self.hints.nested.contains(path) {
+            return self.parse_once(bytes).is_complete();
+        }
+        !self.hints.strings.contains(path) && self.guess_message(bytes, false).is_some()
+    }
+
+    /// Parse a length-delimited field hinted with [`Parser::force_nested`] or declared as a
+    /// message by the descriptor as a message.
+    ///
+    /// Returns `Ok(None)` if the field isn't hinted or doesn't decode into valid fields entirely.
+    fn forced_nested_to_json(
+        &self,
+        bytes: &[u8],
+        walk: &mut Walk,
+    ) -> Result<Option<Value>, ParseError> {
+        let declared = self
+            .declared_field(&walk.path)
+            .is_some_and(|field| field.message.is_some());
+        if !declared && !self.hints.nested.contains(walk.path.as_slice()) {
+            return Ok(None);
+        }
+        walk.spend()?;
+        let msg = self.budgeted_parse_once(bytes, walk)?;
+        if !msg.is_complete() {
+            return Ok(None);
+        }
+        self.message_to_json(&msg, Some(bytes), walk)
+    }
+
+    /// Parse a length-delimited value guessed to be a nested message.
+    ///
+    /// A non-finite float failing the conversion with [`NonFinite::Error`] only rejects the
+    /// candidate, which is then emitted as bytes, as it may not be a message at all.
+    fn guessed_nested_to_json(
+        &self,
+        bytes: &[u8],
+        walk: &mut Walk,
+    ) -> Result<Option<Value>, ParseError> {
+        let mark = walk.mark();
+        let depth = walk.path.len();
+        let frames = walk.typed.as_ref().map_or(0, Vec::len);
+        match self.parse_to_json(bytes, walk) {
+            Err(ParseError::NonFiniteFloat { .. }) => {
+                walk.path.truncate(depth);
+                if let Some(explanations) = &mut walk.explanations {
+                    explanations.truncate(mark);
+                }
+                if let Some(typed) = &mut walk.typed {
+                    typed.truncate(frames);
+                }
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Parse a length-delimited field hinted with [`Parser::with_json_fields`] as JSON.
+    fn embedded_json(&self, bytes: &[u8], path: &[u64]) -> Option<Value> {
+        if !self.hints.json.contains(path) {
+            return None;
+        }
+        serde_json::from_slice(bytes).ok()
+    }
+
+    /// Render a floating point interpretation of the field at `path`, formatting `v` itself
+    /// when canonical floats are enabled so `f32` values keep their shortest form.
+    pub(crate) fn float_to_json(
+        &self,
+        v: impl fmt::Debug,
+        wide: f64,
+        path: &[u64],
+    ) -> Result<Value, ParseError> {
+        if !wide.is_finite() {
+            let s = if wide.is_nan() {
+                "NaN"
+            } else if wide > 0.0 {
+                "Infinity"
+            } else {
+                "-Infinity"
+            };
+            return match self.nonfinite_floats {
+                NonFinite::String => Ok(Value::String(s.to_string())),
+                NonFinite::Null => Ok(Value::Null),
+                NonFinite::Error => Err(ParseError::NonFiniteFloat {
+                    field: path.last().copied().unwrap_or_default(),
+                }),
+            };
+        }
+        if !self.canonical_floats {
+            return Ok(json!(wide));
+        }
+        Ok(Value::String(format!("{v:?}")))
+    }
+
+    /// Render an unsigned integer in the configured radix.
+    pub(crate) fn uint_to_json(&self, v: u64) -> Value {
+        match self.int_radix {
+            IntRadix::Decimal => Value::Number(v.into()),
+            IntRadix::Hex => Value::String(format!("{v:#x}")),
+            IntRadix::Binary => Value::String(format!("{v:#b}")),
+        }
+    }
+
+    /// Render a signed integer in the configured radix, keeping the sign in front of the prefix.
+    pub(crate) fn sint_to_json(&self, v: i64) -> Value {
+        let sign = if v < 0 { "-" } else { "" };
+        match self.int_radix {
+            IntRadix::Decimal => Value::Number(v.into()),
+            IntRadix::Hex => Value::String(format!("{sign}{:#x}", v.unsigned_abs())),
+            IntRadix::Binary => Value::String(format!("{sign}{:#b}", v.unsigned_abs())),
+        }
+    }
+
+    /// Decode a length-delimited field hinted as packed into its elements.
+    ///
+    /// Returns `Ok(None)` if the field isn't hinted or the blob doesn't end on an element
+    /// boundary.
+    fn packed_to_json(&self, bytes: &[u8], path: &[u64]) -> Result<Option<Vec<Value>>, ParseError> {
+        let Some(element) = self.packed_element(path) else {
+            return Ok(None);
+        };
+        self.packed_elements(bytes, element, path)
+    }
+
+    /// Varint decoding function selected by [`Parser::with_msb_first_varints`].
+    pub(crate) fn varint_decoder(&self) -> VarintDecoder {
+        match self.msb_first_varints {
+            true => decode_var_msb_first,
+            false => decode_var,
+        }
+    }
+
+    /// Decode the tag of a field, see [`Parser::with_tag_transform`].
+    ///
+    /// Returns `None` without consuming anything if the data doesn't start with a tag or its
+    /// field number is larger than protobuf allows, so such data is garbage.
+    pub(crate) fn decode_tag(&self, data: &mut &[u8]) -> Option<u64> {
+        let mut rest = *data;
+        let tag = self.varint_decoder()(&mut rest).ok()?;
+        let tag = match &self.tag_transform {
+            Some(Callback(transform)) => transform(tag),
+            None => tag,
+        };
+        if tag >> 3 > MAX_FIELD_NUMBER {
+            return None;
+        }
+        *data = rest;
+        Some(tag)
+    }
+
+    /// Split a message into the wire bytes of its fields, the same way [`Parser::parse_once`]
+    /// splits it into fields.
+    pub(crate) fn field_spans<'a>(&self, mut data: &'a [u8]) -> Vec<&'a [u8]> {
+        let decode_var = self.varint_decoder();
+        let mut spans = Vec::new();
+        while !data.is_empty() {
+            let start = data;
+            let Some(tag) = self.decode_tag(&mut data) else {
+                break;
+            };
+            FieldValue::decode_with(&mut data, WireType::from((tag & 0x07) as u8), decode_var);
+            spans.push(&start[..start.len() - data.len()]);
+        }
+        spans
+    }
+
+    /// Element type of the field at `path`, if it is hinted as packed.
+    pub(crate) fn packed_element(&self, path: &[u64]) -> Option<ElementType> {
+        match self.hints.packed_specs.get(path) {
+            Some(element) => Some(*element),
+            None => self
+                .hints
+                .packed
+                .contains(path)
+                .then_some(ElementType::Varint),
+        }
+    }
+
+    /// Whether the value `bytes` of the field at `path` is emitted as a string or bytes whether or
+    /// not it is a message, because of hints, the descriptor or the ambiguity policy.
+    pub(crate) fn never_nested(&self, bytes: &[u8], path: &[u64]) -> bool {
+        let declared = self.declared_field(path).and_then(|field| field.field_type);
+        self.hints.strings.contains(path)
+            || self.hints.opaque.contains(path)
+            || self.hints.json.contains(path)
+            || self.bytes_format(path).is_some()
+            || matches!(declared, Some(FieldType::String | FieldType::Bytes))
+            || (self.ambiguity != Ambiguity::Guess && std::str::from_utf8(bytes).is_ok())
+    }
+
+    /// Decode the elements of a packed blob of the field at `path`.
+    ///
+    /// Returns `Ok(None)` if the blob doesn't end on an element boundary.
+    fn packed_elements(
+        &self,
+        mut bytes: &[u8],
+        element: ElementType,
+        path: &[u64],
+    ) -> Result<Option<Vec<Value>>, ParseError> {
+        let mut values = Vec::new();
+        match element {
+            ElementType::Fixed32 | ElementType::Sfixed32 | ElementType::Float => {
+                let (chunks, []) = bytes.as_chunks::<4>() else {
+                    return Ok(None);
+                };
+                for chunk in chunks {
+                    let v = u32::from_le_bytes(*chunk);
+                    values.push(match element {
+                        ElementType::Fixed32 => self.uint_to_json(v as u64),
+                        ElementType::Sfixed32 => self.sint_to_json(v as i32 as i64),
+                        _ => {
+                            self.float_to_json(f32::from_bits(v), f32::from_bits(v).into(), path)?
+                        }
+                    });
+                }
+            }
+            ElementType::Fixed64 | ElementType::Sfixed64 | ElementType::Double => {
+                let (chunks, []) = bytes.as_chunks::<8>() else {
+                    return Ok(None);
+                };
+                for chunk in chunks {
+                    let v = u64::from_le_bytes(*chunk);
+                    values.push(match element {
+                        ElementType::Fixed64 => self.uint_to_json(v),
+                        ElementType::Sfixed64 => self.sint_to_json(v as i64),
+                        _ => self.float_to_json(f64::from_bits(v), f64::from_bits(v), path)?,
+                    });
+                }
+            }
+            ElementType::Varint | ElementType::Sint | ElementType::Int | ElementType::Bool => {
+                let decode_var = self.varint_decoder();
+                while !bytes.is_empty() {
+                    let Ok(v) = decode_var(&mut bytes) else {
+                        return Ok(None);
+                    };
+                    values.push(match element {
+                        ElementType::Varint => self.varint_to_json(v, path),
+                        ElementType::Sint => self.sint_to_json(zigzag_decode(v)),
+                        ElementType::Int => self.sint_to_json(v as i64),
+                        _ => Value::Bool(v != 0),
+                    });
+                }
+            }
+        }
+        Ok(Some(values))
+    }
+
+    /// Convert a length-delimited value of the field at `path`, as a nested message if it looks
+    /// like one and as bytes otherwise.
+    #[cfg(feature = "schema")]
+    pub(crate) fn length_delimited_to_json(&self, bytes: &[u8], path: &[u64]) -> Value {
+        let mut walk = Walk::new(self);
+        walk.path.extend_from_slice(path);
+        match self.parse_to_json(bytes, &mut walk) {
+            Ok(Some(nested)) => nested,
+            _ => self.bytes_to_json(bytes),
+        }
+    }
+
+    /// Encode bytes that are neither a nested message nor a packed array.
+    pub(crate) fn bytes_to_json(&self, bytes: &[u8]) -> Value {
+        self.limited_bytes(bytes, self.bytes_encoding)
+    }
+
+    /// Render a string, truncated according to [`Parser::with_max_string_len`].
+    pub(crate) fn string_to_json(&self, s: &str) -> Value {
+        self.limited_bytes(s.as_bytes(), BytesEncoding::Auto)
+    }
+
+    /// Encode bytes, truncated according to [`Parser::with_max_string_len`].
+    fn limited_bytes(&self, bytes: &[u8], encoding: BytesEncoding) -> Value {
+        // A marker would turn a byte array into an array of mixed types, so they are kept whole.
+        let Some(max) = self
+            .max_string_len
+            .filter(|max| bytes.len() > *max && encoding != BytesEncoding::ByteArray)
+        else {
+            return encode_bytes(bytes, encoding);
+        };
+        let text = match encoding {
+            BytesEncoding::Base64
+            | BytesEncoding::Base64Url
+            | BytesEncoding::DataUri
+            | BytesEncoding::Hex => None,
+            _ => std::str::from_utf8(bytes).ok(),
+        };
+        let (encoding, kept) = match text {
+            Some(s) => (encoding, s.floor_char_boundary(max)),
+            // A prefix of invalid UTF-8 may be valid, keep it in base64.
+            None if encoding == BytesEncoding::Auto => (BytesEncoding::Base64, max),
+            None => (encoding, max),
+        };
+        match encode_bytes(&bytes[..kept], encoding) {
+            Value::String(mut s) => {
+                s.push_str(&format!("\u{2026}(+{} bytes)", bytes.len() - kept));
+                Value::String(s)
+            }
+            json => json,
+        }
+    }
+
+    /// Parse a protobuf message from the given byte slice without recursion.
+    pub fn parse_once<'a>(&self, data: &'a [u8]) -> Message<'a> {
+        let mut msg = Message::default();
+        self.parse_once_into(data, &mut msg);
+        msg
+    }
+
+    /// Parse a protobuf message like [`Parser::parse_once`] into an existing message.
+    ///
+    /// `msg` is cleared first, reusing the allocation of its field list across calls. As the
+    /// fields borrow from `data`, all data parsed into the same message must outlive it.
+    pub fn parse_once_into<'a>(&self, data: &'a [u8], msg: &mut Message<'a>) {
+        msg.fields.clear();
+        msg.garbage = self.decode_fields(data, |field| msg.fields.push(field));
+    }
+
+    /// Parse a message like [`Parser::parse_once`], spending one operation of the budget per
+    /// decoded field.
+    fn budgeted_parse_once<'a>(
+        &self,
+        data: &'a [u8],
+        walk: &mut Walk,
+    ) -> Result<Message<'a>, ParseError> {
+        let mut fields = Vec::new();
+        let garbage = self.try_decode_fields(data, |field| {
+            walk.spend()?;
+            fields.push(field);
+            Ok(())
+        })?;
+        Ok(Message { fields, garbage })
+    }
+
+    /// Decode the fields of one layer, passing each to `push`, and return the garbage at the end.
+    pub(crate) fn decode_fields<'a>(
+        &self,
+        data: &'a [u8],
+        mut push: impl FnMut(Field<'a>),
+    ) -> Option<&'a [u8]> {
+        let Ok(garbage) = self.try_decode_fields(data, |field| {
+            push(field);
+            Ok::<_, Infallible>(())
+        });
+        garbage
+    }
+
+    /// Same as [`Parser::decode_fields`], stopping at the first error returned by `push`.
+    fn try_decode_fields<'a, E>(
+        &self,
+        mut data: &'a [u8],
+        mut push: impl FnMut(Field<'a>) -> Result<(), E>,
+    ) -> Result<Option<&'a [u8]>, E> {
+        let data = &mut data;
+        let decode_var = self.varint_decoder();
+
+        loop {
+            if data.is_empty() {
+                return Ok(None);
+            }
+
+            let remaining = data.len();
+            let Some(tag) = self.decode_tag(data) else {
+                return Ok(Some(data));
+            };
+
+            let number = tag >> 3;
+            let wire_type = WireType::from((tag & 0x07) as u8);
+
+            let value = FieldValue::decode_with(data, wire_type, decode_var);
+            push(Field { number, value })?;
+            // Every iteration consumes at least the tag, so the loop always terminates
+            debug_assert!(data.len() < remaining, "parse_once made no progress");
+        }
+    }
+}
+
+/// Formatter of a hinted bytes value, returning `None` if the value has the wrong length.
+type BytesFormat = fn(&[u8]) -> Option<String>;
+
+/// Format a 4 or 16 byte value as an IPv4 or IPv6 address.
+fn ip_address(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string()),
+        16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?).to_string()),
+        _ => None,
+    }
+}
+
+/// Format a 16 byte value as a UUID.
+fn uuid_string(bytes: &[u8]) -> Option<String> {
+    if bytes.len() != 16 {
+        return None;
+    }
+    let hex = hex_string(bytes);
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Validate UTF-8 with SIMD instructions.
+#[cfg(feature = "simd-utf8")]
+fn from_utf8(data: &[u8]) -> Option<&str> {
+    simdutf8::basic::from_utf8(data).ok()
+}
+
+/// Validate UTF-8, without the `simd-utf8` feature.
+#[cfg(not(feature = "simd-utf8"))]
+fn from_utf8(data: &[u8]) -> Option<&str> {
+    std::str::from_utf8(data).ok()
+}
+
+/// Insert the leaves of `value` into `flat`, keyed by their path below `prefix`.
+fn flatten_into(flat: &mut Map<String, Value>, prefix: &mut String, value: Value) {
+    let children: Vec<(String, Value)> = match value {
+        Value::Object(map) if !map.is_empty() => map
+            .into_iter()
+            .map(|(key, value)| (escape_path_key(&key), value))
+            .collect(),
+        Value::Array(values) if !values.is_empty() => values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| (index.to_string(), value))
+            .collect(),
+        leaf => {
+            flat.insert(prefix.clone(), leaf);
+            return;
+        }
+    };
+    let len = prefix.len();
+    for (key, value) in children {
+        if len > 0 {
+            prefix.push('.');
+        }
+        prefix.push_str(&key);
+        flatten_into(flat, prefix, value);
+        prefix.truncate(len);
+    }
+}
+
+/// Escape the dots and backslashes of a key used as a segment of a dotted path.
+fn escape_path_key(key: &str) -> String {
+    key.replace('\\', "\\\\").replace('.', "\\.")
+}
+
+/// Format `v / 10^scale` as an exact decimal string.
+fn format_scaled(v: i64, scale: i32) -> String {
+    let sign = if v < 0 { "-" } else { "" };
+    let digits = v.unsigned_abs().to_string();
+    if scale <= 0 {
+        let zeros = if v == 0 {
+            0
+        } else {
+            scale.unsigned_abs() as usize
+        };
+        return format!("{sign}{digits}{}", "0".repeat(zeros));
+    }
+    let scale = scale as usize;
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (int, frac) = digits.split_at(digits.len() - scale);
+    format!("{sign}{int}.{frac}")
+}
+
+/// Format bytes as lowercase hex without separators.
+pub(crate) fn hex_string(bytes: &[u8]) -> String {
+    use fmt::Write;
+
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{b:02x}").unwrap();
+    }
+    s
+}
+
+/// JSON conversion of a single field.
+enum FieldJson {
+    /// A single value.
+    Single(Value),
+
+    /// The elements of a packed repeated field.
+    Packed(Vec<Value>),
+}
+
+/// Builds the JSON object of a message, merging repeated fields into arrays.
+#[derive(Default)]
+struct ObjectBuilder {
+    /// Entries in order of first occurrence.
+    entries: Vec<Entry>,
+
+    /// Position of each key in `entries`.
+    index: HashMap<String, usize>,
+}
+
+/// Values of one key of an [`ObjectBuilder`].
+struct Entry {
+    key: String,
+    number: u64,
+    values: Vec<Value>,
+
+    /// Emit an array even for a single value.
+    array: bool,
+}
+
+impl ObjectBuilder {
+    /// Add values under the given key, merging them with earlier values of the same key
+    /// according to `duplicates`.
+    fn push(
+        &mut self,
+        key: String,
+        number: u64,
+        values: impl IntoIterator<Item = Value>,
+        array: bool,
+        duplicates: DuplicatePolicy,
+    ) {
+        let len = self.entries.len();
+        let i = *self.index.entry(key).or_insert_with_key(|key| {
+            self.entries.push(Entry {
+                key: key.clone(),
+                number,
+                values: Vec::new(),
+                array: false,
+            });
+            self.entries.len() - 1
+        });
+        let entry = &mut self.entries[i];
+        match duplicates {
+            DuplicatePolicy::First if i < len => return,
+            DuplicatePolicy::Last => entry.values.clear(),
+            _ => {}
+        }
+        entry.values.extend(values);
+        entry.array |= array;
+    }
+
+    /// Build the object, wrapping array elements with their field number and index if
+    /// `tag_elements` is set.
+    fn finish(self, tag_elements: bool) -> Value {
+        let mut map = Map::new();
+        for mut entry in self.entries {
+            let value = if entry.array || entry.values.len() != 1 {
+                if tag_elements {
+                    let number = entry.number;
+                    let tagged = entry.values.into_iter().enumerate().map(
+                        |(index, value)| json!({"field": number, "index": index, "value": value}),
+                    );
+                    Value::Array(tagged.collect())
+                } else {
+                    Value::Array(entry.values)
+                }
+            } else {
+                entry.values.pop().unwrap()
+            };
+            map.insert(entry.key, value);
+        }
+        Value::Object(map)
+    }
+}
+
+/// State of a single parse, threaded through the recursion.
+pub(crate) struct Walk {
+    /// Field numbers leading to the current message, empty for the first layer.
+    path: Vec<u64>,
+
+    /// Remaining operations, if the parser has a budget.
+    budget: Option<u64>,
+
+    /// Why the last length-delimited value was not parsed as a message.
+    rejection: Option<NestedRejection>,
+
+    /// Escaped dotted key of the single field of the last converted nested message, if it is to
+    /// be inlined with [`Parser::with_collapse_single_field`].
+    collapsed: Option<String>,
+
+    /// Report of the converted fields, collected for [`Parser::explain`] only.
+    pub(crate) explanations: Option<Vec<FieldExplanation>>,
+
+    /// Typed fields of the messages being converted, innermost last, collected for
+    /// [`Parser::parse_both`] only.
+    pub(crate) typed: Option<Vec<Vec<(u64, TypedValue)>>>,
+
+    /// Typed tree of the last converted message.
+    pub(crate) last_typed: Option<TypedValue>,
+}
+
+impl Walk {
+    pub(crate) fn new(parser: &Parser) -> Self {
+        Self {
+            path: Vec::new(),
+            budget: parser.op_budget,
+            rejection: None,
+            collapsed: None,
+            explanations: None,
+            typed: None,
+            last_typed: None,
+        }
+    }
+
+    /// Finish the typed fields of the current message.
+    fn close_typed(&mut self) {
+        if let Some(frames) = &mut self.typed {
+            self.last_typed = frames.pop().map(TypedValue::Message);
+        }
+    }
+
+    /// Add the typed value of the current field to its message.
+    fn record_typed(&mut self, value: &FieldValue, output: FieldOutput) {
+        let Some(frames) = &mut self.typed else {
+            return;
+        };
+        let typed = match value {
+            FieldValue::Varint(v) => TypedValue::Varint(*v),
+            FieldValue::Fixed64(v) => TypedValue::Fixed64(*v),
+            FieldValue::Fixed32(v) => TypedValue::Fixed32(*v),
+            _ if output == FieldOutput::Message => self.last_typed.take().unwrap(),
+            FieldValue::LengthDelimited(bytes) => TypedValue::Bytes(bytes.to_vec()),
+            FieldValue::Message(_) | FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _) => {
+                return;
+            }
+        };
+        if let (Some(fields), Some(number)) = (frames.last_mut(), self.path.last()) {
+            fields.push((*number, typed));
+        }
+    }
+
+    /// Position of the report entry of the field about to be converted.
+    fn mark(&self) -> usize {
+        self.explanations.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Report the conversion of the current field ahead of the entries of its nested fields.
+    fn explain(&mut self, mark: usize, wire_type: WireType, output: FieldOutput) {
+        let rejection = self.rejection.take();
+        if let Some(explanations) = &mut self.explanations {
+            let explanation = FieldExplanation {
+                path: FieldPath::from(self.path.as_slice()),
+                wire_type,
+                rejection: rejection.filter(|_| output != FieldOutput::Message),
+                output,
+            };
+            explanations.insert(mark, explanation);
+        }
+    }
+
+    /// Spend one operation of the budget.
+    fn spend(&mut self) -> Result<(), ParseError> {
+        match &mut self.budget {
+            Some(0) => Err(ParseError::BudgetExhausted),
+            Some(remaining) => {
+                *remaining -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// How to encode bytes fields when converting to JSON.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BytesEncoding {
+    #[default]
+    /// Encode bytes as a string if valid UTF-8, otherwise as base64.
+    Auto,
+
+    /// Encode bytes as base64 string.
+    Base64,
+
+    /// Encode bytes as URL-safe base64 without padding, as expected by grpc-web and JWTs, e.g.
+    /// `"Pj_7_w"`.
+    Base64Url,
+
+    /// Encode bytes as a JSON array of numbers.
+    ByteArray,
+
+    #[cfg(feature = "stfu8")]
+    /// Encode bytes as [stfu8](https://crates.io/crates/stfu8) encoded string.
+    Stfu8,
+
+    /// Encode bytes as a UTF-8 lossy string.
+    StringLossy,
+
+    /// Encode bytes as a base64 data URI, e.g. `data:image/png;base64,iVBORw0KGgo=`.
+    ///
+    /// The MIME type is sniffed from the magic bytes of PNG, JPEG, GIF, WebP and PDF files and
+    /// is `application/octet-stream` for anything else.
+    DataUri,
+
+    /// Encode bytes as a lowercase hex string, e.g. `"0aff"`.
+    Hex,
+}
+
+impl BytesEncoding {
+    /// Every encoding, in declaration order.
+    const ALL: &[BytesEncoding] = &[
+        BytesEncoding::Auto,
+        BytesEncoding::Base64,
+        BytesEncoding::Base64Url,
+        BytesEncoding::ByteArray,
+        #[cfg(feature = "stfu8")]
+        BytesEncoding::Stfu8,
+        BytesEncoding::StringLossy,
+        BytesEncoding::DataUri,
+        BytesEncoding::Hex,
+    ];
+
+    /// Name of the encoding, as written by `Display` and in annotated output.
+    fn name(self) -> &'static str {
+        match self {
+            BytesEncoding::Auto => "auto",
+            BytesEncoding::Base64 => "base64",
+            BytesEncoding::Base64Url => "base64url",
+            BytesEncoding::ByteArray => "byte_array",
+            #[cfg(feature = "stfu8")]
+            BytesEncoding::Stfu8 => "stfu8",
+            BytesEncoding::StringLossy => "string_lossy",
+            BytesEncoding::DataUri => "data_uri",
+            BytesEncoding::Hex => "hex",
+        }
+    }
+}
+
+impl fmt::Display for BytesEncoding {
+    /// Write the name parsed by [`BytesEncoding::from_str`], e.g. `byte_array`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BytesEncoding {
+    type Err = UnknownBytesEncoding;
+
+    /// Parse an encoding name as written by `Display`, e.g. for command line options.
+    ///
+    /// Case, `-` and `_` are ignored, so `bytearray` and `Byte-Array` are accepted as well.
+    /// `stfu8` is only known with the `stfu8` feature.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_'))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        Self::ALL
+            .iter()
+            .find(|encoding| encoding.name().replace('_', "") == name)
+            .copied()
+            .ok_or_else(|| UnknownBytesEncoding(s.to_string()))
+    }
+}
+
+/// Encode bytes in the given encoding.
+fn encode_bytes(bytes: &[u8], encoding: BytesEncoding) -> Value {
+    match encoding {
+        BytesEncoding::Auto => {
+            if let Ok(s) = std::str::from_utf8(bytes) {
+                Value::String(s.to_string())
+            } else {
+                Value::String(BASE64_STANDARD.encode(bytes))
+            }
+        }
+        BytesEncoding::Base64 => Value::String(BASE64_STANDARD.encode(bytes)),
+        BytesEncoding::Base64Url => Value::String(BASE64_URL_SAFE_NO_PAD.encode(bytes)),
+        BytesEncoding::ByteArray => {
+            json!(bytes)
+        }
+        #[cfg(feature = "stfu8")]
+        BytesEncoding::Stfu8 => Value::String(stfu8::encode_u8(bytes)),
+        BytesEncoding::StringLossy => {
+            let s = String::from_utf8_lossy(bytes);
+            Value::String(s.to_string())
+        }
+        BytesEncoding::DataUri => Value::String(format!(
+            "data:{};base64,{}",
+            sniff_mime_type(bytes),
+            BASE64_STANDARD.encode(bytes)
+        )),
+        BytesEncoding::Hex => Value::String(hex_string(bytes)),
+    }
+}
+
+/// Guess the MIME type of a bytes field from its magic bytes.
+fn sniff_mime_type(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => "image/png",
+        [0xff, 0xd8, 0xff, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "image/gif",
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'E',
+            b'B',
+            b'P',
+            ..,
+        ] => "image/webp",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Guess the compression format of a bytes field from its magic bytes.
+fn sniff_compression(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x1f, 0x8b, ..] => Some("gzip"),
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => Some("zstd"),
+        // CMF declares deflate with a window of at most 32 KiB, and the header is a multiple of
+        // 31 as required by RFC 1950.
+        [cmf, flg, ..]
+            if cmf & 0x0f == 8 && cmf >> 4 <= 7 && u16::from_be_bytes([*cmf, *flg]) % 31 == 0 =>
+        {
+            Some("zlib")
+        }
+        _ => None,
+    }
+}
+
+/// Closure set on a parser, e.g. with [`Parser::with_tag_transform`].
+///
+/// Closures can't be compared or hashed, so callbacks are equal if they are the same `Arc`.
+pub(crate) struct Callback<F: ?Sized>(Arc<F>);
+
+/// Transform of decoded tags, see [`Parser::with_tag_transform`].
+pub(crate) type TagTransform = Callback<dyn Fn(u64) -> u64 + Send + Sync + RefUnwindSafe>;
+
+/// Predicate on converted values, see [`Parser::with_value_filter`].
+pub(crate) type ValueFilter =
+    Callback<dyn Fn(&FieldPath, &Value) -> bool + Send + Sync + RefUnwindSafe>;
+
+impl<F: ?Sized> Clone for Callback<F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Callback(..)")
+    }
+}
+
+impl<F: ?Sized> PartialEq for Callback<F> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<F: ?Sized> Eq for Callback<F> {}
+
+impl<F: ?Sized> std::hash::Hash for Callback<F> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+/// Selection of top-level field numbers, see [`Parser::with_field_filter`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FieldFilter {
+    /// Keep only the listed fields.
+    Allow(BTreeSet<u64>),
+
+    /// Keep all fields except the listed ones.
+    Deny(BTreeSet<u64>),
+}
+
+impl FieldFilter {
+    /// Create a filter keeping only the given fields.
+    pub fn allow(numbers: &[u64]) -> Self {
+        Self::Allow(numbers.iter().copied().collect())
+    }
+
+    /// Create a filter dropping the given fields.
+    pub fn deny(numbers: &[u64]) -> Self {
+        Self::Deny(numbers.iter().copied().collect())
+    }
+
+    /// Whether the field with the given number is kept.
+    pub fn accepts(&self, number: u64) -> bool {
+        match self {
+            Self::Allow(numbers) => numbers.contains(&number),
+            Self::Deny(numbers) => !numbers.contains(&number),
+        }
+    }
+}
+
+/// How to render integer values when converting to JSON.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntRadix {
+    #[default]
+    /// Render integers as JSON numbers.
+    Decimal,
+
+    /// Render integers as hexadecimal strings, e.g. `"0x1c"`.
+    Hex,
+
+    /// Render integers as binary strings, e.g. `"0b11100"`.
+    Binary,
+}
+
+/// How to render varints named in the enum map, see [`Parser::with_enum_render`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnumRender {
+    #[default]
+    /// Replace the value with its name, e.g. `"CLOSED"`.
+    NameOnly,
+
+    /// Keep the value as a number, ignoring the enum map.
+    ValueOnly,
+
+    /// Emit both, e.g. `{"value": 2, "name": "CLOSED"}`.
+    Both,
+}
+
+/// How to convert values that may be strings or messages, see [`Parser::with_ambiguity`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ambiguity {
+    #[default]
+    /// Convert them to nested messages.
+    Guess,
+
+    /// Fail with [`ParseError::Ambiguous`].
+    Error,
+
+    /// Convert them to bytes, base64 encoded in [`BytesEncoding::Auto`] mode.
+    PreferBytes,
+
+    /// Convert them to strings.
+    PreferString,
+}
+
+/// Which occurrences of a repeated field to keep, see [`Parser::with_duplicate_policy`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DuplicatePolicy {
+    /// Keep the first occurrence only.
+    First,
+
+    /// Keep the last occurrence only, as protobuf does for singular fields.
+    Last,
+
+    #[default]
+    /// Keep all occurrences in an array.
+    Array,
+}
+
+/// How to render NaN and infinite floats, see [`Parser::with_nonfinite_floats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NonFinite {
+    #[default]
+    /// Render them as the strings `"NaN"`, `"Infinity"` and `"-Infinity"`.
+    String,
+
+    /// Render them as `null`.
+    Null,
+
+    /// Fail the conversion with [`ParseError::NonFiniteFloat`].
+    Error,
+}
+
+/// Element type of a packed repeated field, see [`Parser::with_packed_field_spec`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementType {
+    /// `fixed32`, unsigned 32-bit little-endian integers.
+    Fixed32,
+
+    /// `sfixed32`, signed 32-bit little-endian integers.
+    Sfixed32,
+
+    /// `float`, 32-bit floating point numbers.
+    Float,
+
+    /// `fixed64`, unsigned 64-bit little-endian integers.
+    Fixed64,
+
+    /// `sfixed64`, signed 64-bit little-endian integers.
+    Sfixed64,
+
+    /// `double`, 64-bit floating point numbers.
+    Double,
+
+    /// `uint32`/`uint64` varints, or zigzag encoded if the field is hinted so.
+    Varint,
+
+    /// `sint32`/`sint64`, zigzag encoded varints.
+    Sint,
+
+    /// `int32`/`int64`, two's complement varints.
+    Int,
+
+    /// `bool` varints.
+    Bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::UnwindSafe;
+
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_1() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new();
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({
+            "1": 28,
+            "2": "You",
+            "3": "Me",
+            "4": 43,
+            "5": {
+                "1": "abc123",
+                "2": ""
+            }
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_2() {
+        let data =
+            hex!("0d1c0000001203596f751a024d65202b2a0a0a06616263313233120031ba32a96cc10200003801");
+        let parser = Parser::new();
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({"1":28,"2":"You","3":"Me","4":43,"5":{"1":"abc123","2":""},"6":3029774971578u64,"7":1});
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_3() {
+        let data = hex!(
+            "0a0a6173636f6e2d66756c6c120a6173636f6e2d66756c6c1a1b323032352d30392d30325430393a33373a32362e3033393032385a2203302e312a0474657374421b323032352d30392d30325430393a33373a32362e3033393032385a480068007205302e312e308a016e46756c6c204173636f6e20696d706c656d656e746174696f6e202868617368e280913235362c2041454144e280913132382077697468206e6f6e6365206d61736b696e67202620746167207472756e636174696f6e2c20584f46e280913132382c2043584f46e28091313238292e92012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6c9a011a68747470733a2f2f646f63732e72732f6173636f6e2d66756c6ca2012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6caa014612222f6170692f76312f6372617465732f6173636f6e2d66756c6c2f76657273696f6e731a202f6170692f76312f6372617465732f6173636f6e2d66756c6c2f6f776e657273"
+        );
+        let parser = Parser::new();
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({
+          "1": "ascon-full",
+          "13": 0,
+          "14": "0.1.0",
+          "17": "Full Ascon implementation (hash‑256, AEAD‑128 with nonce masking & tag truncation, XOF‑128, CXOF‑128).",
+          "18": "https://github.com/jjkum/ascon-full",
+          "19": "https://docs.rs/ascon-full",
+          "2": "ascon-full",
+          "20": "https://github.com/jjkum/ascon-full",
+          "21": {
+            "2": "/api/v1/crates/ascon-full/versions",
+            "3": "/api/v1/crates/ascon-full/owners"
+          },
+          "3": "2025-09-02T09:37:26.039028Z",
+          "4": "0.1",
+          "5": "test",
+          "8": "2025-09-02T09:37:26.039028Z",
+          "9": 0
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_encoding_bytearray() {
+        let data = hex!("4a050001020304");
+        let parser = Parser::with_bytes_encoding(BytesEncoding::ByteArray);
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({"9":[0,1,2,3,4]});
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_num_array() {
+        let data = hex!("4a050001020304");
+        let parser = Parser::new();
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({"9":"\u{0000}\u{0001}\u{0002}\u{0003}\u{0004}"});
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_packed_zigzag() {
+        let data = hex!("2203010405");
+        let parser = Parser::new()
+            .with_packed_fields(&[FieldPath::from(4)])
+            .with_zigzag_fields(&[FieldPath::from(4)]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"4": [-1, 2, -3]}));
+
+        let parser = Parser::new().with_packed_fields(&[FieldPath::from(4)]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"4": [1, 4, 5]}));
+    }
+
+    #[test]
+    fn test_parse_packed_partial_varint() {
+        let data = hex!("22020185");
+        let parser = Parser::new()
+            .with_packed_fields(&[FieldPath::from(4)])
+            .with_zigzag_fields(&[FieldPath::from(4)]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"4": "AYU="}));
+    }
+
+    #[test]
+    fn test_parse_zigzag_nested() {
+        let data = hex!("2a020803");
+        let parser = Parser::new().with_zigzag_fields(&[FieldPath::from([5, 1])]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"5": {"1": -2}}));
+    }
+
+    #[test]
+    fn test_from_utf8() {
+        let inputs: [&[u8]; 5] = [b"", b"abc", "h\u{e9}llo".as_bytes(), b"\xc3", b"a\xffb"];
+        for data in inputs {
+            assert_eq!(from_utf8(data), std::str::from_utf8(data).ok());
+        }
+    }
+
+    #[test]
+    fn test_parse_wrapper_fields() {
+        // 1: StringValue "x", 2: Int32Value 42, 3: {1: 1, 2: 2}, 4: Int32Value -5, 5: {1: "y"}
+        let data = hex!("0a030a0178120208 2a 1a0408011002 220b08fbffffffffffffffff01 2a030a0179");
+        let parser = Parser::new()
+            .with_wrapper_fields(&[1.into(), 2.into(), 3.into(), 4.into()])
+            .with_int32_fields(&[FieldPath::from([4, 1])]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": "x", "2": 42, "3": {"1": 1, "2": 2}, "4": -5, "5": {"1": "y"}})
+        );
+
+        // Repeated wrappers keep their values in an array.
+        let data = hex!("0a030a01780a030a0179");
+        let json = Parser::new()
+            .with_wrapper_fields(&[1.into()])
+            .parse(&data)
+            .unwrap();
+        assert_eq!(json, json!({"1": ["x", "y"]}));
+    }
+
+    #[test]
+    fn test_parse_int32() {
+        // 1: -1, 2: -2147483648, 3: -1 as 10-byte varints
+        let data = hex!("08ffffffffffffffffff011080808080f8ffffffff0118ffffffffffffffffff01");
+        let parser = Parser::new().with_int32_fields(&[1.into(), 2.into()]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": -1, "2": -2147483648i64, "3": 18446744073709551615u64})
+        );
+
+        // 4: packed [-1, 7, -2147483648]
+        let data = hex!("2215ffffffffffffffffff010780808080f8ffffffff01");
+        let parser = Parser::new()
+            .with_packed_fields(&[4.into()])
+            .with_int32_fields(&[4.into()]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"4": [-1, 7, -2147483648i64]}));
+    }
+
+    #[test]
+    fn test_parser_shared() {
+        fn assert_auto_traits<T: Send + Sync + UnwindSafe + RefUnwindSafe>() {}
+        assert_auto_traits::<Parser>();
+
+        let parser = Parser::new().with_packed_fields(&[FieldPath::from(4)]);
+        let cloned = parser.clone();
+        assert!(Arc::ptr_eq(&parser.hints, &cloned.hints));
+        assert_eq!(parser, cloned);
+    }
+
+    #[test]
+    fn test_parse_verbose_fixed() {
+        let data = hex!("0d0000c03f2a0909000000000000f83f");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": 1069547520, "5": {"1": 4609434218613702656u64}})
+        );
+
+        let json = Parser::new().with_verbose(true).parse(&data).unwrap();
+        let expected = json!({
+            "1": {"uint": 1069547520, "float": 1.5},
+            "5": {"1": {"uint": 4609434218613702656u64, "two_u32": [0, 1073217536], "double": 1.5}}
+        });
+        assert_eq!(json, expected);
+
+        let data = hex!("090100000002000000");
+        let json = Parser::new().with_verbose(true).parse(&data).unwrap();
+        assert_eq!(json["1"]["two_u32"], json!([1, 2]));
+    }
+
+    #[test]
+    fn test_parse_canonical_floats() {
+        let data = hex!("0dcdcccc3d119a9999999999b93f1d000080ff");
+        let parser = Parser::new().with_verbose(true);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["1"]["float"], json!(0.10000000149011612));
+        assert_eq!(json["2"]["double"], json!(0.1));
+
+        let json = parser.with_canonical_floats(true).parse(&data).unwrap();
+        let expected = json!({
+            "1": {"uint": 1036831949, "float": "0.1"},
+            "2": {
+                "uint": 4591870180066957722u64,
+                "two_u32": [2576980378u32, 1069128089],
+                "double": "0.1"
+            },
+            "3": {"uint": 4286578688u64, "float": "-Infinity"}
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_nonfinite_floats() {
+        let data = hex!("11000000000000f87f1a08000000000000f07f");
+        let parser = Parser::new()
+            .with_verbose(true)
+            .with_packed_field_spec(3, ElementType::Double);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["2"]["double"], json!("NaN"));
+        assert_eq!(json["3"], json!(["Infinity"]));
+
+        let parser = parser.with_nonfinite_floats(NonFinite::Null);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["2"]["double"], Value::Null);
+        assert_eq!(json["3"], json!([null]));
+
+        let parser = parser.with_nonfinite_floats(NonFinite::Error);
+        assert_eq!(
+            parser.try_parse(&data),
+            Err(ParseError::NonFiniteFloat { field: 2 })
+        );
+        assert_eq!(parser.parse(&data), None);
+        let json = parser.parse(&hex!("119a9999999999b93f")).unwrap();
+        assert_eq!(json["2"]["double"], json!(0.1));
+
+        // A guessed nested candidate with a NaN is rejected instead of failing the parse.
+        let data = hex!("0a050d0000c07f 1001");
+        let json = parser.try_parse(&data).unwrap();
+        assert_eq!(json, json!({"1": "DQAAwH8=", "2": 1}));
+        let json = parser
+            .clone()
+            .with_typed_json(true)
+            .try_parse(&data)
+            .unwrap();
+        assert_eq!(json["1"]["type"], json!("bytes"));
+    }
+
+    #[test]
+    fn test_parse_packed_scalars() {
+        let parser = Parser::new();
+        let values = parser.parse_packed_scalars(&hex!("01ac027f"), ElementType::Varint);
+        assert_eq!(values, Some(vec![json!(1), json!(300), json!(127)]));
+        let values = parser.parse_packed_scalars(&hex!("01ac02"), ElementType::Sint);
+        assert_eq!(values, Some(vec![json!(-1), json!(150)]));
+        assert_eq!(
+            parser.parse_packed_scalars(&hex!("01ac"), ElementType::Varint),
+            None
+        );
+
+        let data = hex!("2a00000000000000ffffffffffffffff");
+        let values = parser.parse_packed_scalars(&data, ElementType::Fixed64);
+        assert_eq!(values, Some(vec![json!(42), json!(u64::MAX)]));
+        assert_eq!(
+            parser.parse_packed_scalars(&data[..12], ElementType::Fixed64),
+            None
+        );
+        assert_eq!(
+            parser.parse_packed_scalars(&[], ElementType::Fixed64),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_parse_raw_hex() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new().with_verbose(true).with_raw_hex(true);
+        let json = parser.parse(&data).unwrap();
+        let raw: String = (1..=5)
+            .map(|field| json[field.to_string()]["raw"].as_str().unwrap())
+            .collect();
+        assert_eq!(raw, hex_string(&data));
+        assert_eq!(json["4"], json!({"value": 43, "raw": "202b"}));
+        assert_eq!(
+            json["5"]["value"]["1"],
+            json!({"value": "abc123", "raw": "0a06616263313233"})
+        );
+
+        let json = parser.clone().with_typed_json(true).parse(&data).unwrap();
+        assert_eq!(
+            json["4"],
+            json!({"type": "varint", "value": 43, "raw": "202b"})
+        );
+        assert_eq!(json["5"]["raw"], json!("2a0a0a066162633132331200"));
+
+        let json = Parser::new().with_raw_hex(true).parse(&data).unwrap();
+        assert_eq!(json, Parser::new().parse(&data).unwrap());
+    }
+
+    #[test]
+    fn test_parse_duplicate_policy() {
+        let data = hex!("08012a0408010802080330012a020804");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": [1, 3], "5": [{"1": [1, 2]}, {"1": 4}], "6": 1})
+        );
+
+        let parser = Parser::new().with_duplicate_policy(DuplicatePolicy::First);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 1, "5": {"1": 1}, "6": 1}));
+
+        let parser = Parser::new().with_duplicate_policy(DuplicatePolicy::Last);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 3, "5": {"1": 4}, "6": 1}));
+
+        let parser = parser.with_repeated_fields(&[FieldPath::from(&[5, 1][..])]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 3, "5": {"1": [4]}, "6": 1}));
+    }
+
+    #[test]
+    fn test_parse_garbage_field() {
+        let data = hex!("0801ffffffffffffffffffffff");
+        assert_eq!(Parser::new().parse(&data), Some(json!({"1": 1})));
+
+        let parser = Parser::new().with_garbage_field(Some("__garbage__"));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": 1, "__garbage__": "ffffffffffffffffffffff"})
+        );
+
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        assert_eq!(parser.parse(&data), Parser::new().parse(&data));
+    }
+
+    #[test]
+    fn test_parse_length_field() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new().with_length_field(Some("__len__"));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["__len__"], 28);
+        assert_eq!(json["5"]["__len__"], 10);
+        assert_eq!(json["2"], "You");
+
+        let json = parser.parse(&hex!("0801")).unwrap();
+        assert_eq!(json, json!({"1": 1, "__len__": 2}));
+    }
+
+    #[test]
+    fn test_parse_collapse_single_field() {
+        // 1: {2: {3: 4}}, 5: [{1: "a"}, {1: "b", 2: {3: 1}}], 6: {}
+        let data = hex!("0a04 1202 1804 2000 2a030a0161 2a070a016212021801 3200");
+        let parser = Parser::new().with_collapse_single_field(true);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "1.2.3": 4,
+                "4": 0,
+                "5": [{"1": "a"}, {"1": "b", "2.3": 1}],
+                "6": ""
+            })
+        );
+
+        let parser = parser.with_field_names(&[(FieldPath::from([1, 2]), "a.b")]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["1.a\\.b.3"], 4);
+
+        // Objects of embedded JSON are kept as is.
+        let data = hex!("0a07 7b2261223a317d 1202 0801");
+        let parser = Parser::new()
+            .with_collapse_single_field(true)
+            .with_json_fields(&[FieldPath::from(1)]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"a": 1}, "2.1": 1}));
+    }
+
+    #[test]
+    fn test_parse_partial_nested() {
+        let data = hex!("2a070a036162630f00");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"5": "\n\u{3}abc\u{f}\u{0}"}));
+
+        let parser = Parser::new().with_partial_nested(true);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"5": {"1": "abc"}}));
+        let json = parser
+            .clone()
+            .with_garbage_field(Some("__garbage__"))
+            .parse(&data)
+            .unwrap();
+        assert_eq!(json, json!({"5": {"1": "abc", "__garbage__": "0f00"}}));
+
+        // Too little of the value is valid.
+        let data = hex!("2a0708010f00000000");
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, Parser::new().parse(&data).unwrap());
+    }
+
+    #[test]
+    fn test_parse_strict_nested() {
+        let data = hex!("0a030801ff 12020801");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"1": 1}, "2": {"1": 1}}));
+
+        let parser = Parser::new().with_strict_nested(true);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": "CAH/", "2": {"1": 1}}));
+
+        // Partially valid messages are rejected too.
+        let data = hex!("2a070a036162630f00");
+        let json = parser.with_partial_nested(true).parse(&data).unwrap();
+        assert_eq!(json, json!({"5": "\n\u{3}abc\u{f}\u{0}"}));
+    }
+
+    #[test]
+    fn test_parse_max_string_len() {
+        // 1: "abcdefghijklmnopqrst", 2: "h\u{e9}llo", 3: 0xff 0xfe 0xfd 0xfc 0xfb, 4: {1: "abcdef"}
+        let data = hex!(
+            "0a146162636465666768696a6b6c6d6e6f7071727374 120668c3a96c6c6f 1a05fffefdfcfb 22080a06616263646566"
+        );
+        let parser = Parser::new().with_max_string_len(2);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "1": "ab\u{2026}(+18 bytes)",
+                "2": "h\u{2026}(+5 bytes)",
+                "3": "//4=\u{2026}(+3 bytes)",
+                "4": {"1": "ab\u{2026}(+4 bytes)"},
+            })
+        );
+
+        let json = Parser::new().with_max_string_len(6).parse(&data).unwrap();
+        assert_eq!(json["2"], json!("h\u{e9}llo"));
+        assert_eq!(json["4"], json!({"1": "abcdef"}));
+
+        let mut parser = parser;
+        parser.bytes_encoding = BytesEncoding::ByteArray;
+        let json = parser.parse(&hex!("1a05fffefdfcfb")).unwrap();
+        assert_eq!(json, json!({"3": [255, 254, 253, 252, 251]}));
+
+        #[cfg(feature = "stfu8")]
+        {
+            parser.bytes_encoding = BytesEncoding::Stfu8;
+            let json = parser.parse(&hex!("1a030a0361")).unwrap();
+            assert_eq!(json, json!({"3": "\\n\\x03\u{2026}(+1 bytes)"}));
+        }
+    }
+
+    #[test]
+    fn test_field_type_profile() {
+        let a = hex!("0801 1203596f75 1d00000000");
+        let b = hex!("0a03596f75 1002 ff");
+        let profile = Parser::new().field_type_profile(&[&a, &b]);
+        let expected = HashMap::from([
+            (
+                1,
+                HashSet::from([WireType::Varint, WireType::LengthDelimited]),
+            ),
+            (
+                2,
+                HashSet::from([WireType::LengthDelimited, WireType::Varint]),
+            ),
+            (3, HashSet::from([WireType::Fixed32])),
+        ]);
+        assert_eq!(profile, expected);
+        assert!(Parser::new().field_type_profile(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_msb_first_varints() {
+        // 1: 0x82 0x2c, 2: "abc" with a two-byte length of 3, 3: 1
+        let data = hex!("08822c 1280036162631801");
+        let parser = Parser::new().with_msb_first_varints(true);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 300, "2": "abc", "3": 1}));
+        assert_eq!(
+            Parser::new().parse(&hex!("08822c")).unwrap(),
+            json!({"1": 5634})
+        );
+
+        // The fast path for scalar messages decodes the same way.
+        let json = parser.parse(&hex!("08822c1001")).unwrap();
+        assert_eq!(json, json!({"1": 300, "2": 1}));
+    }
+
+    #[test]
+    fn test_parse_tag_transform() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new().with_tag_transform(|tag| tag);
+        assert_eq!(parser.parse(&data), Parser::new().parse(&data));
+
+        // 1: 1, 2: "You", 5: {1: 1}, with every tag offset by one field number.
+        let data = hex!("1001 1a03596f75 32021001");
+        let parser = Parser::new().with_tag_transform(|tag| tag.wrapping_sub(0x08));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 1, "2": "You", "5": {"1": 1}}));
+        assert_eq!(
+            parser.parse(&hex!("10011801")).unwrap(),
+            json!({"1": 1, "2": 1})
+        );
+
+        assert_eq!(parser.clone(), parser);
+        assert_ne!(
+            parser,
+            Parser::new().with_tag_transform(|tag| tag.wrapping_sub(0x08))
+        );
+    }
+
+    #[test]
+    fn test_parse_base64_url() {
+        let data = hex!("0a043e3ffbff");
+        let json = Parser::with_bytes_encoding(BytesEncoding::Base64)
+            .parse(&data)
+            .unwrap();
+        assert_eq!(json, json!({"1": "Pj/7/w=="}));
+        let parser = Parser::with_bytes_encoding(BytesEncoding::Base64Url);
+        assert_eq!(parser.parse(&data).unwrap(), json!({"1": "Pj_7_w"}));
+
+        // Fallbacks to base64 are URL-safe as well.
+        let json = parser
+            .clone()
+            .with_opaque_fields(&[FieldPath::from(1)])
+            .parse(&hex!("0a026869"))
+            .unwrap();
+        assert_eq!(json, json!({"1": "aGk"}));
+        let json = parser
+            .with_max_output_depth(1)
+            .parse(&hex!("0a0308ff3f"))
+            .unwrap();
+        assert_eq!(json, json!({"1": "CP8_"}));
+    }
+
+    #[test]
+    fn test_bytes_encoding_from_str() {
+        for encoding in BytesEncoding::ALL {
+            let name = encoding.to_string();
+            assert_eq!(name.parse::<BytesEncoding>(), Ok(*encoding));
+        }
+        assert_eq!(BytesEncoding::ByteArray.to_string(), "byte_array");
+        assert_eq!("Byte_Array".parse(), Ok(BytesEncoding::ByteArray));
+        assert_eq!("bytearray".parse(), Ok(BytesEncoding::ByteArray));
+        assert_eq!("string-lossy".parse(), Ok(BytesEncoding::StringLossy));
+        assert_eq!(
+            "base65".parse::<BytesEncoding>(),
+            Err(UnknownBytesEncoding("base65".to_string()))
+        );
+
+        let json = Parser::with_bytes_encoding(BytesEncoding::Hex)
+            .parse(&hex!("0a02fffe"))
+            .unwrap();
+        assert_eq!(json, json!({"1": "fffe"}));
+    }
+
+    #[test]
+    fn test_scalar_message_fast_path() {
+        let data = hex!("0801089601109601190100000000000000250200000008ffffffffffffffffff01");
+        for parser in [
+            Parser::new(),
+            Parser::new().with_int_radix(IntRadix::Hex),
+            Parser::new().with_duplicate_policy(DuplicatePolicy::Last),
+        ] {
+            let fast = parser.scalar_message_to_json(&data).unwrap();
+            let msg = parser.parse_once(&data);
+            let general = parser
+                .message_to_json(&msg, Some(&data), &mut Walk::new(&parser))
+                .unwrap()
+                .unwrap();
+            assert_eq!(fast, general);
+            assert_eq!(parser.parse(&data).unwrap(), general);
+        }
+        assert_eq!(
+            Parser::new().parse(&data).unwrap(),
+            json!({"1": [1, 150, u64::MAX], "2": 150, "3": 1, "4": 2})
+        );
+
+        // Length-delimited fields and truncated values take the general path.
+        let parser = Parser::new();
+        assert_eq!(parser.scalar_message_to_json(&hex!("08011203596f75")), None);
+        assert_eq!(parser.scalar_message_to_json(&hex!("080119010000")), None);
+        assert_eq!(parser.parse(&hex!("080119010000")), Some(json!({"1": 1})));
+    }
+
+    #[test]
+    fn test_parse_flatten() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a0661626331323312004a0108");
+        let parser = Parser::new()
+            .with_flatten(true)
+            .with_repeated_fields(&[9.into()]);
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({
+            "1": 28,
+            "2": "You",
+            "3": "Me",
+            "4": 43,
+            "5.1": "abc123",
+            "5.2": "",
+            "9.0": "\u{8}"
+        });
+        assert_eq!(json, expected);
+
+        let parser = parser.with_field_names(&[(FieldPath::from(&[5, 1][..]), "a.b\\c")]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["5.a\\.b\\\\c"], json!("abc123"));
+
+        let json = Parser::new().with_flatten(true).parse(&hex!("08010802"));
+        assert_eq!(json, Some(json!({"1.0": 1, "1.1": 2})));
+    }
+
+    #[test]
+    fn test_packed_candidates() {
+        let parser = Parser::new();
+        assert_eq!(
+            parser.packed_candidates(&hex!("0102030405060708")),
+            vec![
+                ElementType::Varint,
+                ElementType::Fixed32,
+                ElementType::Fixed64
+            ]
+        );
+        assert_eq!(
+            parser.packed_candidates(&hex!("0000803f000000400000c0ff")),
+            vec![ElementType::Fixed32]
+        );
+        assert_eq!(
+            parser.packed_candidates(&hex!("01ac02ffffff7f")),
+            vec![ElementType::Varint]
+        );
+        assert!(parser.packed_candidates(&hex!("01ac02ffffffff")).is_empty());
+    }
+
+    #[test]
+    fn test_parse_expecting() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new();
+        assert_eq!(
+            parser.parse_expecting(&data, &[1, 5]),
+            Ok(parser.parse(&data).unwrap())
+        );
+        assert_eq!(
+            parser.parse_expecting(&data, &[7, 1, 6]),
+            Err(MissingFields {
+                missing: vec![7, 6]
+            })
+        );
+        assert_eq!(
+            parser.parse_expecting(&[], &[1]),
+            Err(MissingFields { missing: vec![1] })
+        );
+        assert_eq!(parser.parse_expecting(&[], &[]), Ok(json!({})));
+    }
+
+    #[test]
+    fn test_parse_verbose_compressed() {
+        let data = hex!("0a0c1f8b0800000000000003ff00");
+        let json = Parser::new().with_verbose(true).parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": {"encoding": "base64", "compressed": "gzip", "value": "H4sIAAAAAAAAA/8A"}})
+        );
+        assert_eq!(
+            Parser::new().parse(&data).unwrap(),
+            json!({"1": "H4sIAAAAAAAAA/8A"})
+        );
+
+        let json = Parser::new()
+            .with_verbose(true)
+            .with_typed_json(true)
+            .parse(&data)
+            .unwrap();
+        assert_eq!(json["1"]["compressed"], json!("gzip"));
+        assert_eq!(json["1"]["type"], json!("bytes"));
+
+        let data = hex!("0a0478da0300");
+        let json = Parser::new().with_verbose(true).parse(&data).unwrap();
+        assert_eq!(json["1"]["compressed"], json!("zlib"));
+    }
+
+    #[test]
+    fn test_parse_max_output_depth() {
+        // 1 -> 2 -> 3 -> 4: "deep"
+        let data = hex!("0a0a12081a06220464656570");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"2": {"3": {"4": "deep"}}}}));
+
+        let json = Parser::new().with_max_output_depth(2).parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"2": "GgYiBGRlZXA="}}));
+        let json = Parser::new().with_max_output_depth(1).parse(&data).unwrap();
+        assert_eq!(json, json!({"1": "EggaBiIEZGVlcA=="}));
+        let json = Parser::new().with_max_output_depth(4).parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"2": {"3": {"4": "deep"}}}}));
+    }
+
+    #[test]
+    fn test_parse_scaled_fields() {
+        // 1: 12345, 2: 5, 3: -5 (sint64), 4: 10
+        let data = hex!("08b96010051809200a");
+        let parser = Parser::new()
+            .with_scaled_fields(&[(1.into(), 2), (2.into(), 2), (3.into(), 2), (4.into(), -3)])
+            .with_zigzag_fields(&[3.into()]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": "123.45", "2": "0.05", "3": "-0.05", "4": "10000"})
+        );
+
+        let parser =
+            Parser::new().with_scaled_fields(&[(1.into(), i32::MAX), (2.into(), i32::MIN)]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["1"], json!(12345));
+        assert_eq!(json["2"], json!(5));
+    }
+
+    #[test]
+    fn test_parse_opaque_fields() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new().with_opaque_fields(&[2.into(), 5.into()]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["2"], json!("WW91"));
+        assert_eq!(json["3"], json!("Me"));
+        assert_eq!(json["5"], json!("CgZhYmMxMjMSAA=="));
+
+        let mut parser = parser;
+        parser.bytes_encoding = BytesEncoding::ByteArray;
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["2"], json!([89, 111, 117]));
+    }
+
+    #[test]
+    fn test_parse_ambiguity() {
+        // Field 2 holds "\n\u{3}abc", which is text as well as a message with field 1: "abc".
+        let data = hex!("080112050a036162631a024d65");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json["2"], json!({"1": "abc"}));
+
+        let parser = Parser::new().with_ambiguity(Ambiguity::Error);
+        assert_eq!(
+            parser.try_parse(&data),
+            Err(ParseError::Ambiguous { path: 2.into() })
+        );
+        let hinted = parser.clone().with_string_fields(&[2.into()]);
+        assert_eq!(hinted.parse(&data).unwrap()["2"], json!("\n\u{3}abc"));
+
+        let parser = parser.with_ambiguity(Ambiguity::PreferString);
+        assert_eq!(parser.parse(&data).unwrap()["2"], json!("\n\u{3}abc"));
+        let truncated = parser.clone().with_max_string_len(2);
+        assert_eq!(
+            truncated.parse(&data).unwrap()["2"],
+            json!("\n\u{3}\u{2026}(+3 bytes)")
+        );
+        let parser = parser.with_ambiguity(Ambiguity::PreferBytes);
+        assert_eq!(parser.parse(&data).unwrap()["2"], json!("CgNhYmM="));
+        assert_eq!(parser.parse(&data).unwrap()["3"], json!("Me"));
+    }
+
+    #[test]
+    fn test_parse_op_budget() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let json = Parser::new().with_op_budget(64).try_parse(&data);
+        assert!(json.is_ok());
+
+        let json = Parser::new().with_op_budget(8).try_parse(&data);
+        assert_eq!(json, Err(ParseError::BudgetExhausted));
+        assert_eq!(Parser::new().with_op_budget(8).parse(&data), None);
+    }
+
+    #[test]
+    fn test_parse_op_budget_flat_message() {
+        // 1000 varint fields cost one operation each to decode and to convert.
+        let data = hex!("0801").repeat(1000);
+        let parser = Parser::new().with_op_budget(1500);
+        assert_eq!(parser.try_parse(&data), Err(ParseError::BudgetExhausted));
+        assert!(Parser::new().with_op_budget(2001).try_parse(&data).is_ok());
+
+        // The fields of a nested candidate are charged while it is decoded.
+        let nested = [&hex!("0ad007")[..], &hex!("0801").repeat(500)].concat();
+        let parser = Parser::new().with_op_budget(400);
+        assert_eq!(parser.try_parse(&nested), Err(ParseError::BudgetExhausted));
+    }
+
+    #[test]
+    fn test_parse_min_nested_len() {
+        let data = hex!("0a0208011203616263");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"1": 1}, "2": "abc"}));
+
+        let json = Parser::new().with_min_nested_len(3).parse(&data).unwrap();
+        assert_eq!(json, json!({"1": "\u{0008}\u{0001}", "2": "abc"}));
+
+        // 1: {1: 150, 2: 2}, five bytes that are not valid UTF-8.
+        let data = hex!("0a050896011002");
+        let nested = json!({"1": {"1": 150, "2": 2}});
+        assert_eq!(Parser::new().parse(&data).unwrap(), nested);
+        let parser = Parser::new().with_min_nested_len(5);
+        assert_eq!(parser.parse(&data).unwrap(), nested);
+        let parser = Parser::new().with_min_nested_len(6);
+        assert_eq!(parser.parse(&data).unwrap(), json!({"1": "CJYBEAI="}));
+        let report = parser.explain(&data);
+        assert_eq!(report[0].rejection, Some(NestedRejection::TooShort));
+        assert_eq!(report[0].output, FieldOutput::Bytes);
+    }
+
+    #[test]
+    fn test_parse_with_header_skip() {
+        let data = hex!("cafe0001082a");
+        let parser = Parser::new();
+        assert_eq!(
+            parser.parse_with_header_skip(&data, 4),
+            Ok(json!({"1": 42}))
+        );
+        assert_eq!(
+            parser.parse_with_header_skip(&data, 7),
+            Err(ParseError::HeaderOutOfBounds { skip: 7, len: 6 })
+        );
+        assert_eq!(
+            parser.parse_with_header_skip(&data, 6),
+            Err(ParseError::NotAMessage)
+        );
+    }
+
+    #[test]
+    fn test_parse_int_radix() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new().with_int_radix(IntRadix::Hex);
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({
+            "1": "0x1c",
+            "2": "You",
+            "3": "Me",
+            "4": "0x2b",
+            "5": {
+                "1": "abc123",
+                "2": ""
+            }
+        });
+        assert_eq!(json, expected);
+
+        let data = hex!("0803");
+        let parser = Parser::new()
+            .with_int_radix(IntRadix::Binary)
+            .with_zigzag_fields(&[FieldPath::from(1)]);
+        assert_eq!(parser.parse(&data).unwrap(), json!({"1": "-0b10"}));
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        let strict = Parser::new().with_strict(true);
+        let cases = [
+            (&hex!("082a1203596f75190100000000")[..], WireType::Fixed64),
+            (&hex!("082a1203596f751d0100")[..], WireType::Fixed32),
+            (
+                &hex!("082a1203596f751a05616263")[..],
+                WireType::LengthDelimited,
+            ),
+            (&hex!("082a1203596f751a")[..], WireType::LengthDelimited),
+        ];
+        for (data, wire_type) in cases {
+            let json = Parser::new().try_parse(data);
+            assert_eq!(json, Ok(json!({"1": 42, "2": "You"})), "{wire_type:?}");
+            assert_eq!(
+                strict.try_parse(data),
+                Err(ParseError::Incomplete {
+                    field: 3,
+                    wire_type
+                })
+            );
+        }
+
+        // A nested message with a truncated field is emitted as bytes instead.
+        let data = hex!("082a2a04082a11ff");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 42, "5": "CCoR/w=="}));
+    }
 
-impl Parser {
-    /// Create a new parser.
-    pub fn new() -> Self {
-        Self::default()
+    #[test]
+    fn test_parse_strict_invalid_wire_types() {
+        let parser = Parser::new().with_strict(true);
+        for wire_type in [6, 7] {
+            let data = [0x08, 0x2a, 0x10 | wire_type, 0x01];
+            let err = parser.try_parse(&data).unwrap_err();
+            assert_eq!(
+                err,
+                ParseError::InvalidWireType {
+                    field: 2,
+                    wire_type
+                }
+            );
+            assert_eq!(
+                err.to_string(),
+                format!("field 2 has invalid wire type {wire_type}")
+            );
+            // Lenient parsing keeps the fields before it.
+            assert_eq!(Parser::new().parse(&data), Some(json!({"1": 42})));
+        }
     }
 
-    /// Create a new parser with the given bytes encoding method.
-    pub fn with_bytes_encoding(bytes_encoding: BytesEncoding) -> Self {
-        Self { bytes_encoding }
+    #[test]
+    fn test_parse_strict() {
+        let data = hex!("082aff");
+        assert_eq!(Parser::new().try_parse(&data), Ok(json!({"1": 42})));
+        let parser = Parser::new().with_strict(true);
+        assert_eq!(
+            parser.try_parse(&data),
+            Err(ParseError::Garbage { offset: 2 })
+        );
+        assert_eq!(parser.parse(&data), None);
+
+        let data = hex!("082a0f01");
+        assert_eq!(
+            parser.try_parse(&data),
+            Err(ParseError::InvalidWireType {
+                field: 1,
+                wire_type: 7
+            })
+        );
+
+        let data = hex!("082a151c00");
+        assert_eq!(
+            parser.try_parse(&data),
+            Err(ParseError::Incomplete {
+                field: 2,
+                wire_type: WireType::Fixed32
+            })
+        );
+
+        let data = hex!("082a2a0a0a066162633132331200");
+        assert!(parser.try_parse(&data).is_ok());
     }
 
-    /// Parse a protobuf message from the given byte slice and convert it to JSON.
-    pub fn parse(&self, data: &[u8]) -> Option<Value> {
-        self.parse_to_json(data, true)
+    #[test]
+    fn test_parse_zero_length_fields() {
+        let data = hex!("0a000a000a00");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": ["", "", ""]}));
     }
 
-    /// Recursively parse a protobuf message and convert it to JSON.
-    fn parse_to_json(&self, data: &[u8], first_layer: bool) -> Option<Value> {
-        if data.is_empty() {
-            return None;
-        }
+    #[test]
+    fn test_parse_once_consumes_incomplete() {
+        let data = hex!("082a151c0008");
+        let msg = Parser::new().parse_once(&data);
+        assert_eq!(msg.fields.len(), 2);
+        assert_eq!(
+            msg.fields[1].value,
+            FieldValue::Incomplete(WireType::Fixed32, &hex!("1c0008"))
+        );
+    }
 
-        // Check if the data is valid UTF-8 and not control characters
-        let utf8_str = simdutf8::basic::from_utf8(data);
-        if !first_layer && utf8_str.is_ok_and(|s| s.chars().all(|c| !c.is_control())) {
-            return None;
-        }
+    #[test]
+    fn test_parse_field_filter() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new().with_field_filter(FieldFilter::allow(&[1, 5]));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 28, "5": {"1": "abc123", "2": ""}}));
 
-        let Message { fields, garbage } = self.parse_once(data);
-        if fields.is_empty() {
-            return None;
-        }
-        // If not the first layer, and the data is valid UTF-8 and contains garbage or reserved fields, return None
-        if !first_layer
-            && utf8_str.is_ok()
-            && (garbage.is_some()
-                || fields
-                    .iter()
-                    .any(|f| RESERVED_FIELD_NUMBER.contains(&f.number)))
-        {
-            return None;
-        }
+        let parser = Parser::new().with_field_filter(FieldFilter::deny(&[1, 5]));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"2": "You", "3": "Me", "4": 43}));
+    }
 
-        let mut map = Map::new();
-        for field in fields {
-            let key = field.number.to_string();
-            let value = match field.value {
-                FieldValue::Varint(v) => Value::Number((v as usize).into()),
-                FieldValue::Fixed64(v) => Value::Number(v.into()),
-                FieldValue::Fixed32(v) => Value::Number(v.into()),
-                FieldValue::LengthDelimited(bytes) => {
-                    if let Some(nested) = self.parse_to_json(bytes, false) {
-                        nested
-                    } else {
-                        match self.bytes_encoding {
-                            BytesEncoding::Auto => {
-                                if let Ok(s) = std::str::from_utf8(bytes) {
-                                    Value::String(s.to_string())
-                                } else {
-                                    Value::String(BASE64_STANDARD.encode(bytes))
-                                }
-                            }
-                            BytesEncoding::Base64 => Value::String(BASE64_STANDARD.encode(bytes)),
-                            BytesEncoding::ByteArray => {
-                                json!(bytes)
-                            }
-                            #[cfg(feature = "stfu8")]
-                            BytesEncoding::Stfu8 => Value::String(stfu8::encode_u8(bytes)),
-                            BytesEncoding::StringLossy => {
-                                let s = String::from_utf8_lossy(bytes);
-                                Value::String(s.to_string())
-                            }
-                        }
-                    }
-                }
-                FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _) => match first_layer {
-                    true => break,
-                    false => return None,
-                },
-            };
+    #[test]
+    fn test_parse_enum_map() {
+        let names = HashMap::from([(0, "UNKNOWN".to_string()), (1, "OK".to_string())]);
+        let parser = Parser::new().with_enum_map(&[(1, names.clone()), (2, names)]);
 
-            if let Some(existing) = map.get_mut(&key) {
-                if let Value::Array(arr) = existing {
-                    arr.push(value);
-                } else {
-                    let old_value = existing.clone();
-                    *existing = Value::Array(vec![old_value, value]);
-                }
-            } else {
-                map.insert(key, value);
-            }
-        }
+        let data = hex!("080010011002220208011801");
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": "UNKNOWN", "2": ["OK", 2], "3": 1, "4": {"1": 1}})
+        );
+    }
+
+    #[test]
+    fn test_parse_enum_render() {
+        let names = HashMap::from([(2, "CLOSED".to_string())]);
+        let parser = Parser::new().with_enum_map(&[(1, names)]);
+        let data = hex!("0802 0803 1002");
+
+        let json = parser
+            .clone()
+            .with_enum_render(EnumRender::NameOnly)
+            .parse(&data);
+        assert_eq!(json.unwrap(), json!({"1": ["CLOSED", 3], "2": 2}));
+
+        let json = parser
+            .clone()
+            .with_enum_render(EnumRender::ValueOnly)
+            .parse(&data);
+        assert_eq!(json.unwrap(), json!({"1": [2, 3], "2": 2}));
+
+        let json = parser.with_enum_render(EnumRender::Both).parse(&data);
+        assert_eq!(
+            json.unwrap(),
+            json!({"1": [{"value": 2, "name": "CLOSED"}, 3], "2": 2})
+        );
+    }
+
+    #[test]
+    fn test_parse_force_nested() {
+        let data = hex!("0a0428413031120261621a00");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": "(A01", "2": "ab", "3": ""}));
 
-        Some(Value::Object(map))
+        let paths = [FieldPath::from(1), FieldPath::from(2), FieldPath::from(3)];
+        let json = Parser::new().force_nested(&paths).parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"5": 65, "6": 49}, "2": "ab", "3": {}}));
     }
 
-    /// Parse a protobuf message from the given byte slice without recursion.
-    pub fn parse_once<'a>(&self, mut data: &'a [u8]) -> Message<'a> {
-        let mut msg = Message {
-            fields: vec![],
-            garbage: None,
+    #[test]
+    fn test_parse_data_uri() {
+        let data = hex!("0a0889504e470d0a1a0a12020102");
+        let json = Parser::with_bytes_encoding(BytesEncoding::DataUri)
+            .parse(&data)
+            .unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "1": "data:image/png;base64,iVBORw0KGgo=",
+                "2": "data:application/octet-stream;base64,AQI="
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_unwrap_singletons() {
+        let parser = Parser::new().with_packed_fields(&[FieldPath::from(4)]);
+        let single = hex!("22012a");
+        let double = hex!("22012a22012b");
+        assert_eq!(parser.parse(&single).unwrap(), json!({"4": [42]}));
+
+        let parser = parser.with_unwrap_singletons(true);
+        assert_eq!(parser.parse(&single).unwrap(), json!({"4": 42}));
+        assert_eq!(parser.parse(&double).unwrap(), json!({"4": [42, 43]}));
+
+        let parser = parser.with_repeated_fields(&[FieldPath::from(4)]);
+        assert_eq!(parser.parse(&single).unwrap(), json!({"4": [42]}));
+    }
+
+    #[test]
+    fn test_parse_once_into() {
+        let first = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let second = hex!("0801ff");
+        let parser = Parser::new();
+
+        let mut msg = Message::default();
+        parser.parse_once_into(&first, &mut msg);
+        assert_eq!(msg, parser.parse_once(&first));
+        parser.parse_once_into(&second, &mut msg);
+        assert_eq!(msg, parser.parse_once(&second));
+        assert_eq!(msg.fields.len(), 1);
+        assert_eq!(msg.garbage, Some(&hex!("ff")[..]));
+    }
+
+    #[test]
+    fn test_parse_oneof_groups() {
+        let parser = Parser::new().with_oneof_groups(&[("payload", &[2, 3]), ("id", &[6, 7])]);
+        let data = hex!("08011a024d65");
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": 1, "payload": {"field": 3, "value": "Me"}})
+        );
+
+        let data = hex!("08011a024d651203596f75");
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": 1, "3": "Me", "payload": {"field": 2, "value": "You"}})
+        );
+    }
+
+    #[test]
+    fn test_parse_field_names() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a0661626331323312002a020801");
+        let parser = Parser::new().with_field_names(&[
+            (FieldPath::from(2), "name"),
+            (FieldPath::from(5), "items"),
+            (FieldPath::from([5, 1]), "id"),
+        ]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "1": 28,
+                "name": "You",
+                "3": "Me",
+                "4": 43,
+                "items": [{"id": "abc123", "2": ""}, {"id": 1}]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_interleaved_repeated_field() {
+        // 1, 2, 1, 3, 1, all varints, which takes the fast path for scalar messages.
+        let data = hex!("0801 1002 0803 1804 0805");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": [1, 3, 5], "2": 2, "3": 4}));
+
+        // The same layout with a string in field 2 takes the general path.
+        let data = hex!("0801 12026869 0803 1804 0805");
+        let parser = Parser::new();
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": [1, 3, 5], "2": "hi", "3": 4}));
+        let mut out = Vec::new();
+        parser.parse_to_writer(&data, &mut out).unwrap();
+        assert_eq!(out, parser.parse_to_string(&data).unwrap().as_bytes());
+
+        let json = parser.with_verbose(true).parse(&data).unwrap();
+        assert_eq!(json["1"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_parse_huge_field_numbers() {
+        let tagged = |number: u64| {
+            let mut data = hex!("0801").to_vec();
+            crate::encode_var(number << 3, &mut data);
+            data.push(0x00);
+            data
         };
+        let parser = Parser::new().with_garbage_field(Some("__garbage__"));
 
-        let data = &mut data;
+        // The largest number a tag can hold.
+        let data = tagged(u64::MAX >> 3);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"1": 1, "__garbage__": hex_string(&data[2..])}));
+        assert_eq!(Parser::new().parse(&data).unwrap(), json!({"1": 1}));
+        assert_eq!(Parser::new().parse_once(&data).garbage, Some(&data[2..]));
 
-        loop {
-            if data.is_empty() {
-                break;
-            }
+        let json = parser.parse(&tagged(MAX_FIELD_NUMBER + 1)).unwrap();
+        assert!(json.get("536870912").is_none());
+        let json = parser.parse(&tagged(MAX_FIELD_NUMBER)).unwrap();
+        assert_eq!(json, json!({"1": 1, "536870911": 0}));
+    }
 
-            let tag = match decode_var(data) {
-                Ok(tag) => tag,
-                Err(_) => {
-                    msg.garbage = Some(data);
-                    break;
-                }
-            };
+    #[test]
+    fn test_parse_as_list() {
+        // 1: {1: "a"}, 2: 7, 1: {1: "b"}, 1: {1: "c", 2: 1}
+        let data = hex!("0a030a0161 1007 0a030a0162 0a050a01631001");
+        let list = Parser::new().parse_as_list(&data, 1).unwrap();
+        assert_eq!(
+            list,
+            [
+                json!({"1": "a"}),
+                json!({"1": "b"}),
+                json!({"1": "c", "2": 1})
+            ]
+        );
+        assert_eq!(Parser::new().parse_as_list(&data, 2), Some(vec![json!(7)]));
+        assert_eq!(Parser::new().parse_as_list(&data, 3), Some(vec![]));
 
-            let number = tag >> 3;
-            let wire_type = WireType::from((tag & 0x07) as u8);
+        // Field names of the elements apply.
+        let parser = Parser::new().with_field_names(&[(FieldPath::from([1, 1]), "name")]);
+        let list = parser.parse_as_list(&data, 1).unwrap();
+        assert_eq!(list[0], json!({"name": "a"}));
 
-            let value = FieldValue::decode(data, wire_type);
-            msg.fields.push(Field { number, value });
-        }
+        assert_eq!(Parser::new().parse_as_list(&[], 1), None);
+    }
 
-        msg
+    #[test]
+    fn test_parse_value_filter() {
+        // 1: 0, 2: "", 3: {1: 0, 2: 7}, 4: [0, 1] packed, 5: 0 fixed32, 6: 0.
+        let data = hex!("0800 1200 1a0408001007 22020001 2d00000000 3000");
+        let parser = Parser::new()
+            .with_packed_fields(&[FieldPath::from(4)])
+            .with_value_filter(|_, value| value != &json!(0));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"2": "", "3": {"2": 7}, "4": [1]}));
+
+        // A new filter replaces the previous one.
+        let parser = parser.with_value_filter(|path, _| path != &FieldPath::from([3, 2]));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": 0, "2": "", "3": {"1": 0}, "4": [0, 1], "5": 0, "6": 0})
+        );
     }
-}
 
-/// How to encode bytes fields when converting to JSON.
-///
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum BytesEncoding {
-    #[default]
-    /// Encode bytes as a string if valid UTF-8, otherwise as base64.
-    Auto,
+    #[test]
+    fn test_parse_rename_map() {
+        // 1: 28, 2: "You", 3: "Me", 5: {1: "abc123", 2: ""}
+        let data = hex!("0d1c0000001203596f751a024d652a0a0a066162633132331200");
+        let parser = Parser::new().with_rename_map(HashMap::from([
+            (FieldPath::from(2), "who".to_string()),
+            (FieldPath::from(3), "who".to_string()),
+            (FieldPath::from([5, 1]), "id".to_string()),
+        ]));
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": 28, "who": ["You", "Me"], "5": {"id": "abc123", "2": ""}})
+        );
+        let names = Parser::new().with_field_names(&[
+            (FieldPath::from(2), "who"),
+            (FieldPath::from(3), "who"),
+            (FieldPath::from([5, 1]), "id"),
+        ]);
+        assert_eq!(names, parser);
 
-    /// Encode bytes as base64 string.
-    Base64,
+        let parser = parser.with_duplicate_policy(DuplicatePolicy::Last);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json["who"], "Me");
+    }
 
-    /// Encode bytes as a JSON array of numbers.
-    ByteArray,
+    #[test]
+    fn test_parse_ip_fields() {
+        // 1: c0a80001, 2: 20010db8000000000000000000000001, 3: 0aff00
+        let data = hex!("0a04c0a80001 121020010db8000000000000000000000001 1a030aff00");
+        let paths = [FieldPath::from(1), FieldPath::from(2), FieldPath::from(3)];
+        let json = Parser::new().with_ip_fields(&paths).parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": "192.168.0.1", "2": "2001:db8::1", "3": "Cv8A"})
+        );
 
-    #[cfg(feature = "stfu8")]
-    /// Encode bytes as [stfu8](https://crates.io/crates/stfu8) encoded string.
-    Stfu8,
+        let json = Parser::new().parse(&data).unwrap();
+        assert_ne!(json["1"], "192.168.0.1");
+    }
 
-    /// Encode bytes as a UTF-8 lossy string.
-    StringLossy,
-}
+    #[test]
+    fn test_parse_uuid_fields() {
+        let data = hex!("0a10550e8400e29b41d4a716446655440000 1202ff00");
+        let paths = [FieldPath::from(1), FieldPath::from(2)];
+        let json = Parser::new().with_uuid_fields(&paths).parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": "550e8400-e29b-41d4-a716-446655440000", "2": "/wA="})
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use hex_literal::hex;
+    #[test]
+    fn test_parse_json_fields() {
+        let data = hex!("0a077b2261223a317d12037b613a");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"1": "{\"a\":1}", "2": "{a:"}));
 
-    use super::*;
+        let paths = [FieldPath::from(1), FieldPath::from(2)];
+        let json = Parser::new().with_json_fields(&paths).parse(&data).unwrap();
+        assert_eq!(json, json!({"1": {"a": 1}, "2": "{a:"}));
+    }
 
     #[test]
-    fn test_parse_1() {
-        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
-        let parser = Parser::new();
+    fn test_parse_array_element_tags() {
+        let data = hex!("2a0a0a0661626331323312002a0208010801");
+        let parser = Parser::new().with_array_element_tags(true);
         let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"1": 1, "5": [{"1": "abc123", "2": ""}, {"1": 1}]})
+        );
+
+        let json = parser.with_verbose(true).parse(&data).unwrap();
+        let expected = json!({
+            "1": 1,
+            "5": [
+                {"field": 5, "index": 0, "value": {"1": "abc123", "2": ""}},
+                {"field": 5, "index": 1, "value": {"1": 1}}
+            ]
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_parse_embedded() {
+        let document = json!({
+            "id": 7,
+            "payload": {"data": "DRwAAAASA1lvdRoCTWUgKyoKCgZhYmMxMjMSAA=="}
+        });
+        let json = Parser::new().parse_embedded(&document, "/payload/data");
         let expected = json!({
             "1": 28,
             "2": "You",
             "3": "Me",
             "4": 43,
+            "5": {"1": "abc123", "2": ""}
+        });
+        assert_eq!(json, Some(expected));
+
+        assert_eq!(Parser::new().parse_embedded(&document, "/id"), None);
+        assert_eq!(Parser::new().parse_embedded(&document, "/missing"), None);
+    }
+
+    #[test]
+    fn test_parse_typed_json() {
+        let data = hex!("0d1c0000001203596f75202b2a0a0a066162633132331200310100000000000000");
+        let parser = Parser::new().with_typed_json(true);
+        let json = parser.parse(&data).unwrap();
+        let expected = json!({
+            "1": {"type": "fixed32", "value": 28},
+            "2": {"type": "string", "value": "You"},
+            "4": {"type": "varint", "value": 43},
             "5": {
-                "1": "abc123",
-                "2": ""
-            }
+                "1": {"type": "string", "value": "abc123"},
+                "2": {"type": "string", "value": ""}
+            },
+            "6": {"type": "fixed64", "value": 1}
+        });
+        assert_eq!(json, expected);
+
+        let parser = Parser::with_bytes_encoding(BytesEncoding::ByteArray)
+            .with_typed_json(true)
+            .with_packed_fields(&[FieldPath::from(3)]);
+        let json = parser.parse(&hex!("0a02fffe1a020102")).unwrap();
+        let expected = json!({
+            "1": {"type": "bytes", "encoding": "byte_array", "value": [255, 254]},
+            "3": [{"type": "varint", "value": 1}, {"type": "varint", "value": 2}]
         });
         assert_eq!(json, expected);
+
+        let json = Parser::new().with_typed_json(true).parse(&hex!("0a02fffe"));
+        let expected = json!({"1": {"type": "bytes", "encoding": "base64", "value": "//4="}});
+        assert_eq!(json, Some(expected));
     }
 
     #[test]
-    fn test_parse_2() {
-        let data =
-            hex!("0d1c0000001203596f751a024d65202b2a0a0a06616263313233120031ba32a96cc10200003801");
-        let parser = Parser::new();
+    fn test_parse_packed_field_spec() {
+        let data = hex!("0a080000c03f000020c1120503047f80011a0201ff");
+        let parser = Parser::new()
+            .with_packed_field_spec(1, ElementType::Float)
+            .with_packed_field_spec(2, ElementType::Sint)
+            .with_packed_field_spec([3], ElementType::Fixed32);
         let json = parser.parse(&data).unwrap();
-        let expected = json!({"1":28,"2":"You","3":"Me","4":43,"5":{"1":"abc123","2":""},"6":3029774971578u64,"7":1});
-        assert_eq!(json, expected);
+        assert_eq!(
+            json,
+            json!({"1": [1.5, -10.0], "2": [-2, 2, -64, 64], "3": "Af8="})
+        );
+
+        let parser = Parser::new()
+            .with_packed_field_spec(2, ElementType::Int)
+            .with_packed_field_spec(3, ElementType::Bool);
+        let data = hex!("120bffffffffffffffffff01001a03010002");
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(json, json!({"2": [-1, 0], "3": [true, false, true]}));
     }
 
     #[test]
-    fn test_parse_3() {
-        let data = hex!(
-            "0a0a6173636f6e2d66756c6c120a6173636f6e2d66756c6c1a1b323032352d30392d30325430393a33373a32362e3033393032385a2203302e312a0474657374421b323032352d30392d30325430393a33373a32362e3033393032385a480068007205302e312e308a016e46756c6c204173636f6e20696d706c656d656e746174696f6e202868617368e280913235362c2041454144e280913132382077697468206e6f6e6365206d61736b696e67202620746167207472756e636174696f6e2c20584f46e280913132382c2043584f46e28091313238292e92012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6c9a011a68747470733a2f2f646f63732e72732f6173636f6e2d66756c6ca2012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6caa014612222f6170692f76312f6372617465732f6173636f6e2d66756c6c2f76657273696f6e731a202f6170692f76312f6372617465732f6173636f6e2d66756c6c2f6f776e657273"
+    fn test_parse_repeated_strings() {
+        let twice = hex!("1203596f75120141");
+        let json = Parser::new().parse(&twice).unwrap();
+        assert_eq!(json, json!({"2": ["You", "A"]}));
+
+        let once = hex!("120a0a036162630a03646566");
+        let json = Parser::new().parse(&once).unwrap();
+        assert_eq!(json, json!({"2": {"1": ["abc", "def"]}}));
+
+        let paths = [FieldPath::from(2)];
+        let parser = Parser::new()
+            .with_string_fields(&paths)
+            .with_repeated_fields(&paths);
+        let json = parser.parse(&once).unwrap();
+        assert_eq!(json, json!({"2": ["\n\u{3}abc\n\u{3}def"]}));
+        let json = parser.parse(&twice).unwrap();
+        assert_eq!(json, json!({"2": ["You", "A"]}));
+    }
+
+    #[test]
+    fn test_parse_repeated_nested() {
+        let data = hex!("2a0a0a06616263313233120032020801");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"5": {"1": "abc123", "2": ""}, "6": {"1": 1}}));
+
+        let parser = Parser::new().with_repeated_fields(&[FieldPath::from(5)]);
+        let json = parser.parse(&data).unwrap();
+        assert_eq!(
+            json,
+            json!({"5": [{"1": "abc123", "2": ""}], "6": {"1": 1}})
         );
-        let parser = Parser::new();
+
+        let data = hex!("2a0a0a0661626331323312002a020801");
         let json = parser.parse(&data).unwrap();
-        let expected = json!({
-          "1": "ascon-full",
-          "13": 0,
-          "14": "0.1.0",
-          "17": "Full Ascon implementation (hash‑256, AEAD‑128 with nonce masking & tag truncation, XOF‑128, CXOF‑128).",
-          "18": "https://github.com/jjkum/ascon-full",
-          "19": "https://docs.rs/ascon-full",
-          "2": "ascon-full",
-          "20": "https://github.com/jjkum/ascon-full",
-          "21": {
-            "2": "/api/v1/crates/ascon-full/versions",
-            "3": "/api/v1/crates/ascon-full/owners"
-          },
-          "3": "2025-09-02T09:37:26.039028Z",
-          "4": "0.1",
-          "5": "test",
-          "8": "2025-09-02T09:37:26.039028Z",
-          "9": 0
-        });
-        assert_eq!(json, expected);
+        assert_eq!(json, json!({"5": [{"1": "abc123", "2": ""}, {"1": 1}]}));
     }
 
     #[test]
-    fn test_parse_encoding_bytearray() {
-        let data = hex!("4a050001020304");
+    fn test_parse_repeated_mixed_types() {
+        let data = hex!("2a0208012a03616263");
+        let json = Parser::new().parse(&data).unwrap();
+        assert_eq!(json, json!({"5": [{"1": 1}, "abc"]}));
+
         let parser = Parser::with_bytes_encoding(BytesEncoding::ByteArray);
-        let json = parser.parse(&data).unwrap();
-        let expected = json!({"9":[0,1,2,3,4]});
-        assert_eq!(json, expected);
+        let json = parser.parse(&hex!("0a02fffe0a0102")).unwrap();
+        assert_eq!(json, json!({"1": [[255, 254], [2]]}));
     }
 
     #[test]
-    fn test_parse_num_array() {
-        let data = hex!("4a050001020304");
-        let parser = Parser::new();
-        let json = parser.parse(&data).unwrap();
-        let expected = json!({"9":"\u{0000}\u{0001}\u{0002}\u{0003}\u{0004}"});
-        assert_eq!(json, expected);
+    fn test_parse_require_full_parse() {
+        let parser = Parser::new().with_require_full_parse(true);
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        assert!(parser.parse(&data).is_some());
+
+        let random = hex!("089601fffe");
+        assert_eq!(Parser::new().parse(&random), Some(json!({"1": 150})));
+        assert_eq!(parser.try_parse(&random), Err(ParseError::NotAMessage));
+
+        let random = hex!("1a9c53e2");
+        assert_eq!(parser.parse(&random), None);
+    }
+
+    #[test]
+    fn test_parse_top_level_rejection() {
+        let reserved = hex!("0801c0a30901");
+        let garbage = hex!("0801ff");
+        assert_eq!(
+            Parser::new().parse(&reserved),
+            Some(json!({"1": 1, "19000": 1}))
+        );
+        assert_eq!(Parser::new().parse(&garbage), Some(json!({"1": 1})));
+
+        let parser = Parser::new().with_top_level_rejection(true);
+        assert_eq!(parser.parse(&reserved), None);
+        assert_eq!(parser.parse(&garbage), None);
+        assert_eq!(parser.parse(&hex!("0801")), Some(json!({"1": 1})));
     }
 }
@@ -0,0 +1,66 @@
+//! Field paths used to target hints at specific fields.
+//!
+
+use std::borrow::Borrow;
+use std::fmt;
+
+/// Path of field numbers from the root message to a field.
+///
+/// `FieldPath::from([5, 1])` is field 1 of the message held by top-level field 5. A single
+/// field number converts to a top-level path.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FieldPath(Vec<u64>);
+
+impl FieldPath {
+    /// Create a path from field numbers, outermost first.
+    pub fn new(numbers: Vec<u64>) -> Self {
+        Self(numbers)
+    }
+
+    /// Field numbers of the path, outermost first.
+    pub fn numbers(&self) -> &[u64] {
+        &self.0
+    }
+}
+
+impl From<u64> for FieldPath {
+    fn from(number: u64) -> Self {
+        Self(vec![number])
+    }
+}
+
+impl From<&[u64]> for FieldPath {
+    fn from(numbers: &[u64]) -> Self {
+        Self(numbers.to_vec())
+    }
+}
+
+impl<const N: usize> From<[u64; N]> for FieldPath {
+    fn from(numbers: [u64; N]) -> Self {
+        Self(numbers.to_vec())
+    }
+}
+
+impl From<Vec<u64>> for FieldPath {
+    fn from(numbers: Vec<u64>) -> Self {
+        Self(numbers)
+    }
+}
+
+impl Borrow<[u64]> for FieldPath {
+    fn borrow(&self) -> &[u64] {
+        &self.0
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, number) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{number}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,482 @@
+//! Canonical proto3 JSON output for messages with a known schema.
+
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use base64::prelude::*;
+use protofish::context::{Context, Multiplicity};
+use protofish::decode::{MessageValue, PackedArray, UnknownValue, Value as ProtoValue};
+use serde_json::{Map, Value};
+
+use crate::{NonFinite, ParseError, Parser, SchemaError};
+
+/// Schema of the root message, parsed from `.proto` files with
+/// [protofish](https://crates.io/crates/protofish).
+#[derive(Debug)]
+pub struct Schema {
+    context: Context,
+    message: String,
+}
+
+impl Schema {
+    /// Parse the given `.proto` file contents and select the root message by its full name,
+    /// including the package.
+    pub fn parse(files: &[&str], message: &str) -> Result<Self, SchemaError> {
+        let context = Context::parse(files).map_err(|e| SchemaError::Parse(e.to_string()))?;
+        Self::new(context, message)
+    }
+
+    /// Use an existing protofish context with the given root message.
+    pub fn new(context: Context, message: &str) -> Result<Self, SchemaError> {
+        if context.get_message(message).is_none() {
+            return Err(SchemaError::UnknownMessage(message.to_string()));
+        }
+        Ok(Self {
+            context,
+            message: message.to_string(),
+        })
+    }
+}
+
+impl PartialEq for Schema {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message && self.context == other.context
+    }
+}
+
+impl Eq for Schema {}
+
+impl Hash for Schema {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.message.hash(state);
+    }
+}
+
+impl Parser {
+    /// Decode messages with the given schema in [`Parser::parse_to_protojson`].
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        Arc::make_mut(&mut self.hints).schema = Some(Arc::new(schema));
+        self
+    }
+
+    /// Parse a protobuf message with the schema and convert it to canonical proto3 JSON.
+    ///
+    /// The output follows the proto3 JSON mapping: lowerCamelCase field names, 64-bit integers
+    /// as strings, bytes as base64, enum values by name, `NaN`/`Infinity` as strings unless
+    /// [`Parser::with_nonfinite_floats`] says otherwise, and
+    /// `Timestamp`, `Duration` and wrapper types in their special formats. Singular fields
+    /// holding their default value are omitted.
+    ///
+    /// Deviations from canonical proto3 JSON, where the schema doesn't help:
+    /// * fields missing from the schema fall back to schemaless guessing and are keyed by field
+    ///   number,
+    /// * enum values missing from the schema are emitted as numbers,
+    /// * incomplete and invalid values are dropped,
+    /// * well-known types other than `Timestamp`, `Duration` and the wrappers are emitted as
+    ///   regular messages.
+    ///
+    /// Returns `None` if the parser has no schema, or if a float is NaN or infinite with
+    /// [`NonFinite::Error`].
+    pub fn parse_to_protojson(&self, data: &[u8]) -> Option<Value> {
+        let schema = self.hints.schema.as_ref()?;
+        let info = schema.context.get_message(&schema.message)?;
+        let msg = info.decode(data, &schema.context);
+        self.protojson_message(&msg, &schema.context).ok()
+    }
+
+    fn protojson_message(
+        &self,
+        msg: &MessageValue,
+        context: &Context,
+    ) -> Result<Value, ParseError> {
+        let info = context.resolve_message(msg.msg_ref);
+        if let Some(value) = self.well_known_to_json(&info.full_name, msg)? {
+            return Ok(value);
+        }
+
+        let mut map = Map::new();
+        for field in &msg.fields {
+            let Some(def) = info.get_field(field.number) else {
+                let value = match &field.value {
+                    ProtoValue::Unknown(UnknownValue::Varint(v)) => match u64::try_from(*v) {
+                        Ok(v) => Value::Number(v.into()),
+                        Err(_) => Value::String(v.to_string()),
+                    },
+                    ProtoValue::Unknown(UnknownValue::Fixed64(v)) => Value::Number((*v).into()),
+                    ProtoValue::Unknown(UnknownValue::Fixed32(v)) => Value::Number((*v).into()),
+                    ProtoValue::Unknown(UnknownValue::VariableLength(bytes)) => {
+                        self.length_delimited_to_json(bytes, &[field.number])
+                    }
+                    _ => continue,
+                };
+                merge_unknown(&mut map, field.number.to_string(), value);
+                continue;
+            };
+
+            let key = json_name(&def.name);
+            let values = match &field.value {
+                ProtoValue::Packed(array) => self.protojson_packed(array, field.number)?,
+                ProtoValue::Incomplete(_, _) | ProtoValue::Unknown(_) => continue,
+                value => {
+                    if def.multiplicity == Multiplicity::Single && is_default(value) {
+                        continue;
+                    }
+                    vec![self.protojson_value(value, field.number, context)?]
+                }
+            };
+            match def.multiplicity {
+                Multiplicity::Repeated | Multiplicity::RepeatedPacked => {
+                    if let Value::Array(array) =
+                        map.entry(key).or_insert_with(|| Value::Array(Vec::new()))
+                    {
+                        array.extend(values);
+                    }
+                }
+                // The last occurrence of a singular field wins
+                Multiplicity::Single | Multiplicity::Optional => {
+                    if let Some(value) = values.into_iter().last() {
+                        map.insert(key, value);
+                    }
+                }
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn protojson_value(
+        &self,
+        value: &ProtoValue,
+        number: u64,
+        context: &Context,
+    ) -> Result<Value, ParseError> {
+        let json = match value {
+            ProtoValue::Double(v) => self.protojson_float(*v, number)?,
+            ProtoValue::Float(v) => self.protojson_float32(*v, number)?,
+            ProtoValue::Int32(v) | ProtoValue::SInt32(v) | ProtoValue::SFixed32(v) => {
+                Value::Number((*v).into())
+            }
+            ProtoValue::UInt32(v) | ProtoValue::Fixed32(v) => Value::Number((*v).into()),
+            ProtoValue::Int64(v) | ProtoValue::SInt64(v) | ProtoValue::SFixed64(v) => {
+                Value::String(v.to_string())
+            }
+            ProtoValue::UInt64(v) | ProtoValue::Fixed64(v) => Value::String(v.to_string()),
+            ProtoValue::Bool(v) => Value::Bool(*v),
+            ProtoValue::String(v) => Value::String(v.clone()),
+            ProtoValue::Bytes(v) => Value::String(BASE64_STANDARD.encode(v)),
+            ProtoValue::Enum(v) => {
+                match context.resolve_enum(v.enum_ref).get_field_by_value(v.value) {
+                    Some(field) => Value::String(field.name.clone()),
+                    None => Value::Number(v.value.into()),
+                }
+            }
+            ProtoValue::Message(msg) => self.protojson_message(msg, context)?,
+            ProtoValue::Packed(array) => Value::Array(self.protojson_packed(array, number)?),
+            ProtoValue::Incomplete(_, _) | ProtoValue::Unknown(_) => Value::Null,
+        };
+        Ok(json)
+    }
+
+    /// Convert a float of field `number`, rendering NaN and infinities as configured with
+    /// [`Parser::with_nonfinite_floats`].
+    fn protojson_float(&self, v: f64, number: u64) -> Result<Value, ParseError> {
+        if v.is_finite() {
+            return Ok(Value::from(v));
+        }
+        match self.nonfinite_floats {
+            NonFinite::String if v.is_nan() => Ok(Value::String("NaN".to_string())),
+            NonFinite::String => {
+                let sign = if v < 0.0 { "-" } else { "" };
+                Ok(Value::String(format!("{sign}Infinity")))
+            }
+            NonFinite::Null => Ok(Value::Null),
+            NonFinite::Error => Err(ParseError::NonFiniteFloat { field: number }),
+        }
+    }
+
+    /// Convert a `float` through its shortest decimal representation, so `0.1f32` stays `0.1`.
+    fn protojson_float32(&self, v: f32, number: u64) -> Result<Value, ParseError> {
+        match v.to_string().parse() {
+            Ok(v) if f32::is_finite(v as f32) => self.protojson_float(v, number),
+            _ => self.protojson_float(v as f64, number),
+        }
+    }
+
+    fn protojson_packed(&self, array: &PackedArray, number: u64) -> Result<Vec<Value>, ParseError> {
+        fn numbers<T: Copy + Into<serde_json::Number>>(values: &[T]) -> Vec<Value> {
+            values.iter().map(|v| Value::Number((*v).into())).collect()
+        }
+        fn strings<T: ToString>(values: &[T]) -> Vec<Value> {
+            values
+                .iter()
+                .map(|v| Value::String(v.to_string()))
+                .collect()
+        }
+        let values = match array {
+            PackedArray::Double(v) => {
+                return v.iter().map(|v| self.protojson_float(*v, number)).collect();
+            }
+            PackedArray::Float(v) => {
+                return v
+                    .iter()
+                    .map(|v| self.protojson_float32(*v, number))
+                    .collect();
+            }
+            PackedArray::Int32(v) | PackedArray::SInt32(v) | PackedArray::SFixed32(v) => numbers(v),
+            PackedArray::UInt32(v) | PackedArray::Fixed32(v) => numbers(v),
+            PackedArray::Int64(v) | PackedArray::SInt64(v) | PackedArray::SFixed64(v) => strings(v),
+            PackedArray::UInt64(v) | PackedArray::Fixed64(v) => strings(v),
+            PackedArray::Bool(v) => v.iter().map(|v| Value::Bool(*v)).collect(),
+        };
+        Ok(values)
+    }
+
+    /// Convert the well-known types that have a special JSON representation.
+    fn well_known_to_json(
+        &self,
+        full_name: &str,
+        msg: &MessageValue,
+    ) -> Result<Option<Value>, ParseError> {
+        let field = |number: u64| {
+            msg.fields
+                .iter()
+                .rev()
+                .find(|f| f.number == number)
+                .map(|f| &f.value)
+        };
+        let int = |number: u64| match field(number) {
+            Some(ProtoValue::Int64(v)) => *v,
+            Some(ProtoValue::Int32(v)) => *v as i64,
+            _ => 0,
+        };
+
+        let value = match full_name {
+            "google.protobuf.Timestamp" => Value::String(format_timestamp(int(1), int(2))),
+            "google.protobuf.Duration" => Value::String(format_duration(int(1), int(2))),
+            "google.protobuf.DoubleValue" => match field(1) {
+                Some(ProtoValue::Double(v)) => self.protojson_float(*v, 1)?,
+                _ => Value::from(0.0),
+            },
+            "google.protobuf.FloatValue" => match field(1) {
+                Some(ProtoValue::Float(v)) => self.protojson_float32(*v, 1)?,
+                _ => Value::from(0.0),
+            },
+            "google.protobuf.Int64Value" | "google.protobuf.UInt64Value" => match field(1) {
+                Some(ProtoValue::Int64(v)) => Value::String(v.to_string()),
+                Some(ProtoValue::UInt64(v)) => Value::String(v.to_string()),
+                _ => Value::String("0".to_string()),
+            },
+            "google.protobuf.Int32Value" | "google.protobuf.UInt32Value" => match field(1) {
+                Some(ProtoValue::Int32(v)) => Value::Number((*v).into()),
+                Some(ProtoValue::UInt32(v)) => Value::Number((*v).into()),
+                _ => Value::Number(0.into()),
+            },
+            "google.protobuf.BoolValue" => {
+                Value::Bool(matches!(field(1), Some(ProtoValue::Bool(true))))
+            }
+            "google.protobuf.StringValue" => match field(1) {
+                Some(ProtoValue::String(v)) => Value::String(v.clone()),
+                _ => Value::String(String::new()),
+            },
+            "google.protobuf.BytesValue" => match field(1) {
+                Some(ProtoValue::Bytes(v)) => Value::String(BASE64_STANDARD.encode(v)),
+                _ => Value::String(String::new()),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+}
+
+/// Convert a proto field name to its lowerCamelCase JSON name.
+fn json_name(name: &str) -> String {
+    let mut json_name = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            json_name.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            json_name.push(c);
+        }
+    }
+    json_name
+}
+
+/// Merge a field missing from the schema, collecting repeated occurrences into an array.
+fn merge_unknown(map: &mut Map<String, Value>, key: String, value: Value) {
+    match map.get_mut(&key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            map.insert(key, value);
+        }
+    }
+}
+
+fn is_default(value: &ProtoValue) -> bool {
+    match value {
+        ProtoValue::Double(v) => *v == 0.0,
+        ProtoValue::Float(v) => *v == 0.0,
+        ProtoValue::Int32(v) | ProtoValue::SInt32(v) | ProtoValue::SFixed32(v) => *v == 0,
+        ProtoValue::UInt32(v) | ProtoValue::Fixed32(v) => *v == 0,
+        ProtoValue::Int64(v) | ProtoValue::SInt64(v) | ProtoValue::SFixed64(v) => *v == 0,
+        ProtoValue::UInt64(v) | ProtoValue::Fixed64(v) => *v == 0,
+        ProtoValue::Bool(v) => !v,
+        ProtoValue::String(v) => v.is_empty(),
+        ProtoValue::Bytes(v) => v.is_empty(),
+        ProtoValue::Enum(v) => v.value == 0,
+        _ => false,
+    }
+}
+
+/// Format fractional seconds with 0, 3, 6 or 9 digits as the proto3 JSON mapping does.
+fn format_nanos(nanos: u32) -> String {
+    if nanos == 0 {
+        String::new()
+    } else if nanos.is_multiple_of(1_000_000) {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos.is_multiple_of(1_000) {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{nanos:09}")
+    }
+}
+
+/// Format a `Timestamp` as RFC 3339 in UTC.
+fn format_timestamp(seconds: i64, nanos: i64) -> String {
+    let days = seconds.div_euclid(86_400);
+    let secs = seconds.rem_euclid(86_400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}{}Z",
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60,
+        format_nanos(nanos.clamp(0, 999_999_999) as u32)
+    )
+}
+
+/// Format a `Duration` as seconds with an `s` suffix.
+fn format_duration(seconds: i64, nanos: i64) -> String {
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    format!(
+        "{sign}{}{}s",
+        seconds.unsigned_abs(),
+        format_nanos(nanos.unsigned_abs().min(999_999_999) as u32)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+
+    const PROTO: &str = r#"
+        syntax = "proto3";
+        package sample;
+
+        enum Status {
+            UNKNOWN = 0;
+            OK = 1;
+        }
+
+        message Inner {
+            string first_name = 1;
+            string last_name = 2;
+        }
+
+        message Sample {
+            fixed32 id = 1;
+            string you = 2;
+            string me = 3;
+            int64 count = 4;
+            Inner inner_message = 5;
+            Status status = 6;
+            repeated sint32 deltas = 7;
+        }
+    "#;
+
+    #[test]
+    fn test_parse_to_protojson() {
+        let schema = Schema::parse(&[PROTO], "sample.Sample").unwrap();
+        let parser = Parser::new().with_schema(schema);
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a06616263313233120030013a020103400a");
+        let json = parser.parse_to_protojson(&data).unwrap();
+        let expected = json!({
+            "id": 28,
+            "you": "You",
+            "me": "Me",
+            "count": "43",
+            "innerMessage": {"firstName": "abc123"},
+            "status": "OK",
+            "deltas": [-1, -2],
+            "8": 10
+        });
+        assert_eq!(json, expected);
+        assert_eq!(Parser::new().parse_to_protojson(&data), None);
+    }
+
+    #[test]
+    fn test_parse_to_protojson_nonfinite() {
+        let proto = r#"
+            syntax = "proto3";
+            message Floats {
+                double d = 1;
+                repeated float f = 2;
+            }
+        "#;
+        // d: -inf, f: [NaN, 1.5]
+        let data = hex!("09000000000000f0ff 12080000c07f0000c03f");
+        let schema = || Schema::parse(&[proto], "Floats").unwrap();
+        let parser = Parser::new().with_schema(schema());
+        assert_eq!(
+            parser.parse_to_protojson(&data),
+            Some(json!({"d": "-Infinity", "f": ["NaN", 1.5]}))
+        );
+        let parser = parser.with_nonfinite_floats(NonFinite::Null);
+        assert_eq!(
+            parser.parse_to_protojson(&data),
+            Some(json!({"d": null, "f": [null, 1.5]}))
+        );
+        let parser = parser.with_nonfinite_floats(NonFinite::Error);
+        assert_eq!(parser.parse_to_protojson(&data), None);
+    }
+
+    #[test]
+    fn test_schema_unknown_message() {
+        assert_eq!(
+            Schema::parse(&[PROTO], "Sample").unwrap_err(),
+            SchemaError::UnknownMessage("Sample".to_string())
+        );
+    }
+
+    #[test]
+    fn test_well_known_formats() {
+        assert_eq!(format_timestamp(0, 0), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_timestamp(1_756_805_846, 39_028_000),
+            "2025-09-02T09:37:26.039028Z"
+        );
+        assert_eq!(format_timestamp(-1, 0), "1969-12-31T23:59:59Z");
+        assert_eq!(format_duration(1, 500_000_000), "1.500s");
+        assert_eq!(format_duration(0, -1), "-0.000000001s");
+    }
+}
@@ -0,0 +1,245 @@
+//! Serialization of the JSON output.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::Value;
+
+use crate::{FieldValue, Message, Parser};
+
+/// Serializes a JSON value with object keys in numeric order, e.g. `"2"` before `"10"`.
+///
+/// `serde_json` keeps object keys sorted as strings. Wrapping a value in `NumericKeyOrder`
+/// serializes keys that are field numbers in numeric order instead, followed by any other keys
+/// in string order.
+///
+/// ``` rust
+/// use protobuf_to_json::NumericKeyOrder;
+/// use serde_json::json;
+///
+/// let json = json!({"10": 1, "2": 2});
+/// assert_eq!(json.to_string(), r#"{"10":1,"2":2}"#);
+/// assert_eq!(serde_json::to_string(&NumericKeyOrder(&json)).unwrap(), r#"{"2":2,"10":1}"#);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumericKeyOrder<'a>(pub &'a Value);
+
+impl Serialize for NumericKeyOrder<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                // `Ok` sorts before `Err`, putting field numbers before other keys.
+                entries.sort_by_key(|(key, _)| key.parse::<u64>().map_err(|_| key.as_str()));
+                let mut ser = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    ser.serialize_entry(key, &NumericKeyOrder(value))?;
+                }
+                ser.end()
+            }
+            Value::Array(values) => {
+                let mut ser = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    ser.serialize_element(&NumericKeyOrder(value))?;
+                }
+                ser.end()
+            }
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+impl Parser {
+    /// Parse a protobuf message and serialize the JSON output to a string.
+    ///
+    /// Keys are in numeric order if enabled with [`Parser::with_numeric_key_order`].
+    pub fn parse_to_string(&self, data: &[u8]) -> Option<String> {
+        let json = self.parse(data)?;
+        let s = match self.numeric_key_order {
+            true => serde_json::to_string(&NumericKeyOrder(&json)),
+            false => serde_json::to_string(&json),
+        };
+        // Serializing a `Value` can't fail.
+        Some(s.unwrap())
+    }
+
+    /// Parse a protobuf message and write the JSON output to `writer`.
+    ///
+    /// Unless the output depends on options such as hints or the verbose output, the JSON is
+    /// written while walking the message, without building a [`Value`] first. The output is the
+    /// same as [`Parser::parse_to_string`]. Returns `Ok(false)` without writing anything if the
+    /// data is not a message.
+    pub fn parse_to_writer<W: io::Write>(&self, data: &[u8], writer: W) -> io::Result<bool> {
+        let mut ser = serde_json::Serializer::new(writer);
+        if !self.streamable() {
+            let Some(json) = self.parse(data) else {
+                return Ok(false);
+            };
+            match self.numeric_key_order {
+                true => NumericKeyOrder(&json).serialize(&mut ser)?,
+                false => json.serialize(&mut ser)?,
+            }
+            return Ok(true);
+        }
+        let Some(msg) = self.root_message(data) else {
+            return Ok(false);
+        };
+        StreamedMessage { parser: self, msg }.serialize(&mut ser)?;
+        Ok(true)
+    }
+}
+
+/// Message serialized while it is converted, for [`Parser::parse_to_writer`].
+struct StreamedMessage<'p, 'a> {
+    parser: &'p Parser,
+    msg: Message<'a>,
+}
+
+impl Serialize for StreamedMessage<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields: BTreeMap<u64, Vec<&FieldValue>> = BTreeMap::new();
+        for field in &self.msg.fields {
+            // Only the first layer may hold invalid fields, its output stops before them.
+            if matches!(
+                field.value,
+                FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _)
+            ) {
+                break;
+            }
+            fields.entry(field.number).or_default().push(&field.value);
+        }
+        let mut entries: Vec<_> = fields
+            .into_iter()
+            .map(|(number, values)| (number.to_string(), values))
+            .collect();
+        if !self.parser.numeric_key_order {
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+
+        let mut ser = serializer.serialize_map(Some(entries.len()))?;
+        for (key, values) in entries {
+            match values.as_slice() {
+                [value] => ser.serialize_entry(
+                    &key,
+                    &StreamedValue {
+                        parser: self.parser,
+                        value,
+                    },
+                )?,
+                values => ser.serialize_entry(
+                    &key,
+                    &StreamedValues {
+                        parser: self.parser,
+                        values,
+                    },
+                )?,
+            }
+        }
+        ser.end()
+    }
+}
+
+/// Field value serialized while it is converted.
+struct StreamedValue<'p, 'a> {
+    parser: &'p Parser,
+    value: &'p FieldValue<'a>,
+}
+
+impl Serialize for StreamedValue<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let parser = self.parser;
+        match self.value {
+            FieldValue::Varint(v) => parser.uint_to_json(*v as u64).serialize(serializer),
+            FieldValue::Fixed64(v) => parser.uint_to_json(*v).serialize(serializer),
+            FieldValue::Fixed32(v) => parser.uint_to_json(*v as u64).serialize(serializer),
+            FieldValue::LengthDelimited(bytes) => match parser.classify_message(bytes, false) {
+                Ok(msg) if !bytes.is_empty() => {
+                    StreamedMessage { parser, msg }.serialize(serializer)
+                }
+                _ => parser.bytes_to_json(bytes).serialize(serializer),
+            },
+            FieldValue::Message(msg) => StreamedMessage {
+                parser,
+                msg: msg.clone(),
+            }
+            .serialize(serializer),
+            FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _) => serializer.serialize_unit(),
+        }
+    }
+}
+
+/// Values of a repeated field serialized as an array.
+struct StreamedValues<'p, 'a> {
+    parser: &'p Parser,
+    values: &'p [&'p FieldValue<'a>],
+}
+
+impl Serialize for StreamedValues<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut ser = serializer.serialize_seq(Some(self.values.len()))?;
+        for value in self.values {
+            ser.serialize_element(&StreamedValue {
+                parser: self.parser,
+                value,
+            })?;
+        }
+        ser.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_to_writer() {
+        let inputs = [
+            &hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200")[..],
+            &hex!("0801100252020801a00103080a")[..],
+            &hex!("0a0a12081a06220464656570")[..],
+            &hex!("0801ffffffff")[..],
+            &hex!("08010f00")[..],
+        ];
+        let parsers = [
+            Parser::new(),
+            Parser::new().with_numeric_key_order(true),
+            Parser::new().with_verbose(true),
+            Parser::new()
+                .with_numeric_key_order(true)
+                .with_verbose(true),
+        ];
+        for parser in &parsers {
+            for data in inputs {
+                let mut out = Vec::new();
+                assert!(parser.parse_to_writer(data, &mut out).unwrap());
+                let expected = parser.parse_to_string(data).unwrap();
+                assert_eq!(String::from_utf8(out).unwrap(), expected);
+            }
+        }
+
+        let mut out = Vec::new();
+        assert!(!Parser::new().parse_to_writer(&[], &mut out).unwrap());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_parse_to_string_numeric_key_order() {
+        let data = hex!("0801100252020801a00103");
+        let s = Parser::new().parse_to_string(&data).unwrap();
+        assert_eq!(s, r#"{"1":1,"10":{"1":1},"2":2,"20":3}"#);
+
+        let parser = Parser::new().with_numeric_key_order(true);
+        let s = parser.parse_to_string(&data).unwrap();
+        assert_eq!(s, r#"{"1":1,"2":2,"10":{"1":1},"20":3}"#);
+
+        let parser = parser.with_oneof_groups(&[("kind", &[20])]);
+        let s = parser.parse_to_string(&data).unwrap();
+        assert_eq!(
+            s,
+            r#"{"1":1,"2":2,"10":{"1":1},"kind":{"field":20,"value":3}}"#
+        );
+    }
+}
@@ -0,0 +1,429 @@
+//! Shape of messages inferred from samples, to detect schema changes.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::{Map, Value, json};
+
+use crate::{FieldPath, Parser, ShapeError, ShapeIssue, ShapeMismatch, TypedValue, WireType};
+
+/// Fields of a message inferred from samples with [`Parser::infer_shape`].
+///
+/// A shape converts to JSON keyed by field number, and back with `TryFrom<&Value>`, so that it
+/// can be stored and loaded later:
+///
+/// ```json
+/// {"1": {"wire_types": ["varint"], "required": true},
+///  "5": {"wire_types": ["length-delimited"], "required": false, "message": {}}}
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ShapeDescriptor {
+    fields: BTreeMap<u64, FieldShape>,
+}
+
+/// Shape of a single field of a [`ShapeDescriptor`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldShape {
+    /// Wire types the field had in the samples, in wire type number order.
+    pub wire_types: Vec<WireType>,
+
+    /// Whether every sample of the enclosing message has the field.
+    pub required: bool,
+
+    /// Shape of the nested message, if the field held one in any sample.
+    pub message: Option<ShapeDescriptor>,
+}
+
+impl ShapeDescriptor {
+    /// Shape of the field at the given path.
+    pub fn field(&self, path: &[u64]) -> Option<&FieldShape> {
+        let (last, parents) = path.split_last()?;
+        let mut shape = self;
+        for number in parents {
+            shape = shape.fields.get(number)?.message.as_ref()?;
+        }
+        shape.fields.get(last)
+    }
+
+    /// Infer the shape of the given messages, which are occurrences of the same message.
+    fn infer(messages: &[&[(u64, TypedValue)]]) -> Self {
+        #[derive(Default)]
+        struct Seen<'a> {
+            messages: usize,
+            wire_types: BTreeSet<u8>,
+            nested: Vec<&'a [(u64, TypedValue)]>,
+        }
+
+        let mut seen: BTreeMap<u64, Seen> = BTreeMap::new();
+        for fields in messages {
+            let mut numbers = BTreeSet::new();
+            for (number, value) in *fields {
+                let field = seen.entry(*number).or_default();
+                if numbers.insert(*number) {
+                    field.messages += 1;
+                }
+                field.wire_types.insert(value.wire_type().into());
+                if let TypedValue::Message(nested) = value {
+                    field.nested.push(nested);
+                }
+            }
+        }
+        let fields = seen
+            .into_iter()
+            .map(|(number, field)| {
+                let shape = FieldShape {
+                    wire_types: field.wire_types.into_iter().map(WireType::from).collect(),
+                    required: field.messages == messages.len(),
+                    message: (!field.nested.is_empty()).then(|| Self::infer(&field.nested)),
+                };
+                (number, shape)
+            })
+            .collect();
+        Self { fields }
+    }
+
+    /// Collect the differences of the fields of a message at `path` from this shape.
+    fn check(
+        &self,
+        fields: &[(u64, TypedValue)],
+        path: &mut Vec<u64>,
+        issues: &mut Vec<ShapeIssue>,
+    ) {
+        let mut reported = BTreeSet::new();
+        for (number, value) in fields {
+            path.push(*number);
+            let issue = match self.fields.get(number) {
+                None => Some(ShapeIssue::NewField {
+                    path: FieldPath::from(path.as_slice()),
+                }),
+                Some(field) if !field.wire_types.contains(&value.wire_type()) => {
+                    Some(ShapeIssue::WireTypeChanged {
+                        path: FieldPath::from(path.as_slice()),
+                        expected: field.wire_types.clone(),
+                        found: value.wire_type(),
+                    })
+                }
+                // A value guessed as bytes in a message field is not reported, as short or empty
+                // messages are often not recognized as such.
+                Some(field) => {
+                    if let (Some(shape), TypedValue::Message(nested)) = (&field.message, value) {
+                        shape.check(nested, path, issues);
+                    }
+                    None
+                }
+            };
+            // Occurrences of a repeated field are reported once.
+            if let Some(issue) = issue
+                && reported.insert(*number)
+            {
+                issues.push(issue);
+            }
+            path.pop();
+        }
+        for (number, field) in &self.fields {
+            if field.required && !fields.iter().any(|(n, _)| n == number) {
+                path.push(*number);
+                issues.push(ShapeIssue::MissingField {
+                    path: FieldPath::from(path.as_slice()),
+                });
+                path.pop();
+            }
+        }
+    }
+}
+
+impl Parser {
+    /// Infer the shape of a message from samples of it, to check later messages with
+    /// [`Parser::validate_against_shape`].
+    ///
+    /// The shape records the wire types of every field, whether every sample has it and the
+    /// shape of nested messages, which are guessed the same way as [`Parser::parse_typed`] does.
+    /// Samples that are not messages are skipped.
+    pub fn infer_shape(&self, samples: &[&[u8]]) -> ShapeDescriptor {
+        let typed: Vec<_> = samples
+            .iter()
+            .filter_map(|data| self.parse_typed(data))
+            .collect();
+        let messages: Vec<_> = typed
+            .iter()
+            .filter_map(|typed| match typed {
+                TypedValue::Message(fields) => Some(fields.as_slice()),
+                _ => None,
+            })
+            .collect();
+        ShapeDescriptor::infer(&messages)
+    }
+
+    /// Check that a message matches a shape inferred with [`Parser::infer_shape`].
+    ///
+    /// Reports fields that are not in the shape, fields that every sample had but the message
+    /// lacks, and fields with a wire type they never had, recursively. This detects silent
+    /// schema changes of captured messages.
+    pub fn validate_against_shape(
+        &self,
+        data: &[u8],
+        shape: &ShapeDescriptor,
+    ) -> Result<(), ShapeMismatch> {
+        let Some(TypedValue::Message(fields)) = self.parse_typed(data) else {
+            return Err(ShapeMismatch {
+                issues: vec![ShapeIssue::NotAMessage],
+            });
+        };
+        let mut issues = Vec::new();
+        shape.check(&fields, &mut Vec::new(), &mut issues);
+        match issues.is_empty() {
+            true => Ok(()),
+            false => Err(ShapeMismatch { issues }),
+        }
+    }
+}
+
+impl From<&ShapeDescriptor> for Value {
+    fn from(shape: &ShapeDescriptor) -> Self {
+        let fields = shape.fields.iter().map(|(number, field)| {
+            let wire_types: Vec<_> = field.wire_types.iter().map(WireType::to_string).collect();
+            let mut json = json!({"wire_types": wire_types, "required": field.required});
+            if let Some(message) = &field.message {
+                json["message"] = Value::from(message);
+            }
+            (number.to_string(), json)
+        });
+        Value::Object(fields.collect())
+    }
+}
+
+impl From<ShapeDescriptor> for Value {
+    fn from(shape: ShapeDescriptor) -> Self {
+        Value::from(&shape)
+    }
+}
+
+impl TryFrom<&Value> for ShapeDescriptor {
+    type Error = ShapeError;
+
+    /// Load a shape from the JSON it was converted to.
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let Value::Object(map) = value else {
+            return Err(ShapeError::NotAnObject);
+        };
+        let mut fields = BTreeMap::new();
+        for (key, value) in map {
+            let number = key
+                .parse()
+                .map_err(|_| ShapeError::InvalidFieldNumber(key.clone()))?;
+            let Value::Object(field) = value else {
+                return Err(ShapeError::NotAnObject);
+            };
+            fields.insert(number, FieldShape::from_json(field)?);
+        }
+        Ok(Self { fields })
+    }
+}
+
+impl FieldShape {
+    fn from_json(map: &Map<String, Value>) -> Result<Self, ShapeError> {
+        let entry = |key: &str| {
+            map.get(key)
+                .ok_or_else(|| ShapeError::MissingKey(key.into()))
+        };
+        let invalid = |key: &str| ShapeError::InvalidValue(key.into());
+        let wire_types = entry("wire_types")?
+            .as_array()
+            .ok_or_else(|| invalid("wire_types"))?
+            .iter()
+            .map(
+                |name| match name.as_str().ok_or_else(|| invalid("wire_types"))? {
+                    "varint" => Ok(WireType::Varint),
+                    "fixed64" => Ok(WireType::Fixed64),
+                    "length-delimited" => Ok(WireType::LengthDelimited),
+                    "fixed32" => Ok(WireType::Fixed32),
+                    name => Err(ShapeError::UnknownWireType(name.to_string())),
+                },
+            )
+            .collect::<Result<_, _>>()?;
+        let required = entry("required")?
+            .as_bool()
+            .ok_or_else(|| invalid("required"))?;
+        let message = map
+            .get("message")
+            .map(ShapeDescriptor::try_from)
+            .transpose()?;
+        Ok(Self {
+            wire_types,
+            required,
+            message,
+        })
+    }
+}
+
+impl From<&ShapeMismatch> for Value {
+    /// Convert to JSON with an `issues` list, each issue having a `kind` and the `path` of its
+    /// field, e.g. `{"kind": "new_field", "path": "5.3"}`.
+    fn from(mismatch: &ShapeMismatch) -> Self {
+        let issues: Vec<_> = mismatch
+            .issues
+            .iter()
+            .map(|issue| match issue {
+                ShapeIssue::NotAMessage => json!({"kind": "not_a_message"}),
+                ShapeIssue::NewField { path } => {
+                    json!({"kind": "new_field", "path": path.to_string()})
+                }
+                ShapeIssue::MissingField { path } => {
+                    json!({"kind": "missing_field", "path": path.to_string()})
+                }
+                ShapeIssue::WireTypeChanged {
+                    path,
+                    expected,
+                    found,
+                } => json!({
+                    "kind": "wire_type_changed",
+                    "path": path.to_string(),
+                    "expected": expected.iter().map(WireType::to_string).collect::<Vec<_>>(),
+                    "found": found.to_string(),
+                }),
+            })
+            .collect();
+        json!({ "issues": issues })
+    }
+}
+
+impl From<ShapeMismatch> for Value {
+    fn from(mismatch: ShapeMismatch) -> Self {
+        Value::from(&mismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_infer_shape() {
+        // 1: 1, 2: "a", 5: {1: 1}; and 1: 2, 5: {1: 2, 2: 3}.
+        let a = hex!("0801 120161 2a020801");
+        let b = hex!("0802 2a0408021003");
+        let shape = Parser::new().infer_shape(&[&a, &b, b"\xff"]);
+
+        let field = shape.field(&[1]).unwrap();
+        assert_eq!(field.wire_types, [WireType::Varint]);
+        assert!(field.required);
+        assert_eq!(field.message, None);
+        assert!(!shape.field(&[2]).unwrap().required);
+        assert!(shape.field(&[5, 1]).unwrap().required);
+        assert!(!shape.field(&[5, 2]).unwrap().required);
+        assert_eq!(shape.field(&[3]), None);
+    }
+
+    #[test]
+    fn test_shape_json() {
+        let a = hex!("0801 120161 2a020801");
+        let b = hex!("0d02000000 2a0408021003");
+        let shape = Parser::new().infer_shape(&[&a, &b]);
+        let json = Value::from(&shape);
+        assert_eq!(
+            json,
+            json!({
+                "1": {"wire_types": ["varint", "fixed32"], "required": true},
+                "2": {"wire_types": ["length-delimited"], "required": false},
+                "5": {
+                    "wire_types": ["length-delimited"],
+                    "required": true,
+                    "message": {
+                        "1": {"wire_types": ["varint"], "required": true},
+                        "2": {"wire_types": ["varint"], "required": false},
+                    },
+                },
+            })
+        );
+        assert_eq!(ShapeDescriptor::try_from(&json), Ok(shape));
+
+        let cases = [
+            (json!([]), ShapeError::NotAnObject),
+            (
+                json!({"x": {}}),
+                ShapeError::InvalidFieldNumber("x".to_string()),
+            ),
+            (json!({"1": 1}), ShapeError::NotAnObject),
+            (
+                json!({"1": {"required": true}}),
+                ShapeError::MissingKey("wire_types".to_string()),
+            ),
+            (
+                json!({"1": {"wire_types": ["varint"], "required": 1}}),
+                ShapeError::InvalidValue("required".to_string()),
+            ),
+            (
+                json!({"1": {"wire_types": ["group"], "required": true}}),
+                ShapeError::UnknownWireType("group".to_string()),
+            ),
+            (
+                json!({"1": {"wire_types": [], "required": true, "message": {"1": null}}}),
+                ShapeError::NotAnObject,
+            ),
+        ];
+        for (json, err) in cases {
+            assert_eq!(ShapeDescriptor::try_from(&json), Err(err), "{json}");
+        }
+    }
+
+    #[test]
+    fn test_validate_against_shape() {
+        let samples = [
+            &hex!("0801 120161 2a020801")[..],
+            &hex!("0802 2a0408021003")[..],
+        ];
+        let parser = Parser::new();
+        let shape = parser.infer_shape(&samples);
+        for sample in samples {
+            assert_eq!(parser.validate_against_shape(sample, &shape), Ok(()));
+        }
+        // Repeated fields and missing optional fields conform.
+        let data = hex!("0801 0802 2a020801");
+        assert_eq!(parser.validate_against_shape(&data, &shape), Ok(()));
+
+        // 1 as fixed32, 5.1 missing, 5.3 and 6 new.
+        let data = hex!("0d01000000 2a04100218013001");
+        let mismatch = parser.validate_against_shape(&data, &shape).unwrap_err();
+        assert_eq!(
+            mismatch.issues,
+            [
+                ShapeIssue::WireTypeChanged {
+                    path: FieldPath::from(1),
+                    expected: vec![WireType::Varint],
+                    found: WireType::Fixed32,
+                },
+                ShapeIssue::NewField {
+                    path: FieldPath::from([5, 3]),
+                },
+                ShapeIssue::MissingField {
+                    path: FieldPath::from([5, 1]),
+                },
+                ShapeIssue::NewField {
+                    path: FieldPath::from(6),
+                },
+            ]
+        );
+        assert_eq!(
+            Value::from(&mismatch)["issues"][0],
+            json!({
+                "kind": "wire_type_changed",
+                "path": "1",
+                "expected": ["varint"],
+                "found": "fixed32"
+            })
+        );
+        assert_eq!(
+            mismatch.to_string(),
+            "message doesn't match the shape: field 1 has unexpected wire type fixed32, \
+             new field 5.3, missing field 5.1, new field 6"
+        );
+
+        assert_eq!(
+            parser.validate_against_shape(&[], &shape),
+            Err(ShapeMismatch {
+                issues: vec![ShapeIssue::NotAMessage]
+            })
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Tree of fields with the byte ranges they were decoded from.
+
+use std::ops::Range;
+
+use crate::{FieldValue, Parser, TypedValue, WireType};
+
+/// Field of a message with its position in the input, as returned by
+/// [`Parser::parse_spanned_tree`].
+///
+/// Ranges are byte offsets into the data given to the parser, so a hex view can map a click to
+/// the field under it and highlight the bytes of a selected field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpannedNode {
+    /// Bytes of the whole field, tag and length prefix included. The root spans the whole
+    /// message.
+    pub range: Range<usize>,
+
+    /// Bytes of the value, without tag and length prefix.
+    pub value_range: Range<usize>,
+
+    /// Field number, 0 for the root.
+    pub number: u64,
+
+    /// Wire type of the field, [`WireType::LengthDelimited`] for the root.
+    pub wire_type: WireType,
+
+    /// Decoded value, `None` for messages, whose fields are the children.
+    pub value: Option<TypedValue>,
+
+    /// Fields of a nested message in wire order, empty for other values.
+    pub children: Vec<SpannedNode>,
+}
+
+impl Parser {
+    /// Parse a protobuf message into a tree of its fields and their byte ranges.
+    ///
+    /// Nested messages are guessed the same way as [`Parser::parse_typed`] does. Returns `None`
+    /// if the data doesn't contain any field.
+    pub fn parse_spanned_tree(&self, data: &[u8]) -> Option<SpannedNode> {
+        if data.is_empty() {
+            return None;
+        }
+        self.guess_message(data, true)?;
+        Some(SpannedNode {
+            range: 0..data.len(),
+            value_range: 0..data.len(),
+            number: 0,
+            wire_type: WireType::LengthDelimited,
+            value: None,
+            children: self.spanned_fields(data, 0),
+        })
+    }
+
+    /// Build the nodes of the fields of a message starting at `offset`, stopping at the first
+    /// invalid or incomplete field.
+    fn spanned_fields(&self, data: &[u8], offset: usize) -> Vec<SpannedNode> {
+        let decode_var = self.varint_decoder();
+        let mut nodes = Vec::new();
+        let mut start = offset;
+        for span in self.field_spans(data) {
+            let mut value = span;
+            let Some(tag) = self.decode_tag(&mut value) else {
+                break;
+            };
+            let wire_type = WireType::from((tag & 0x07) as u8);
+            let value_start = start + span.len() - value.len();
+            let range = start..start + span.len();
+            start = range.end;
+
+            let (value, value_range, children) =
+                match FieldValue::decode_with(&mut value, wire_type, decode_var) {
+                    FieldValue::Varint(v) => {
+                        (Some(TypedValue::Varint(v)), value_start..range.end, vec![])
+                    }
+                    FieldValue::Fixed64(v) => {
+                        (Some(TypedValue::Fixed64(v)), value_start..range.end, vec![])
+                    }
+                    FieldValue::Fixed32(v) => {
+                        (Some(TypedValue::Fixed32(v)), value_start..range.end, vec![])
+                    }
+                    FieldValue::LengthDelimited(bytes) => {
+                        let value_range = range.end - bytes.len()..range.end;
+                        match self.guess_message(bytes, false) {
+                            Some(_) => {
+                                let children = self.spanned_fields(bytes, value_range.start);
+                                (None, value_range, children)
+                            }
+                            None => (Some(TypedValue::Bytes(bytes.to_vec())), value_range, vec![]),
+                        }
+                    }
+                    FieldValue::Message(_)
+                    | FieldValue::Invalid(_, _)
+                    | FieldValue::Incomplete(_, _) => {
+                        break;
+                    }
+                };
+            nodes.push(SpannedNode {
+                range,
+                value_range,
+                number: tag >> 3,
+                wire_type,
+                value,
+                children,
+            });
+        }
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_spanned_tree() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let root = Parser::new().parse_spanned_tree(&data).unwrap();
+        assert_eq!(root.range, 0..28);
+        assert_eq!(root.number, 0);
+        assert_eq!(root.children.len(), 5);
+
+        let first = &root.children[0];
+        assert_eq!(
+            (first.range.clone(), first.value_range.clone()),
+            (0..5, 1..5)
+        );
+        assert_eq!(first.wire_type, WireType::Fixed32);
+        assert_eq!(first.value, Some(TypedValue::Fixed32(28)));
+
+        let nested = &root.children[4];
+        assert_eq!(nested.number, 5);
+        assert_eq!(
+            (nested.range.clone(), nested.value_range.clone()),
+            (16..28, 18..28)
+        );
+        assert_eq!(nested.value, None);
+
+        let string = &nested.children[0];
+        assert_eq!(string.number, 1);
+        assert_eq!(
+            (string.range.clone(), string.value_range.clone()),
+            (18..26, 20..26)
+        );
+        assert_eq!(string.value, Some(TypedValue::Bytes(b"abc123".to_vec())));
+        assert_eq!(&data[string.value_range.clone()], b"abc123");
+
+        let empty = &nested.children[1];
+        assert_eq!(
+            (empty.range.clone(), empty.value_range.clone()),
+            (26..28, 28..28)
+        );
+
+        assert_eq!(Parser::new().parse_spanned_tree(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_spanned_tree_invalid() {
+        // The first layer keeps the fields before an invalid one.
+        let root = Parser::new().parse_spanned_tree(&hex!("08010f00")).unwrap();
+        assert_eq!(root.range, 0..4);
+        assert_eq!(root.children.len(), 1);
+        assert_eq!(root.children[0].range, 0..2);
+    }
+}
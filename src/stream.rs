@@ -0,0 +1,398 @@
+//! Parsing of streams of several root messages.
+
+use std::{fmt, io};
+
+use serde_json::{Map, Value};
+
+use crate::{FieldValue, Parser, WireType, varint::MSB};
+
+/// Longest possible varint length prefix.
+const MAX_PREFIX_LEN: usize = 10;
+
+/// Default maximum length of a frame, see [`StreamParser::with_max_frame_len`].
+const DEFAULT_MAX_FRAME_LEN: usize = 64 << 20;
+
+/// Stateful parser for a stream of varint length-prefixed messages, as written by
+/// `writeDelimitedTo`.
+///
+/// Bytes can be fed in chunks of any size. A frame split across chunks is buffered until the
+/// rest of it arrives, unless it is longer than [`StreamParser::with_max_frame_len`].
+///
+/// ``` rust
+/// use protobuf_to_json::{Parser, StreamParser};
+/// use serde_json::json;
+///
+/// let mut stream = StreamParser::new(Parser::new());
+/// assert!(stream.feed(&[0x04, 0x08, 0x01]).is_empty());
+/// assert_eq!(stream.feed(&[0x10, 0x02, 0x02]), vec![json!({"1": 1, "2": 2})]);
+/// assert_eq!(stream.feed(&[0x08, 0x03]), vec![json!({"1": 3})]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamParser {
+    parser: Parser,
+    buffer: Vec<u8>,
+    max_frame_len: usize,
+    /// Bytes left of a skipped frame that is too long.
+    skip: u64,
+}
+
+impl Default for StreamParser {
+    fn default() -> Self {
+        Self::new(Parser::default())
+    }
+}
+
+impl StreamParser {
+    /// Create a stream parser converting each frame with the given parser.
+    pub fn new(parser: Parser) -> Self {
+        Self {
+            parser,
+            buffer: Vec::new(),
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            skip: 0,
+        }
+    }
+
+    /// Set the maximum length of a frame, 64 MiB by default.
+    ///
+    /// A frame whose length prefix is larger yields `null` as soon as the prefix is read, and
+    /// its bytes are skipped as they arrive instead of being buffered, so that a corrupt or
+    /// hostile length prefix can't make the parser buffer gigabytes. The frames after it are
+    /// parsed as usual.
+    ///
+    /// ``` rust
+    /// use protobuf_to_json::{Parser, StreamParser};
+    /// use serde_json::{Value, json};
+    ///
+    /// let mut stream = StreamParser::new(Parser::new()).with_max_frame_len(2);
+    /// assert_eq!(stream.feed(&[0x04, 0x08, 0x01]), vec![Value::Null]);
+    /// assert_eq!(stream.feed(&[0x10, 0x02, 0x02, 0x08, 0x03]), vec![json!({"1": 3})]);
+    /// ```
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Feed the next chunk of the stream and return the messages completed by it.
+    ///
+    /// An empty frame yields an empty object, and a frame that doesn't parse as a message or is
+    /// too long yields `null` so the output stays aligned with the frames. A malformed length
+    /// prefix means the stream is corrupt; the buffered data is dropped.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Value> {
+        let skipped = usize::try_from(self.skip).map_or(bytes.len(), |skip| skip.min(bytes.len()));
+        self.skip -= skipped as u64;
+        self.buffer.extend_from_slice(&bytes[skipped..]);
+
+        let mut messages = Vec::new();
+        let mut rest = self.buffer.as_slice();
+        loop {
+            let mut body = rest;
+            let Ok(len) = crate::decode_var(&mut body) else {
+                if rest.len() >= MAX_PREFIX_LEN || rest.iter().any(|b| b & MSB == 0) {
+                    rest = &[];
+                }
+                break;
+            };
+            let Some(len) = usize::try_from(len)
+                .ok()
+                .filter(|len| *len <= self.max_frame_len)
+            else {
+                messages.push(Value::Null);
+                let available = usize::try_from(len).map_or(body.len(), |len| len.min(body.len()));
+                self.skip = len - available as u64;
+                rest = &body[available..];
+                continue;
+            };
+            let Some(frame) = body.get(..len) else {
+                break;
+            };
+            messages.push(if frame.is_empty() {
+                Value::Object(Map::new())
+            } else {
+                self.parser.parse(frame).unwrap_or(Value::Null)
+            });
+            rest = &body[frame.len()..];
+        }
+
+        let consumed = self.buffer.len() - rest.len();
+        self.buffer.drain(..consumed);
+        messages
+    }
+
+    /// Number of buffered bytes belonging to an incomplete frame. The bytes of a frame that is
+    /// too long are not buffered, and not counted.
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+/// Writer parsing the varint length-prefixed messages written to it, see [`StreamParser`].
+///
+/// Each completed message is passed to the callback, so a stream can be converted with
+/// [`io::copy`] or from any code writing to an [`io::Write`].
+///
+/// ``` rust
+/// use std::io::Write;
+///
+/// use protobuf_to_json::{FrameSink, Parser};
+/// use serde_json::json;
+///
+/// let mut messages = Vec::new();
+/// let mut sink = FrameSink::new(Parser::new(), |json| messages.push(json));
+/// sink.write_all(&[0x02, 0x08, 0x01, 0x02, 0x08]).unwrap();
+/// sink.write_all(&[0x02]).unwrap();
+/// drop(sink);
+/// assert_eq!(messages, vec![json!({"1": 1}), json!({"1": 2})]);
+/// ```
+pub struct FrameSink<F> {
+    stream: StreamParser,
+    callback: F,
+}
+
+impl<F: FnMut(Value)> FrameSink<F> {
+    /// Create a sink converting each frame with the given parser and passing it to `callback`.
+    pub fn new(parser: Parser, callback: F) -> Self {
+        Self {
+            stream: StreamParser::new(parser),
+            callback,
+        }
+    }
+
+    /// Set the maximum length of a frame, see [`StreamParser::with_max_frame_len`].
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.stream = self.stream.with_max_frame_len(max_frame_len);
+        self
+    }
+
+    /// Number of buffered bytes belonging to an incomplete frame.
+    pub fn pending(&self) -> usize {
+        self.stream.pending()
+    }
+}
+
+impl<F: FnMut(Value)> io::Write for FrameSink<F> {
+    /// Feed the bytes to the stream parser. Never fails, a corrupt stream is handled as
+    /// [`StreamParser::feed`] does.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for json in self.stream.feed(buf) {
+            (self.callback)(json);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> fmt::Debug for FrameSink<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameSink")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Parser {
+    /// Parse a whole stream of varint length-prefixed messages into a JSON array.
+    ///
+    /// Frames are converted as [`StreamParser::feed`] does. A truncated last frame is left out;
+    /// use a [`StreamParser`] and check [`StreamParser::pending`] to detect it.
+    pub fn parse_stream_to_array(&self, data: &[u8]) -> Value {
+        let mut stream = StreamParser::new(self.clone());
+        Value::Array(stream.feed(data))
+    }
+
+    /// Split data holding several root messages back to back, without any framing, and parse
+    /// each of them.
+    ///
+    /// This is a heuristic: serializers write fields in ascending field number order, so a new
+    /// message is assumed to start wherever the field number goes down, e.g. a field 1 following
+    /// a field 5. Messages whose first field number is not lower than the last one of the
+    /// previous message are merged, and a message written out of order is split.
+    ///
+    /// Data from the first invalid or incomplete field on can't be part of any message. It
+    /// yields a `null` as the last item instead of being dropped, as [`StreamParser::feed`] does
+    /// for frames that are not messages.
+    pub fn parse_concatenated_heuristic(&self, data: &[u8]) -> Vec<Value> {
+        let mut messages = Vec::new();
+        let mut rest = data;
+        let mut start = 0;
+        let mut last_number = 0;
+        let mut invalid = None;
+        let decode_var = self.varint_decoder();
+        while !rest.is_empty() {
+            let offset = data.len() - rest.len();
+            let Some(tag) = self.decode_tag(&mut rest) else {
+                invalid = Some(offset);
+                break;
+            };
+            let number = tag >> 3;
+            if number < last_number {
+                messages.extend(self.parse(&data[start..offset]));
+                start = offset;
+            }
+            last_number = number;
+            let wire_type = WireType::from((tag & 0x07) as u8);
+            let value = FieldValue::decode_with(&mut rest, wire_type, decode_var);
+            if matches!(
+                value,
+                FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _)
+            ) {
+                invalid = Some(offset);
+                break;
+            }
+        }
+        let end = invalid.unwrap_or(data.len());
+        if start < end {
+            messages.extend(self.parse(&data[start..end]));
+        }
+        if invalid.is_some() {
+            messages.push(Value::Null);
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_feed_split_frames() {
+        let data = hex!("1c 0d1c0000001203596f751a024d65202b2a0a0a066162633132331200 00 03 1a014d");
+        let expected = vec![
+            json!({"1": 28, "2": "You", "3": "Me", "4": 43, "5": {"1": "abc123", "2": ""}}),
+            json!({}),
+            json!({"3": "M"}),
+        ];
+
+        for chunk_size in 1..data.len() {
+            let mut stream = StreamParser::new(Parser::new());
+            let messages: Vec<_> = data
+                .chunks(chunk_size)
+                .flat_map(|chunk| stream.feed(chunk))
+                .collect();
+            assert_eq!(messages, expected, "chunk size {chunk_size}");
+            assert_eq!(stream.pending(), 0);
+        }
+    }
+
+    #[test]
+    fn test_frame_sink() {
+        use std::io::Write;
+
+        let data = hex!("1c 0d1c0000001203596f751a024d65202b2a0a0a066162633132331200 03 1a014d");
+        for chunk_size in 1..data.len() {
+            let mut messages = Vec::new();
+            let mut sink = FrameSink::new(Parser::new(), |json| messages.push(json));
+            for chunk in data.chunks(chunk_size) {
+                sink.write_all(chunk).unwrap();
+            }
+            assert_eq!(sink.pending(), 0);
+            drop(sink);
+            assert_eq!(messages.len(), 2, "chunk size {chunk_size}");
+            assert_eq!(messages[0]["5"], json!({"1": "abc123", "2": ""}));
+            assert_eq!(messages[1], json!({"3": "M"}));
+        }
+
+        let mut messages = Vec::new();
+        let mut sink = FrameSink::new(Parser::new(), |json| messages.push(json));
+        std::io::copy(&mut &data[..], &mut sink).unwrap();
+        drop(sink);
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_stream_to_array() {
+        let message = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            data.push(message.len() as u8);
+            data.extend_from_slice(&message);
+        }
+        let parser = Parser::new();
+        let expected = parser.parse(&message).unwrap();
+        let json = parser.parse_stream_to_array(&data);
+        assert_eq!(json, json!([expected, expected]));
+
+        data.extend_from_slice(&hex!("1c0d1c"));
+        assert_eq!(parser.parse_stream_to_array(&data), json);
+        assert_eq!(parser.parse_stream_to_array(&[]), json!([]));
+    }
+
+    #[test]
+    fn test_parse_concatenated_heuristic() {
+        let first = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let second = hex!("0d1d0000001203596f75202c");
+        let data = [&first[..], &second[..], &first[..]].concat();
+        let messages = Parser::new().parse_concatenated_heuristic(&data);
+        assert_eq!(
+            messages,
+            vec![
+                Parser::new().parse(&first).unwrap(),
+                json!({"1": 29, "2": "You", "4": 44}),
+                Parser::new().parse(&first).unwrap(),
+            ]
+        );
+
+        let messages = Parser::new().parse_concatenated_heuristic(&first);
+        assert_eq!(messages, vec![Parser::new().parse(&first).unwrap()]);
+        assert!(Parser::new().parse_concatenated_heuristic(&[]).is_empty());
+
+        // 2: 1, then 1: a truncated string.
+        let messages = Parser::new().parse_concatenated_heuristic(&hex!("1001 0a0561"));
+        assert_eq!(messages, vec![json!({"2": 1}), Value::Null]);
+        // 1: 1, 2: 2, then garbage.
+        let messages = Parser::new().parse_concatenated_heuristic(&hex!("0801 1002 ff"));
+        assert_eq!(messages, vec![json!({"1": 1, "2": 2}), Value::Null]);
+        let messages = Parser::new().parse_concatenated_heuristic(&hex!("ff"));
+        assert_eq!(messages, vec![Value::Null]);
+    }
+
+    #[test]
+    fn test_feed_partial_frame() {
+        let mut stream = StreamParser::new(Parser::new());
+        assert!(stream.feed(&hex!("ac02")).is_empty());
+        assert!(stream.feed(&hex!("08")).is_empty());
+        assert_eq!(stream.pending(), 3);
+
+        let mut stream = StreamParser::new(Parser::new());
+        assert_eq!(stream.feed(&hex!("02ff")), Vec::<Value>::new());
+        assert_eq!(stream.feed(&hex!("ff")), vec![Value::Null]);
+    }
+
+    #[test]
+    fn test_feed_max_frame_len() {
+        // 1: 1, a frame of 5 bytes, 1: 3
+        let data = hex!("02 0801 05 0a03596f75 02 0803");
+        let expected = vec![json!({"1": 1}), Value::Null, json!({"1": 3})];
+        for chunk_size in 1..data.len() {
+            let mut stream = StreamParser::new(Parser::new()).with_max_frame_len(4);
+            let messages: Vec<_> = data
+                .chunks(chunk_size)
+                .flat_map(|chunk| stream.feed(chunk))
+                .collect();
+            assert_eq!(messages, expected, "chunk size {chunk_size}");
+            assert_eq!(stream.pending(), 0);
+        }
+
+        // A huge length prefix isn't buffered.
+        let mut stream = StreamParser::new(Parser::new());
+        assert_eq!(
+            stream.feed(&hex!("ffffffffffffffff7f 0801")),
+            vec![Value::Null]
+        );
+        assert!(stream.feed(&[0; 4096]).is_empty());
+        assert_eq!(stream.pending(), 0);
+
+        let mut stream = StreamParser::new(Parser::new()).with_max_frame_len(5);
+        assert_eq!(
+            stream.feed(&data),
+            vec![json!({"1": 1}), json!({"1": "You"}), json!({"1": 3})]
+        );
+    }
+}
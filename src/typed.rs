@@ -0,0 +1,333 @@
+//! Lossless typed tree of a protobuf message.
+
+use std::collections::BTreeMap;
+
+use base64::prelude::*;
+use serde_json::{Map, Value};
+
+use crate::{FieldPath, FieldValue, Message, Parser, WireType, parser::Walk};
+
+/// Decoded protobuf value keeping its wire type.
+///
+/// Unlike the JSON output, numbers keep their wire type and bytes are kept as is instead of
+/// being guessed as strings, so nothing is lost in the conversion.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypedValue {
+    /// Varint (wire type = 0).
+    Varint(u128),
+
+    /// 64-bit value (wire type = 1).
+    Fixed64(u64),
+
+    /// 32-bit value (wire type = 5).
+    Fixed32(u32),
+
+    /// Length-delimited value that is not a nested message (wire type = 2).
+    Bytes(Vec<u8>),
+
+    /// Message, as field numbers and values in wire order.
+    Message(Vec<(u64, TypedValue)>),
+}
+
+impl TypedValue {
+    /// Wire type the value was decoded from.
+    pub fn wire_type(&self) -> WireType {
+        match self {
+            TypedValue::Varint(_) => WireType::Varint,
+            TypedValue::Fixed64(_) => WireType::Fixed64,
+            TypedValue::Fixed32(_) => WireType::Fixed32,
+            TypedValue::Bytes(_) | TypedValue::Message(_) => WireType::LengthDelimited,
+        }
+    }
+}
+
+impl Parser {
+    /// Parse a protobuf message from the given byte slice into a [`TypedValue`] tree.
+    ///
+    /// Nested messages are guessed the same way as [`Parser::parse`] does. Returns `None` if the
+    /// data doesn't contain any field.
+    pub fn parse_typed(&self, data: &[u8]) -> Option<TypedValue> {
+        if data.is_empty() {
+            return None;
+        }
+        let msg = self.guess_message(data, true)?;
+        Some(self.message_to_typed(&msg))
+    }
+
+    /// Parse a protobuf message into both its JSON conversion and its [`TypedValue`] tree.
+    ///
+    /// Both are built in a single pass, so this is cheaper than calling [`Parser::parse`] and
+    /// [`Parser::parse_typed`]. The typed tree holds the fields of the JSON output: top-level
+    /// fields left out by [`Parser::with_field_filter`] are missing from it too.
+    pub fn parse_both(&self, data: &[u8]) -> Option<(Value, TypedValue)> {
+        let mut walk = Walk::new(self);
+        walk.typed = Some(Vec::new());
+        let json = self.parse_to_json(data, &mut walk).ok()??;
+        Some((json, walk.last_typed?))
+    }
+
+    /// Extract every field that holds a UTF-8 string, recursively, with its path.
+    ///
+    /// Strings are detected the same way as [`Parser::parse`] does in
+    /// [`BytesEncoding::Auto`](crate::BytesEncoding::Auto) mode: length-delimited values that are
+    /// not nested messages and are valid UTF-8. The list is in wire order.
+    pub fn extract_strings(&self, data: &[u8]) -> Vec<(FieldPath, String)> {
+        let mut strings = Vec::new();
+        if let Some(typed) = self.parse_typed(data) {
+            collect_strings(&typed, &mut Vec::new(), &mut strings);
+        }
+        strings
+    }
+
+    /// Hash the structure of a message, its field numbers and typed values.
+    ///
+    /// The hash is taken over the [`TypedValue`] tree of [`Parser::parse_typed`], with the fields
+    /// of each message in field number order, so it doesn't depend on the bytes encoding or on
+    /// the order fields were serialized in, only on the order of the values of a repeated field.
+    /// It uses 64-bit FNV-1a over a fixed serialization of the tree, so it is stable across
+    /// platforms, runs and versions and can be used as a cache key. Data that is not a message
+    /// hashes like an empty message.
+    pub fn structural_hash(&self, data: &[u8]) -> u64 {
+        let mut hasher = Fnv1a::default();
+        match self.parse_typed(data) {
+            Some(typed) => hasher.typed(&typed),
+            None => hasher.typed(&TypedValue::Message(Vec::new())),
+        }
+        hasher.0
+    }
+
+    /// Convert the fields of a message, stopping at the first invalid or incomplete field.
+    fn message_to_typed(&self, msg: &Message) -> TypedValue {
+        let mut fields = Vec::with_capacity(msg.fields.len());
+        for field in &msg.fields {
+            let value = match &field.value {
+                FieldValue::Varint(v) => TypedValue::Varint(*v),
+                FieldValue::Fixed64(v) => TypedValue::Fixed64(*v),
+                FieldValue::Fixed32(v) => TypedValue::Fixed32(*v),
+                FieldValue::LengthDelimited(bytes) => match self.guess_message(bytes, false) {
+                    Some(nested) => self.message_to_typed(&nested),
+                    None => TypedValue::Bytes(bytes.to_vec()),
+                },
+                FieldValue::Message(nested) => self.message_to_typed(nested),
+                FieldValue::Invalid(_, _) | FieldValue::Incomplete(_, _) => break,
+            };
+            fields.push((field.number, value));
+        }
+        TypedValue::Message(fields)
+    }
+}
+
+/// 64-bit FNV-1a hasher for [`Parser::structural_hash`].
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Hash a value as a kind byte followed by its content, lengths and numbers little endian.
+    fn typed(&mut self, value: &TypedValue) {
+        match value {
+            TypedValue::Varint(v) => {
+                self.write(&[0]);
+                self.write(&v.to_le_bytes());
+            }
+            TypedValue::Fixed64(v) => {
+                self.write(&[1]);
+                self.write(&v.to_le_bytes());
+            }
+            TypedValue::Fixed32(v) => {
+                self.write(&[5]);
+                self.write(&v.to_le_bytes());
+            }
+            TypedValue::Bytes(bytes) => {
+                self.write(&[2]);
+                self.write(&(bytes.len() as u64).to_le_bytes());
+                self.write(bytes);
+            }
+            TypedValue::Message(fields) => {
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(number, _)| *number);
+                self.write(&[3]);
+                self.write(&(fields.len() as u64).to_le_bytes());
+                for (number, value) in fields {
+                    self.write(&number.to_le_bytes());
+                    self.typed(value);
+                }
+            }
+        }
+    }
+}
+
+fn collect_strings(
+    value: &TypedValue,
+    path: &mut Vec<u64>,
+    strings: &mut Vec<(FieldPath, String)>,
+) {
+    match value {
+        TypedValue::Bytes(bytes) => {
+            if let Ok(s) = std::str::from_utf8(bytes) {
+                strings.push((FieldPath::from(path.as_slice()), s.to_string()));
+            }
+        }
+        TypedValue::Message(fields) => {
+            for (number, value) in fields {
+                path.push(*number);
+                collect_strings(value, path, strings);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+impl From<&TypedValue> for Value {
+    /// Convert to JSON keyed by field numbers.
+    ///
+    /// Bytes become a string if they are valid UTF-8 and base64 otherwise, repeated fields are
+    /// merged into arrays.
+    fn from(value: &TypedValue) -> Self {
+        match value {
+            TypedValue::Varint(v) => match u64::try_from(*v) {
+                Ok(v) => Value::Number(v.into()),
+                Err(_) => Value::String(v.to_string()),
+            },
+            TypedValue::Fixed64(v) => Value::Number((*v).into()),
+            TypedValue::Fixed32(v) => Value::Number((*v).into()),
+            TypedValue::Bytes(bytes) => match std::str::from_utf8(bytes) {
+                Ok(s) => Value::String(s.to_string()),
+                Err(_) => Value::String(BASE64_STANDARD.encode(bytes)),
+            },
+            TypedValue::Message(fields) => {
+                let mut grouped: BTreeMap<u64, Vec<Value>> = BTreeMap::new();
+                for (number, value) in fields {
+                    grouped.entry(*number).or_default().push(Value::from(value));
+                }
+                let map = grouped
+                    .into_iter()
+                    .map(|(number, mut values)| {
+                        let value = match values.len() {
+                            1 => values.pop().unwrap(),
+                            _ => Value::Array(values),
+                        };
+                        (number.to_string(), value)
+                    })
+                    .collect::<Map<_, _>>();
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+impl From<TypedValue> for Value {
+    fn from(value: TypedValue) -> Self {
+        Value::from(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_typed() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let typed = Parser::new().parse_typed(&data).unwrap();
+        let expected = TypedValue::Message(vec![
+            (1, TypedValue::Fixed32(28)),
+            (2, TypedValue::Bytes(b"You".to_vec())),
+            (3, TypedValue::Bytes(b"Me".to_vec())),
+            (4, TypedValue::Varint(43)),
+            (
+                5,
+                TypedValue::Message(vec![
+                    (1, TypedValue::Bytes(b"abc123".to_vec())),
+                    (2, TypedValue::Bytes(vec![])),
+                ]),
+            ),
+        ]);
+        assert_eq!(typed, expected);
+        assert_eq!(Value::from(&typed), Parser::new().parse(&data).unwrap());
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let parser = Parser::new();
+        let hash = parser.structural_hash(&data);
+        let base64 = Parser::with_bytes_encoding(crate::BytesEncoding::Base64);
+        assert_eq!(hash, base64.structural_hash(&data));
+
+        // Same fields in another order.
+        let reordered = hex!("1203596f750d1c0000002a0a0a0661626331323312001a024d65202b");
+        assert_eq!(hash, parser.structural_hash(&reordered));
+
+        // 4: 44 instead of 43.
+        let changed = hex!("0d1c0000001203596f751a024d65202c2a0a0a066162633132331200");
+        assert_ne!(hash, parser.structural_hash(&changed));
+        // Varint 1 instead of fixed32 1.
+        assert_ne!(
+            parser.structural_hash(&hex!("0801")),
+            parser.structural_hash(&hex!("0d01000000"))
+        );
+        // The order of repeated values matters.
+        assert_ne!(
+            parser.structural_hash(&hex!("08010802")),
+            parser.structural_hash(&hex!("08020801"))
+        );
+    }
+
+    #[test]
+    fn test_parse_both() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a0661626331323312002a020801");
+        let parser = Parser::new();
+        let (json, typed) = parser.parse_both(&data).unwrap();
+        assert_eq!(json, parser.parse(&data).unwrap());
+        assert_eq!(typed, parser.parse_typed(&data).unwrap());
+
+        assert_eq!(parser.parse_both(&[]), None);
+    }
+
+    #[test]
+    fn test_typed_to_json_repeated() {
+        let typed = TypedValue::Message(vec![
+            (1, TypedValue::Varint(1)),
+            (2, TypedValue::Bytes(vec![0xff])),
+            (1, TypedValue::Varint(2)),
+        ]);
+        assert_eq!(Value::from(typed), json!({"1": [1, 2], "2": "/w=="}));
+    }
+
+    #[test]
+    fn test_extract_strings() {
+        let data = hex!(
+            "0a0a6173636f6e2d66756c6c120a6173636f6e2d66756c6c1a1b323032352d30392d30325430393a33373a32362e3033393032385a2203302e312a0474657374421b323032352d30392d30325430393a33373a32362e3033393032385a480068007205302e312e308a016e46756c6c204173636f6e20696d706c656d656e746174696f6e202868617368e280913235362c2041454144e280913132382077697468206e6f6e6365206d61736b696e67202620746167207472756e636174696f6e2c20584f46e280913132382c2043584f46e28091313238292e92012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6c9a011a68747470733a2f2f646f63732e72732f6173636f6e2d66756c6ca2012368747470733a2f2f6769746875622e636f6d2f6a6a6b756d2f6173636f6e2d66756c6caa014612222f6170692f76312f6372617465732f6173636f6e2d66756c6c2f76657273696f6e731a202f6170692f76312f6372617465732f6173636f6e2d66756c6c2f6f776e657273"
+        );
+        let strings = Parser::new().extract_strings(&data);
+        assert_eq!(strings.len(), 13);
+        let urls: Vec<_> = strings
+            .iter()
+            .filter(|(_, s)| s.starts_with("https://") || s.starts_with("/api/"))
+            .map(|(path, s)| (path.to_string(), s.as_str()))
+            .collect();
+        let expected = vec![
+            ("18".to_string(), "https://github.com/jjkum/ascon-full"),
+            ("19".to_string(), "https://docs.rs/ascon-full"),
+            ("20".to_string(), "https://github.com/jjkum/ascon-full"),
+            ("21.2".to_string(), "/api/v1/crates/ascon-full/versions"),
+            ("21.3".to_string(), "/api/v1/crates/ascon-full/owners"),
+        ];
+        assert_eq!(urls, expected);
+    }
+}
@@ -1,35 +1,95 @@
 //! Variable-length integer decoding.
 //!
 
+use crate::VarintError;
+
 /// Most-significant byte, == 0x80
 pub const MSB: u8 = 0b1000_0000;
 /// All bits except for the most significant. Can be used as bitmask to drop the most-signficant
 /// bit using `&` (binary-and).
 const DROP_MSB: u8 = 0b0111_1111;
 
+/// Function decoding a varint, [`decode_var`] or [`decode_var_msb_first`].
+pub(crate) type VarintDecoder = fn(&mut &[u8]) -> Result<u64, VarintError>;
+
 /// Decode a variable-length integer from a byte slice.
-pub fn decode_var(src: &mut &[u8]) -> Result<u64, ()> {
+pub fn decode_var(src: &mut &[u8]) -> Result<u64, VarintError> {
     let mut result: u64 = 0;
     let mut shift = 0;
 
-    let mut success = false;
     for b in src.iter() {
         let msb_dropped = b & DROP_MSB;
         result |= (msb_dropped as u64) << shift;
         shift += 7;
 
-        if b & MSB == 0 || shift > (9 * 7) {
-            success = b & MSB == 0;
-            break;
+        if b & MSB == 0 {
+            *src = &src[shift / 7..];
+            return Ok(result);
+        }
+        if shift > (9 * 7) {
+            return Err(VarintError::TooLong);
         }
     }
+    Err(VarintError::Incomplete)
+}
 
-    if success {
-        *src = &src[shift / 7..];
-        Ok(result)
-    } else {
-        Err(())
+/// Decode a variable-length integer whose 7-bit groups are in most significant first order.
+///
+/// This is not the protobuf encoding, which puts the least significant group first. Some
+/// non-standard or obfuscated serializers use this order, e.g. `0x82 0x2c` is 300 here and 5634
+/// for [`decode_var`]. As with [`decode_var`], a varint is at most 10 bytes long.
+pub fn decode_var_msb_first(src: &mut &[u8]) -> Result<u64, VarintError> {
+    let mut result: u64 = 0;
+    for (i, b) in src.iter().enumerate() {
+        result = (result << 7) | (b & DROP_MSB) as u64;
+        if b & MSB == 0 {
+            *src = &src[i + 1..];
+            return Ok(result);
+        }
+        if i == 9 {
+            return Err(VarintError::TooLong);
+        }
     }
+    Err(VarintError::Incomplete)
+}
+
+/// Decode a varint holding an `int32`/`int64` as its two's complement signed value.
+pub fn decode_varint_i64(src: &mut &[u8]) -> Result<i64, VarintError> {
+    decode_var(src).map(|v| v as i64)
+}
+
+/// Decode a zigzag encoded `sint64` varint.
+pub fn decode_zigzag_i64(src: &mut &[u8]) -> Result<i64, VarintError> {
+    decode_var(src).map(zigzag_decode)
+}
+
+/// Decode a zigzag encoded `sint32` varint.
+pub fn decode_zigzag_i32(src: &mut &[u8]) -> Result<i32, VarintError> {
+    decode_var(src).map(|v| {
+        let v = v as u32;
+        ((v >> 1) as i32) ^ -((v & 1) as i32)
+    })
+}
+
+/// Append the shortest varint encoding of `value` to `dst`.
+pub fn encode_var(mut value: u64, dst: &mut Vec<u8>) {
+    while value >= MSB as u64 {
+        dst.push(value as u8 | MSB);
+        value >>= 7;
+    }
+    dst.push(value as u8);
+}
+
+/// Number of bytes the varint encoding of `value` occupies.
+pub const fn varint_len(value: u64) -> usize {
+    // One byte per started group of 7 significant bits, at least one byte for zero.
+    let bits = 64 - (value | 1).leading_zeros() as usize;
+    bits.div_ceil(7)
+}
+
+/// Map a zigzag encoded value (`sint32`/`sint64`) back to its signed value.
+pub(crate) fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
 }
 
 #[cfg(test)]
@@ -41,10 +101,21 @@ mod tests {
         let max_vec_encoded = vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
         assert_eq!(
             decode_var(&mut max_vec_encoded.as_slice()).unwrap(),
-            u64::max_value()
+            u64::MAX
         );
     }
 
+    #[test]
+    fn test_varint_len() {
+        assert_eq!(varint_len(0), 1);
+        assert_eq!(varint_len(127), 1);
+        assert_eq!(varint_len(128), 2);
+        assert_eq!(varint_len(16383), 2);
+        assert_eq!(varint_len(16384), 3);
+        assert_eq!(varint_len(u64::MAX >> 1), 9);
+        assert_eq!(varint_len(u64::MAX), 10);
+    }
+
     #[test]
     fn test_decode_zero() {
         let zero_encoded = vec![0x00];
@@ -72,6 +143,52 @@ mod tests {
         assert!(decode_var(&mut incomplete_encoded.as_slice()).is_err());
     }
 
+    #[test]
+    fn test_encode_var() {
+        for value in [0, 1, 127, 128, 300, 16384, u64::MAX >> 1, u64::MAX] {
+            let mut encoded = Vec::new();
+            encode_var(value, &mut encoded);
+            assert_eq!(encoded.len(), varint_len(value));
+            assert_eq!(decode_var(&mut encoded.as_slice()), Ok(value));
+        }
+    }
+
+    #[test]
+    fn test_decode_msb_first() {
+        let data = [0x82, 0x2c, 0x01];
+        let mut rest = &data[..];
+        assert_eq!(decode_var(&mut rest), Ok(5634));
+        let mut rest_msb = &data[..];
+        assert_eq!(decode_var_msb_first(&mut rest_msb), Ok(300));
+        assert_eq!(rest, rest_msb);
+
+        // Single bytes decode the same in both orders.
+        assert_eq!(decode_var_msb_first(&mut &[0x7f][..]), Ok(127));
+        let max = [0x81, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(decode_var_msb_first(&mut &max[..]), Ok(u64::MAX));
+        assert_eq!(
+            decode_var_msb_first(&mut &[0x80][..]),
+            Err(VarintError::Incomplete)
+        );
+        assert_eq!(
+            decode_var_msb_first(&mut &[0xff; 11][..]),
+            Err(VarintError::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(
+            decode_var(&mut &[0xff, 0xff][..]),
+            Err(VarintError::Incomplete)
+        );
+        assert_eq!(decode_var(&mut &[0xff; 11][..]), Err(VarintError::TooLong));
+        assert_eq!(
+            decode_zigzag_i32(&mut &[][..]),
+            Err(VarintError::Incomplete)
+        );
+    }
+
     #[test]
     fn test_decode_single_byte_with_msb() {
         let single_byte_with_msb = vec![0x80];
@@ -83,4 +200,54 @@ mod tests {
         let empty_input: Vec<u8> = vec![];
         assert!(decode_var(&mut empty_input.as_slice()).is_err());
     }
+
+    #[test]
+    fn test_decode_varint_i64() {
+        let cases: [(&[u8], i64); 4] = [
+            (
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+                -1,
+            ),
+            (&[0x00], 0),
+            (&[0x01], 1),
+            (
+                &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+                i64::MIN,
+            ),
+        ];
+        for (mut encoded, expected) in cases {
+            assert_eq!(decode_varint_i64(&mut encoded).unwrap(), expected);
+            assert!(encoded.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_decode_zigzag_i64() {
+        let cases: [(&[u8], i64); 4] = [
+            (&[0x01], -1),
+            (&[0x00], 0),
+            (&[0x02], 1),
+            (
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+                i64::MIN,
+            ),
+        ];
+        for (mut encoded, expected) in cases {
+            assert_eq!(decode_zigzag_i64(&mut encoded).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_zigzag_i32() {
+        let cases: [(&[u8], i32); 4] = [
+            (&[0x01], -1),
+            (&[0x00], 0),
+            (&[0x02], 1),
+            (&[0xFF, 0xFF, 0xFF, 0xFF, 0x0F], i32::MIN),
+        ];
+        for (mut encoded, expected) in cases {
+            assert_eq!(decode_zigzag_i32(&mut encoded).unwrap(), expected);
+        }
+        assert!(decode_zigzag_i32(&mut [0x81].as_slice()).is_err());
+    }
 }
@@ -0,0 +1,229 @@
+//! YAML output.
+
+use serde_json::{Map, Value};
+
+use crate::Parser;
+
+impl Parser {
+    /// Parse a protobuf message and serialize the output to a YAML document.
+    ///
+    /// The document holds the same structure as [`Parser::parse`], in block style. Keys are
+    /// sorted the same way as [`Parser::parse_to_string`] does, and strings that YAML would read
+    /// as another type, such as field numbers used as keys, are quoted.
+    ///
+    /// ``` rust
+    /// use protobuf_to_json::Parser;
+    ///
+    /// let yaml = Parser::new().parse_to_yaml(&[0x08, 0x01, 0x12, 0x01, 0x61]).unwrap();
+    /// assert_eq!(yaml, "'1': 1\n'2': a\n");
+    /// ```
+    pub fn parse_to_yaml(&self, data: &[u8]) -> Option<String> {
+        let json = self.parse(data)?;
+        let mut out = String::new();
+        match &json {
+            Value::Object(map) if !map.is_empty() => {
+                write_map(&mut out, map, 0, false, self.numeric_key_order)
+            }
+            Value::Array(values) if !values.is_empty() => {
+                write_seq(&mut out, values, 0, false, self.numeric_key_order)
+            }
+            value => {
+                write_scalar(&mut out, value);
+                out.push('\n');
+            }
+        }
+        Some(out)
+    }
+}
+
+/// Write the entries of a non-empty map at the given indentation. The indentation of the first
+/// line is skipped when it follows a `- ` sequence marker.
+fn write_map(
+    out: &mut String,
+    map: &Map<String, Value>,
+    indent: usize,
+    inline: bool,
+    numeric: bool,
+) {
+    let mut entries: Vec<_> = map.iter().collect();
+    if numeric {
+        entries.sort_by_key(|(key, _)| key.parse::<u64>().map_err(|_| key.as_str()));
+    }
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        if i > 0 || !inline {
+            out.extend(std::iter::repeat_n(' ', indent));
+        }
+        write_string(out, key);
+        out.push(':');
+        write_nested(out, value, indent, false, numeric);
+    }
+}
+
+/// Write the items of a non-empty sequence, see [`write_map`].
+fn write_seq(out: &mut String, values: &[Value], indent: usize, inline: bool, numeric: bool) {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 || !inline {
+            out.extend(std::iter::repeat_n(' ', indent));
+        }
+        out.push_str("- ");
+        write_nested(out, value, indent, true, numeric);
+    }
+}
+
+/// Write a map value or a sequence item, after its `key:` or `- ` marker.
+fn write_nested(out: &mut String, value: &Value, indent: usize, item: bool, numeric: bool) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            if !item {
+                out.push('\n');
+            }
+            write_map(out, map, indent + 2, item, numeric);
+        }
+        // Sequences in a map are not indented further, as in most YAML emitters.
+        Value::Array(values) if !values.is_empty() => match item {
+            true => write_seq(out, values, indent + 2, true, numeric),
+            false => {
+                out.push('\n');
+                write_seq(out, values, indent, false, numeric);
+            }
+        },
+        value => {
+            if !item {
+                out.push(' ');
+            }
+            write_scalar(out, value);
+            out.push('\n');
+        }
+    }
+}
+
+/// Write a scalar, or an empty map or sequence in flow style.
+fn write_scalar(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => write_string(out, s),
+        Value::Array(_) => out.push_str("[]"),
+        Value::Object(_) => out.push_str("{}"),
+    }
+}
+
+/// Write a string, plain if YAML reads it back as the same string, quoted otherwise.
+fn write_string(out: &mut String, s: &str) {
+    if s.chars().any(needs_escape) {
+        out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                '\r' => out.push_str("\\r"),
+                c if needs_escape(c) => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    } else if is_plain(s) {
+        out.push_str(s);
+    } else {
+        out.push('\'');
+        out.push_str(&s.replace('\'', "''"));
+        out.push('\'');
+    }
+}
+
+/// Whether a character can't appear in a single-quoted string.
+fn needs_escape(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{2028}' | '\u{2029}' | '\u{feff}')
+}
+
+/// Whether a string can be written without quotes.
+fn is_plain(s: &str) -> bool {
+    let Some(first) = s.chars().next() else {
+        return false;
+    };
+    if first.is_ascii_digit() || "-+.?:,[]{}#&*!|>'\"%@` ".contains(first) {
+        return false;
+    }
+    // `=` and `<<` are the value and merge keys of YAML 1.1 loaders such as libyaml.
+    let reserved = [
+        "true", "false", "null", "~", "yes", "no", "on", "off", "y", "n", "=",
+    ];
+    !s.ends_with([' ', ':'])
+        && !s.starts_with("<<")
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !reserved.iter().any(|r| s.eq_ignore_ascii_case(r))
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+
+    use super::*;
+    use crate::BytesEncoding;
+
+    #[test]
+    fn test_parse_to_yaml() {
+        let data = hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200");
+        let yaml = Parser::new().parse_to_yaml(&data).unwrap();
+        let expected = "\
+'1': 28
+'2': You
+'3': Me
+'4': 43
+'5':
+  '1': abc123
+  '2': ''
+";
+        assert_eq!(yaml, expected);
+
+        // 1: [1, 2], 2: [{1: 1}, {1: 2, 2: 3}], 10: "a: b"
+        let data = hex!("0801 0802 12020801 120408021003 5204613a2062");
+        let parser = Parser::new().with_numeric_key_order(true);
+        let expected = "\
+'1':
+- 1
+- 2
+'2':
+- '1': 1
+- '1': 2
+  '2': 3
+'10': 'a: b'
+";
+        assert_eq!(parser.parse_to_yaml(&data).unwrap(), expected);
+
+        let parser = Parser::with_bytes_encoding(BytesEncoding::ByteArray);
+        let yaml = parser.parse_to_yaml(&hex!("0a02fffe")).unwrap();
+        assert_eq!(yaml, "'1':\n- 255\n- 254\n");
+
+        assert_eq!(Parser::new().parse_to_yaml(&[]), None);
+    }
+
+    #[test]
+    fn test_yaml_strings() {
+        let cases = [
+            ("abc", "abc"),
+            ("", "''"),
+            ("12", "'12'"),
+            ("true", "'true'"),
+            ("it's", "it's"),
+            ("'x'", "'''x'''"),
+            ("- x", "'- x'"),
+            ("a #b", "'a #b'"),
+            ("a\nb", "\"a\\nb\""),
+            ("\u{1}\"", "\"\\u0001\\\"\""),
+            ("h\u{e9}llo", "h\u{e9}llo"),
+            ("=", "'='"),
+            ("<<", "'<<'"),
+            ("a=b", "a=b"),
+        ];
+        for (s, expected) in cases {
+            let mut out = String::new();
+            write_string(&mut out, s);
+            assert_eq!(out, expected, "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "mmap")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use hex_literal::hex;
+use protobuf_to_json::Parser;
+use serde_json::json;
+
+/// Temporary file removed on drop.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, contents: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn test_parse_mmap() {
+    let file = TempFile::new(
+        "message.pb",
+        &hex!("0d1c0000001203596f751a024d65202b2a0a0a066162633132331200"),
+    );
+    // SAFETY: the temporary file is not modified while it is mapped.
+    let json = unsafe { Parser::new().parse_mmap(&file.0) }.unwrap();
+    let expected = json!({
+        "1": 28,
+        "2": "You",
+        "3": "Me",
+        "4": 43,
+        "5": {"1": "abc123", "2": ""}
+    });
+    assert_eq!(json, Some(expected));
+}
+
+#[test]
+fn test_parse_mmap_empty() {
+    let file = TempFile::new("empty.pb", &[]);
+    // SAFETY: the temporary file is not modified while it is mapped.
+    let json = unsafe { Parser::new().parse_mmap(&file.0) }.unwrap();
+    assert_eq!(json, None);
+}
+
+#[test]
+fn test_parse_mmap_missing() {
+    let path = std::env::temp_dir().join(format!("{}-missing.pb", std::process::id()));
+    // SAFETY: the file doesn't exist, so nothing is mapped.
+    let err = unsafe { Parser::new().parse_mmap(path) }.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}